@@ -0,0 +1,14 @@
+#version 100
+precision mediump float;
+varying lowp vec4 color;
+varying lowp vec2 uv;
+uniform sampler2D Texture;
+uniform float iTime;
+uniform vec4 tint;
+uniform float speed;
+
+void main() {
+    vec4 texel = color * texture2D(Texture, uv);
+    float wave = 0.5 + 0.5 * sin(iTime * speed + gl_FragCoord.x * 0.05);
+    gl_FragColor = vec4(mix(texel.rgb, tint.rgb, wave * tint.a), texel.a);
+}
@@ -0,0 +1,67 @@
+//! Unit separation with the spatial hash against the pairwise loop it
+//! replaced. Run with `cargo bench --bench separation`.
+
+use macroquad::prelude::*;
+use macroquad_test::spatial::{separate, SpatialHash};
+use std::time::{Duration, Instant};
+
+const SIZE: f32 = 20.0;
+const PUSH: f32 = 5.0;
+const ROUNDS: usize = 15;
+
+/// The original O(n²) loop from the units example.
+fn pairwise(positions: &mut [Vec2]) {
+    for i in 0..positions.len() {
+        for j in (i + 1)..positions.len() {
+            let (a, b) = (positions[i], positions[j]);
+            if a.x < b.x + SIZE && a.x + SIZE > b.x && a.y < b.y + SIZE && a.y + SIZE > b.y {
+                let dir = (a - b).normalize();
+                positions[i] += dir * PUSH;
+                positions[j] -= dir * PUSH;
+            }
+        }
+    }
+}
+
+/// Units scattered over a square about twice their total area, so many touch.
+fn scatter(count: usize) -> Vec<Vec2> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 40) as f32 / (1u64 << 24) as f32
+    };
+    let side = (count as f32 * 2.0).sqrt() * SIZE;
+    (0..count).map(|_| vec2(next() * side, next() * side)).collect()
+}
+
+/// The median time of one frame's separation.
+fn time(positions: &[Vec2], mut run: impl FnMut(&mut [Vec2])) -> Duration {
+    let mut times: Vec<Duration> = (0..ROUNDS)
+        .map(|_| {
+            let mut positions = positions.to_vec();
+            let start = Instant::now();
+            run(&mut positions);
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[ROUNDS / 2]
+}
+
+fn main() {
+    println!("{:>8} {:>14} {:>14} {:>8}", "units", "pairwise", "spatial hash", "speedup");
+    let mut hash = SpatialHash::new(SIZE);
+    for count in [100, 1_000, 5_000, 10_000] {
+        let positions = scatter(count);
+        let pairwise = time(&positions, pairwise);
+        let hashed = time(&positions, |positions| separate(positions, SIZE, PUSH, &mut hash));
+        println!(
+            "{count:>8} {:>14?} {:>14?} {:>7.1}x",
+            pairwise,
+            hashed,
+            pairwise.as_secs_f64() / hashed.as_secs_f64()
+        );
+    }
+}
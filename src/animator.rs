@@ -0,0 +1,189 @@
+use crate::aseprite::AnimationPlayer;
+use macroquad::prelude::*;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+/// State that can be entered from any other state in a transition's `from`.
+pub const ANY_STATE: &str = "*";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnimatorConfig {
+    pub initial: String,
+    pub states: HashMap<String, StateConfig>,
+    #[serde(default)]
+    pub transitions: Vec<TransitionConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StateConfig {
+    /// Aseprite tag played while in this state.
+    pub tag: String,
+    /// Makes the state a one-shot: once its tag has played through, the animator
+    /// moves to this state.
+    #[serde(default)]
+    pub return_to: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransitionConfig {
+    pub from: String,
+    pub to: String,
+    /// When several transitions are possible, the highest priority wins.
+    #[serde(default)]
+    pub priority: i32,
+    /// All conditions must hold for the transition to fire.
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Condition {
+    Greater { param: String, value: f32 },
+    Less { param: String, value: f32 },
+    Is { param: String, value: bool },
+    Trigger { param: String },
+}
+
+impl Condition {
+    fn holds(&self, animator: &Animator) -> bool {
+        match self {
+            Condition::Greater { param, value } => animator.float(param) > *value,
+            Condition::Less { param, value } => animator.float(param) < *value,
+            Condition::Is { param, value } => animator.bool(param) == *value,
+            Condition::Trigger { param } => animator.triggers.contains(param),
+        }
+    }
+}
+
+/// Drives an [`AnimationPlayer`] from gameplay parameters: each state plays an Aseprite
+/// tag, and transitions between states fire when their conditions hold.
+pub struct Animator {
+    config: AnimatorConfig,
+    current: String,
+    entered: bool,
+    floats: HashMap<String, f32>,
+    bools: HashMap<String, bool>,
+    triggers: HashSet<String>,
+}
+
+impl Animator {
+    pub fn new(config: AnimatorConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let check_state = |name: &str, context: &str| {
+            if config.states.contains_key(name) {
+                Ok(())
+            } else {
+                Err(format!("unknown animation state {:?} in {}", name, context))
+            }
+        };
+        check_state(&config.initial, "initial")?;
+        for (name, state) in &config.states {
+            if let Some(return_to) = &state.return_to {
+                check_state(return_to, &format!("return_to of state {:?}", name))?;
+            }
+        }
+        for transition in &config.transitions {
+            if transition.from != ANY_STATE {
+                check_state(&transition.from, "transition from")?;
+            }
+            check_state(&transition.to, "transition to")?;
+        }
+
+        Ok(Animator {
+            current: config.initial.clone(),
+            config,
+            entered: false,
+            floats: HashMap::new(),
+            bools: HashMap::new(),
+            triggers: HashSet::new(),
+        })
+    }
+
+    pub async fn load(json_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let json_data = load_string(json_path).await?;
+        Animator::new(serde_json::from_str(&json_data)?)
+    }
+
+    /// Name of the current state.
+    pub fn state(&self) -> &str {
+        &self.current
+    }
+
+    pub fn float(&self, name: &str) -> f32 {
+        self.floats.get(name).copied().unwrap_or(0.0)
+    }
+
+    pub fn bool(&self, name: &str) -> bool {
+        self.bools.get(name).copied().unwrap_or(false)
+    }
+
+    pub fn set_float(&mut self, name: &str, value: f32) {
+        self.floats.insert(name.to_string(), value);
+    }
+
+    pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.bools.insert(name.to_string(), value);
+    }
+
+    /// Sets a one-frame flag: it is consumed by the transition it fires, and
+    /// otherwise cleared at the end of the next [`Animator::update`].
+    pub fn set_trigger(&mut self, name: &str) {
+        self.triggers.insert(name.to_string());
+    }
+
+    /// Switches state immediately, ignoring transitions.
+    pub fn force_state(&mut self, name: &str) -> bool {
+        if !self.config.states.contains_key(name) {
+            return false;
+        }
+        self.current = name.to_string();
+        self.entered = false;
+        true
+    }
+
+    /// Evaluates transitions and one-shot returns, and starts the new state's tag
+    /// on `animation` when the state changes. Call once per frame before
+    /// `animation.update`.
+    pub fn update(&mut self, animation: &mut AnimationPlayer) {
+        if !self.entered {
+            self.enter(animation);
+        }
+
+        let next = self
+            .config
+            .transitions
+            .iter()
+            .filter(|transition| transition.from == ANY_STATE || transition.from == self.current)
+            .filter(|transition| transition.to != self.current)
+            .filter(|transition| transition.conditions.iter().all(|condition| condition.holds(self)))
+            .fold(None::<&TransitionConfig>, |best, transition| match best {
+                Some(best) if best.priority >= transition.priority => Some(best),
+                _ => Some(transition),
+            });
+
+        if let Some(transition) = next {
+            for condition in &transition.conditions {
+                if let Condition::Trigger { param } = condition {
+                    self.triggers.remove(param);
+                }
+            }
+            self.current = transition.to.clone();
+            self.enter(animation);
+        } else if let Some(return_to) = &self.config.states[&self.current].return_to {
+            if animation.loops() > 0 {
+                self.current = return_to.clone();
+                self.enter(animation);
+            }
+        }
+
+        self.triggers.clear();
+    }
+
+    fn enter(&mut self, animation: &mut AnimationPlayer) {
+        let tag = &self.config.states[&self.current].tag;
+        if !animation.play_tag(tag) {
+            warn!("animation state {:?} plays missing tag {:?}", self.current, tag);
+        }
+        self.entered = true;
+    }
+}
@@ -1,144 +1,992 @@
+use crate::assets::resolve_path;
+use macroquad::miniquad::{BlendFactor, BlendState, BlendValue, Equation};
 use macroquad::prelude::*;
 use serde::Deserialize;
-use std::fs;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 #[derive(Debug, Deserialize)]
-struct AsepriteData {
-    frames: HashMap<String, FrameData>,
-    meta: MetaData,
+pub struct AsepriteData {
+    pub frames: HashMap<String, FrameData>,
+    pub meta: MetaData,
 }
 
-#[derive(Debug, Deserialize)]
-struct FrameData {
-    frame: AsepriteRect,
-    rotated: bool,
-    trimmed: bool,
+#[derive(Debug, Clone, Deserialize)]
+pub struct FrameData {
+    pub frame: AsepriteRect,
+    pub rotated: bool,
+    pub trimmed: bool,
     #[serde(rename = "spriteSourceSize")]
-    sprite_source_size: AsepriteRect,
+    pub sprite_source_size: AsepriteRect,
     #[serde(rename = "sourceSize")]
-    source_size: Size,
-    duration: i32,
+    pub source_size: Size,
+    pub duration: i32,
 }
 
-#[derive(Debug, Deserialize)]
-struct AsepriteRect {
-    x: i32,
-    y: i32,
-    w: i32,
-    h: i32,
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+pub struct AsepriteRect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
 }
 
 #[derive(Debug, Deserialize)]
-struct MetaData {
-    app: String,
-    version: String,
-    image: String,
-    format: String,
-    size: Size,
-    scale: String,
+pub struct MetaData {
+    pub app: String,
+    pub version: String,
+    pub image: String,
+    pub format: String,
+    pub size: Size,
+    pub scale: String,
     #[serde(rename = "frameTags")]
-    frame_tags: Vec<FrameTag>,
-    layers: Vec<Layer>,
-    slices: Vec<String>, // This is empty in the provided JSON, so we'll use Vec<String> for simplicity
+    pub frame_tags: Vec<FrameTag>,
+    #[serde(default)]
+    pub layers: Vec<Layer>,
+    #[serde(default)]
+    pub slices: Vec<Slice>,
 }
 
-#[derive(Debug, Deserialize)]
-struct Size {
-    w: i32,
-    h: i32,
+#[derive(Debug, Clone, Deserialize)]
+pub struct Slice {
+    pub name: String,
+    #[serde(default)]
+    pub color: String,
+    #[serde(default)]
+    pub data: Option<String>,
+    pub keys: Vec<SliceKey>,
+}
+
+/// Slice bounds from `frame` until the next key. Zero-sized bounds mean the slice
+/// is absent on those frames.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SliceKey {
+    pub frame: usize,
+    pub bounds: AsepriteRect,
+    #[serde(default)]
+    pub pivot: Option<AsepritePoint>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct AsepritePoint {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Slice {
+    fn key_at(&self, frame: usize) -> Option<&SliceKey> {
+        let key = self.keys.iter().filter(|key| key.frame <= frame).max_by_key(|key| key.frame)?;
+        (key.bounds.w > 0 && key.bounds.h > 0).then_some(key)
+    }
+
+    /// Bounds of the slice on `frame`, in sprite pixels.
+    pub fn bounds_at(&self, frame: usize) -> Option<Rect> {
+        let bounds = &self.key_at(frame)?.bounds;
+        Some(Rect::new(bounds.x as f32, bounds.y as f32, bounds.w as f32, bounds.h as f32))
+    }
+
+    /// Pivot of the slice on `frame` in sprite pixels, falling back to the centre
+    /// of its bounds.
+    pub fn pivot_at(&self, frame: usize) -> Option<Vec2> {
+        let key = self.key_at(frame)?;
+        let origin = vec2(key.bounds.x as f32, key.bounds.y as f32);
+        Some(match key.pivot {
+            Some(pivot) => origin + vec2(pivot.x as f32, pivot.y as f32),
+            None => origin + vec2(key.bounds.w as f32, key.bounds.h as f32) / 2.0,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Size {
+    pub w: i32,
+    pub h: i32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FrameTag {
+    pub name: String,
+    pub from: i32,
+    pub to: i32,
+    #[serde(default)]
+    pub direction: TagDirection,
+    pub color: String,
+    /// Tag user data; each comma-separated name becomes an event fired when a
+    /// pass through the tag starts.
+    #[serde(default)]
+    pub data: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagDirection {
+    #[default]
+    Forward,
+    Reverse,
+    Pingpong,
+    PingpongReverse,
 }
 
 #[derive(Debug, Deserialize)]
-struct FrameTag {
-    name: String,
-    from: i32,
-    to: i32,
-    direction: String,
-    color: String,
+pub struct Layer {
+    pub name: String,
+    #[serde(default = "default_opacity")]
+    pub opacity: i32,
+    #[serde(rename = "blendMode", default)]
+    pub blend_mode: BlendMode,
+    /// Cels carrying user data, exported with `--list-layers`.
+    #[serde(default)]
+    pub cels: Vec<Cel>,
 }
 
 #[derive(Debug, Deserialize)]
-struct Layer {
-    name: String,
-    opacity: i32,
-    #[serde(rename = "blendMode")]
-    blend_mode: String,
+pub struct Cel {
+    pub frame: usize,
+    /// Cel user data; each comma-separated name becomes an event fired when the
+    /// frame is reached.
+    #[serde(default)]
+    pub data: Option<String>,
+}
+
+fn default_opacity() -> i32 {
+    255
+}
+
+/// Aseprite layer blend modes. Modes that can't be expressed with fixed-function
+/// blending (overlay, hue, ...) are drawn as `Normal`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Addition,
+    Subtract,
+    #[serde(other)]
+    Unsupported,
+}
+
+impl BlendMode {
+    pub(crate) fn blend_state(self) -> BlendState {
+        match self {
+            BlendMode::Multiply => BlendState::new(
+                Equation::Add,
+                BlendFactor::Value(BlendValue::DestinationColor),
+                BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+            ),
+            BlendMode::Screen => BlendState::new(
+                Equation::Add,
+                BlendFactor::One,
+                BlendFactor::OneMinusValue(BlendValue::SourceColor),
+            ),
+            BlendMode::Addition => BlendState::new(
+                Equation::Add,
+                BlendFactor::Value(BlendValue::SourceAlpha),
+                BlendFactor::One,
+            ),
+            BlendMode::Subtract => BlendState::new(
+                Equation::ReverseSubtract,
+                BlendFactor::Value(BlendValue::SourceAlpha),
+                BlendFactor::One,
+            ),
+            BlendMode::Normal | BlendMode::Unsupported => BlendState::new(
+                Equation::Add,
+                BlendFactor::Value(BlendValue::SourceAlpha),
+                BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+            ),
+        }
+    }
+
+    /// As [`BlendMode::blend_state`], for colours premultiplied by their alpha.
+    /// Multiply and screen only take opacity into account this way, since
+    /// their source factor is a colour rather than the source alpha.
+    fn premultiplied_blend_state(self) -> BlendState {
+        match self {
+            BlendMode::Addition => BlendState::new(Equation::Add, BlendFactor::One, BlendFactor::One),
+            BlendMode::Subtract => BlendState::new(Equation::ReverseSubtract, BlendFactor::One, BlendFactor::One),
+            BlendMode::Normal | BlendMode::Unsupported => BlendState::new(
+                Equation::Add,
+                BlendFactor::One,
+                BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+            ),
+            BlendMode::Multiply | BlendMode::Screen => self.blend_state(),
+        }
+    }
+
+    fn needs_material(self) -> bool {
+        !matches!(self, BlendMode::Normal | BlendMode::Unsupported)
+    }
+}
+
+pub(crate) const SPRITE_VERTEX_SHADER: &str = r#"#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+varying lowp vec2 uv;
+varying lowp vec4 color;
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    color = color0 / 255.0;
+    uv = texcoord;
+}
+"#;
+
+/// Premultiplies by alpha, so layer opacity fades every blend mode.
+const LAYER_FRAGMENT_SHADER: &str = r#"#version 100
+varying lowp vec4 color;
+varying lowp vec2 uv;
+uniform sampler2D Texture;
+
+void main() {
+    lowp vec4 texel = color * texture2D(Texture, uv);
+    gl_FragColor = vec4(texel.rgb * texel.a, texel.a);
+}
+"#;
+
+fn load_blend_material(blend_mode: BlendMode) -> Result<Material, macroquad::Error> {
+    load_material(
+        ShaderSource::Glsl {
+            vertex: SPRITE_VERTEX_SHADER,
+            fragment: LAYER_FRAGMENT_SHADER,
+        },
+        MaterialParams {
+            pipeline_params: PipelineParams {
+                color_blend: Some(blend_mode.premultiplied_blend_state()),
+                alpha_blend: Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::Zero,
+                    BlendFactor::One,
+                )),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+}
+
+/// Splits an Aseprite frame name such as `"skeletron 3.ase"` or, when exported
+/// with `--split-layers`, `"skeletron (Weapon) 3.ase"` into its layer and frame number.
+fn parse_frame_name(name: &str) -> Option<(Option<&str>, usize)> {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let number = stem.split_whitespace().last()?.parse().ok()?;
+    let layer = stem
+        .rfind('(')
+        .zip(stem.rfind(')'))
+        .filter(|(open, close)| open < close)
+        .map(|(open, close)| &stem[open + 1..close]);
+    Some((layer, number))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagBoundary {
+    #[default]
+    Start,
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum EventTrigger {
+    Frame { frame: usize },
+    Tag {
+        tag: String,
+        #[serde(default)]
+        at: TagBoundary,
+    },
+}
+
+/// A named event and when it fires. Sidecar event files hold a JSON list of these,
+/// e.g. `{ "event": "footstep", "frame": 5 }` or `{ "event": "land", "tag": "fall", "at": "end" }`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventDefinition {
+    pub event: String,
+    #[serde(flatten)]
+    pub trigger: EventTrigger,
+}
+
+/// An event fired during [`AnimationPlayer::update`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationEvent {
+    pub name: String,
+    pub frame: usize,
+    pub tag: Option<String>,
+}
+
+pub type EventListener = Box<dyn FnMut(&AnimationEvent)>;
+
+fn user_data_events(data: &Option<String>, trigger: EventTrigger) -> Vec<EventDefinition> {
+    data.iter()
+        .flat_map(|data| data.split(','))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| EventDefinition {
+            event: name.to_string(),
+            trigger: trigger.clone(),
+        })
+        .collect()
+}
+
+/// Point a sprite rotates around.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pivot {
+    /// A pixel of the untrimmed sprite.
+    Pixel(Vec2),
+    /// The pivot of the named slice on the current frame (its centre if the
+    /// slice has no pivot).
+    Slice(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DrawSpace {
+    /// Drawn through the active camera.
+    #[default]
+    World,
+    /// Drawn in screen pixels, ignoring the active camera.
+    Screen,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpriteDrawParams {
+    pub scale: Vec2,
+    /// Mirrors the sprite within its own bounds.
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Rotation in radians around `pivot`.
+    pub rotation: f32,
+    /// Defaults to the anchor.
+    pub pivot: Option<Pivot>,
+    /// Point of the sprite placed at the draw position, as a fraction of its
+    /// size: `(0, 0)` is the top-left corner, `(0.5, 1)` the bottom centre.
+    pub anchor: Vec2,
+    pub tint: Color,
+    pub space: DrawSpace,
 }
 
-struct Animation {
+impl Default for SpriteDrawParams {
+    fn default() -> Self {
+        SpriteDrawParams {
+            scale: Vec2::ONE,
+            flip_x: false,
+            flip_y: false,
+            rotation: 0.0,
+            pivot: None,
+            anchor: Vec2::ZERO,
+            tint: WHITE,
+            space: DrawSpace::World,
+        }
+    }
+}
+
+/// One layer of a sprite sheet and its frames.
+pub struct SpriteLayer {
+    pub name: String,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    pub frames: Vec<FrameData>,
+}
+
+/// Everything loaded from an Aseprite export: the texture, layers, tags, slices
+/// and events. Loaded once and shared between [`AnimationPlayer`]s with an `Rc`.
+pub struct SpriteSheet {
     texture: Texture2D,
-    frames: Vec<FrameData>,
-    current_frame: usize,
-    frame_time: f32,
+    layers: Vec<SpriteLayer>,
+    blend_materials: HashMap<BlendMode, Material>,
+    tags: Vec<FrameTag>,
+    slices: Vec<Slice>,
+    event_definitions: Vec<EventDefinition>,
 }
 
-impl Animation {
-    async fn new(json_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+impl SpriteSheet {
+    pub async fn load(json_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let json_data = load_string(json_path).await?;
         let aseprite_data: AsepriteData = serde_json::from_str(&json_data)?;
-        
-        let texture = load_texture(&format!("assets/{}", aseprite_data.meta.image)).await?;
+
+        // The image path is relative to the JSON file
+        let texture = load_texture(&resolve_path(json_path, &aseprite_data.meta.image)).await?;
+        SpriteSheet::from_aseprite(json_path, aseprite_data, texture)
+    }
+
+    /// Builds a sheet from parsed Aseprite data and its already loaded image.
+    /// `json_path` is only used in error messages.
+    pub fn from_aseprite(
+        json_path: &str,
+        aseprite_data: AsepriteData,
+        texture: Texture2D,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         texture.set_filter(FilterMode::Nearest);
 
-        // Convert HashMap to Vec, sorted by frame number
-        let mut frames: Vec<_> = aseprite_data.frames.into_iter().collect();
-        frames.sort_by(|(a, _), (b, _)| {
-            let a_num: i32 = a.split_whitespace().last().unwrap().trim_end_matches(".ase").parse().unwrap();
-            let b_num: i32 = b.split_whitespace().last().unwrap().trim_end_matches(".ase").parse().unwrap();
-            a_num.cmp(&b_num)
+        let mut event_definitions = Vec::new();
+        for tag in &aseprite_data.meta.frame_tags {
+            let trigger = EventTrigger::Tag {
+                tag: tag.name.clone(),
+                at: TagBoundary::Start,
+            };
+            event_definitions.extend(user_data_events(&tag.data, trigger));
+        }
+        for layer in &aseprite_data.meta.layers {
+            for cel in &layer.cels {
+                let trigger = EventTrigger::Frame { frame: cel.frame };
+                event_definitions.extend(user_data_events(&cel.data, trigger));
+            }
+        }
+
+        // Split-layer exports name the layer in each frame key; otherwise the whole
+        // sheet is a single, flattened layer
+        let mut parsed_frames = Vec::with_capacity(aseprite_data.frames.len());
+        for (name, frame_data) in aseprite_data.frames {
+            let (layer, number) = parse_frame_name(&name)
+                .ok_or_else(|| format!("unrecognised frame name {:?} in {}", name, json_path))?;
+            parsed_frames.push((layer.map(str::to_string), number, frame_data));
+        }
+        let split_layers = aseprite_data.meta.layers.iter().any(|layer| {
+            parsed_frames
+                .iter()
+                .any(|(frame_layer, _, _)| frame_layer.as_deref() == Some(layer.name.as_str()))
         });
-        let frames = frames.into_iter().map(|(_, frame_data)| frame_data).collect();
 
-        Ok(Animation {
+        // Group frames by layer, each sorted by frame number
+        let mut frames_by_layer: HashMap<Option<String>, Vec<(usize, FrameData)>> = HashMap::new();
+        for (layer, number, frame_data) in parsed_frames {
+            let layer = if split_layers { layer } else { None };
+            frames_by_layer.entry(layer).or_default().push((number, frame_data));
+        }
+        let mut take_frames = |layer: Option<String>| {
+            let mut frames = frames_by_layer.remove(&layer).unwrap_or_default();
+            frames.sort_by_key(|(number, _)| *number);
+            frames.into_iter().map(|(_, frame_data)| frame_data).collect::<Vec<_>>()
+        };
+
+        let layers: Vec<SpriteLayer> = if split_layers {
+            aseprite_data
+                .meta
+                .layers
+                .into_iter()
+                .map(|layer| SpriteLayer {
+                    frames: take_frames(Some(layer.name.clone())),
+                    name: layer.name,
+                    opacity: layer.opacity as f32 / 255.0,
+                    blend_mode: layer.blend_mode,
+                })
+                .filter(|layer| !layer.frames.is_empty())
+                .collect()
+        } else {
+            let name = aseprite_data
+                .meta
+                .layers
+                .first()
+                .map_or_else(|| "Layer".to_string(), |layer| layer.name.clone());
+            vec![SpriteLayer {
+                name,
+                opacity: 1.0,
+                blend_mode: BlendMode::Normal,
+                frames: take_frames(None),
+            }]
+        };
+        if layers.iter().all(|layer| layer.frames.is_empty()) {
+            return Err(format!("no frames found in {}", json_path).into());
+        }
+
+        let mut blend_materials = HashMap::new();
+        for layer in &layers {
+            if layer.blend_mode.needs_material() && !blend_materials.contains_key(&layer.blend_mode) {
+                blend_materials.insert(layer.blend_mode, load_blend_material(layer.blend_mode)?);
+            }
+        }
+
+        Ok(SpriteSheet {
             texture,
-            frames,
+            layers,
+            blend_materials,
+            tags: aseprite_data.meta.frame_tags,
+            slices: aseprite_data.meta.slices,
+            event_definitions,
+        })
+    }
+
+    /// Adds events from a sidecar JSON file holding a list of [`EventDefinition`]s.
+    pub async fn load_events(&mut self, json_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let json_data = load_string(json_path).await?;
+        let definitions: Vec<EventDefinition> = serde_json::from_str(&json_data)?;
+        self.event_definitions.extend(definitions);
+        Ok(())
+    }
+
+    pub fn add_event(&mut self, definition: EventDefinition) {
+        self.event_definitions.push(definition);
+    }
+
+    pub fn texture(&self) -> &Texture2D {
+        &self.texture
+    }
+
+    pub fn layers(&self) -> &[SpriteLayer] {
+        &self.layers
+    }
+
+    /// Distinct source rects of every frame of every layer in the texture.
+    pub fn frame_rects(&self) -> Vec<AsepriteRect> {
+        let mut rects: Vec<AsepriteRect> = Vec::new();
+        for frame in self.layers.iter().flat_map(|layer| &layer.frames) {
+            if !rects.contains(&frame.frame) {
+                rects.push(frame.frame.clone());
+            }
+        }
+        rects
+    }
+
+    /// Moves the sheet onto `texture`, e.g. an atlas page, where `relocate` gives
+    /// the new top-left corner of each frame's source rect.
+    pub fn relocate_frames(&mut self, texture: Texture2D, relocate: impl Fn(&AsepriteRect) -> Option<Vec2>) {
+        for frame in self.layers.iter_mut().flat_map(|layer| &mut layer.frames) {
+            if let Some(position) = relocate(&frame.frame) {
+                frame.frame.x = position.x as i32;
+                frame.frame.y = position.y as i32;
+            }
+        }
+        self.texture = texture;
+    }
+
+    pub fn tags(&self) -> &[FrameTag] {
+        &self.tags
+    }
+
+    pub fn slices(&self) -> &[Slice] {
+        &self.slices
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.layers.iter().map(|layer| layer.frames.len()).max().unwrap_or(0)
+    }
+
+    /// Size of the untrimmed sprite on `frame`, in pixels.
+    pub fn size(&self, frame: usize) -> Vec2 {
+        self.frame(frame).map_or(Vec2::ZERO, |frame| {
+            vec2(frame.source_size.w as f32, frame.source_size.h as f32)
+        })
+    }
+
+    fn frame(&self, frame: usize) -> Option<&FrameData> {
+        self.layers.iter().find_map(|layer| layer.frames.get(frame))
+    }
+
+    fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+}
+
+/// Per-instance display state of a [`SpriteLayer`].
+#[derive(Debug, Clone, Copy)]
+pub struct LayerState {
+    pub visible: bool,
+    pub tint: Color,
+}
+
+/// Playback state of one animated instance of a shared [`SpriteSheet`].
+pub struct AnimationPlayer {
+    sheet: Rc<SpriteSheet>,
+    layer_states: Vec<LayerState>,
+    current_tag: Option<usize>,
+    current_frame: usize,
+    frame_time: f32,
+    ping_pong_forward: bool,
+    loops: u32,
+    speed: f32,
+    paused: bool,
+    events: Vec<AnimationEvent>,
+    listeners: Vec<EventListener>,
+    frame_entered: bool,
+}
+
+impl AnimationPlayer {
+    pub fn new(sheet: Rc<SpriteSheet>) -> Self {
+        let layer_states = vec![
+            LayerState {
+                visible: true,
+                tint: WHITE,
+            };
+            sheet.layers.len()
+        ];
+        AnimationPlayer {
+            sheet,
+            layer_states,
+            current_tag: None,
             current_frame: 0,
             frame_time: 0.0,
-        })
+            ping_pong_forward: true,
+            loops: 0,
+            speed: 1.0,
+            paused: false,
+            events: Vec::new(),
+            listeners: Vec::new(),
+            frame_entered: true,
+        }
+    }
+
+    /// Loads a sheet for a single player; use [`AnimationPlayer::new`] to share one.
+    pub async fn load(json_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(AnimationPlayer::new(Rc::new(SpriteSheet::load(json_path).await?)))
+    }
+
+    pub fn sheet(&self) -> &Rc<SpriteSheet> {
+        &self.sheet
+    }
+
+    /// Calls `listener` for every event as it fires.
+    pub fn add_event_listener(&mut self, listener: impl FnMut(&AnimationEvent) + 'static) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    /// Events fired during the most recent [`AnimationPlayer::update`].
+    pub fn events(&self) -> &[AnimationEvent] {
+        &self.events
+    }
+
+    pub fn layers(&self) -> &[SpriteLayer] {
+        &self.sheet.layers
+    }
+
+    pub fn layer_state(&self, name: &str) -> Option<&LayerState> {
+        self.sheet.layer_index(name).map(|index| &self.layer_states[index])
+    }
+
+    pub fn layer_state_mut(&mut self, name: &str) -> Option<&mut LayerState> {
+        self.sheet.layer_index(name).map(|index| &mut self.layer_states[index])
+    }
+
+    /// Shows or hides a layer. Returns `false` if no layer has that name.
+    pub fn set_layer_visible(&mut self, name: &str, visible: bool) -> bool {
+        self.layer_state_mut(name).map(|state| state.visible = visible).is_some()
+    }
+
+    /// Multiplies a layer's pixels by `tint`. Returns `false` if no layer has that name.
+    pub fn set_layer_tint(&mut self, name: &str, tint: Color) -> bool {
+        self.layer_state_mut(name).map(|state| state.tint = tint).is_some()
+    }
+
+    /// Tints every layer.
+    pub fn set_tint(&mut self, tint: Color) {
+        for state in &mut self.layer_states {
+            state.tint = tint;
+        }
+    }
+
+    pub fn tags(&self) -> &[FrameTag] {
+        &self.sheet.tags
+    }
+
+    /// Name of the tag being played, or `None` when looping over every frame.
+    pub fn current_tag(&self) -> Option<&str> {
+        self.current_tag.map(|index| self.sheet.tags[index].name.as_str())
+    }
+
+    /// Restarts playback on the named tag. Returns `false` if there is no such tag.
+    pub fn play_tag(&mut self, name: &str) -> bool {
+        let Some(index) = self.sheet.tags.iter().position(|tag| tag.name == name) else {
+            return false;
+        };
+        self.current_tag = Some(index);
+        let (from, to) = self.frame_range();
+        let direction = self.sheet.tags[index].direction;
+        self.ping_pong_forward = matches!(direction, TagDirection::Forward | TagDirection::Pingpong);
+        self.current_frame = if self.ping_pong_forward { from } else { to };
+        self.frame_time = 0.0;
+        self.loops = 0;
+        self.frame_entered = true;
+        true
+    }
+
+    /// Plays every frame of the sheet in order, ignoring tags.
+    pub fn play_all(&mut self) {
+        self.current_tag = None;
+        self.current_frame = 0;
+        self.frame_time = 0.0;
+        self.ping_pong_forward = true;
+        self.loops = 0;
+        self.frame_entered = true;
+    }
+
+    /// Jumps to `frame`, clamped to the current tag's frames.
+    pub fn seek(&mut self, frame: usize) {
+        let (from, to) = self.frame_range();
+        self.current_frame = frame.clamp(from, to);
+        self.frame_time = 0.0;
+        self.frame_entered = true;
+    }
+
+    /// Playback rate multiplier; `1.0` plays at the durations set in Aseprite.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// How many times the current tag (or the whole sheet) has played through
+    /// since it was started.
+    pub fn loops(&self) -> u32 {
+        self.loops
+    }
+
+    pub fn current_frame(&self) -> usize {
+        self.current_frame
+    }
+
+    pub fn slices(&self) -> &[Slice] {
+        &self.sheet.slices
+    }
+
+    /// Bounds of the named slice on the current frame, in sprite pixels.
+    pub fn slice_bounds(&self, name: &str) -> Option<Rect> {
+        self.sheet
+            .slices
+            .iter()
+            .find(|slice| slice.name == name)?
+            .bounds_at(self.current_frame)
+    }
+
+    /// Size of the untrimmed sprite, in pixels.
+    pub fn size(&self) -> Vec2 {
+        self.sheet.size(self.current_frame)
+    }
+
+    fn frame_range(&self) -> (usize, usize) {
+        let last = self.sheet.frame_count().saturating_sub(1);
+        match self.current_tag {
+            Some(index) => {
+                let tag = &self.sheet.tags[index];
+                let from = (tag.from.max(0) as usize).min(last);
+                let to = (tag.to.max(0) as usize).clamp(from, last);
+                (from, to)
+            }
+            None => (0, last),
+        }
+    }
+
+    fn frame_duration(&self) -> f32 {
+        self.sheet
+            .frame(self.current_frame)
+            .map_or(0.0, |frame| frame.duration as f32 / 1000.0)
     }
 
-    fn update(&mut self, dt: f32) {
-        self.frame_time += dt;
-        if self.frame_time >= self.frames[self.current_frame].duration as f32 / 1000.0 {
-            self.current_frame = (self.current_frame + 1) % self.frames.len();
+    fn advance_frame(&mut self) {
+        let (from, to) = self.frame_range();
+        let direction = self
+            .current_tag
+            .map_or(TagDirection::Forward, |index| self.sheet.tags[index].direction);
+        match direction {
+            TagDirection::Forward => {
+                if self.current_frame >= to {
+                    self.current_frame = from;
+                    self.loops += 1;
+                } else {
+                    self.current_frame += 1;
+                }
+            }
+            TagDirection::Reverse => {
+                if self.current_frame <= from {
+                    self.current_frame = to;
+                    self.loops += 1;
+                } else {
+                    self.current_frame -= 1;
+                }
+            }
+            TagDirection::Pingpong | TagDirection::PingpongReverse => {
+                // A loop is one full there-and-back pass
+                let start_forward = direction == TagDirection::Pingpong;
+                if from == to {
+                    self.loops += 1;
+                } else if self.ping_pong_forward && self.current_frame >= to {
+                    self.ping_pong_forward = false;
+                    self.current_frame -= 1;
+                    if !start_forward {
+                        self.loops += 1;
+                    }
+                } else if !self.ping_pong_forward && self.current_frame <= from {
+                    self.ping_pong_forward = true;
+                    self.current_frame += 1;
+                    if start_forward {
+                        self.loops += 1;
+                    }
+                } else if self.ping_pong_forward {
+                    self.current_frame += 1;
+                } else {
+                    self.current_frame -= 1;
+                }
+            }
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.events.clear();
+        if self.frame_entered {
+            self.frame_entered = false;
+            self.fire_events(TagBoundary::Start);
+            self.fire_frame_events();
+        }
+        if self.paused {
+            return;
+        }
+
+        self.frame_time += dt * self.speed;
+        if self.frame_time >= self.frame_duration() {
+            let loops = self.loops;
+            self.advance_frame();
             self.frame_time = 0.0;
+            if self.loops > loops {
+                self.fire_events(TagBoundary::End);
+                self.fire_events(TagBoundary::Start);
+            }
+            self.fire_frame_events();
         }
     }
 
-    fn draw(&self, x: f32, y: f32) {
-        let scale = 10;
-        let frame = &self.frames[self.current_frame];
-        let source_rect = Rect::new(
-            frame.frame.x as f32,
-            frame.frame.y as f32,
-            frame.frame.w as f32,
-            frame.frame.h as f32,
-        );
-        draw_texture_ex(
-            &self.texture,
-            x,
-            y,
-            WHITE,
-            DrawTextureParams {
-                dest_size: Some(vec2((frame.source_size.w * scale) as f32, (frame.source_size.h * scale)as f32)),
-                source: Some(source_rect),
-                ..Default::default()
-            },
-        );
+    fn fire_events(&mut self, boundary: TagBoundary) {
+        let Some(tag) = self.current_tag().map(str::to_string) else {
+            return;
+        };
+        let names: Vec<String> = self
+            .sheet
+            .event_definitions
+            .iter()
+            .filter(|definition| match &definition.trigger {
+                EventTrigger::Tag { tag: event_tag, at } => *event_tag == tag && *at == boundary,
+                EventTrigger::Frame { .. } => false,
+            })
+            .map(|definition| definition.event.clone())
+            .collect();
+        for name in names {
+            self.fire(name);
+        }
     }
-}
 
-#[macroquad::main("Aseprite Animation")]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut animation = Animation::new("assets/skeletron.json").await?;
+    fn fire_frame_events(&mut self) {
+        let names: Vec<String> = self
+            .sheet
+            .event_definitions
+            .iter()
+            .filter(|definition| definition.trigger == EventTrigger::Frame { frame: self.current_frame })
+            .map(|definition| definition.event.clone())
+            .collect();
+        for name in names {
+            self.fire(name);
+        }
+    }
 
-    loop {
-        clear_background(Color::new(0.1, 0.2, 0.3, 1.0));
+    fn fire(&mut self, name: String) {
+        let event = AnimationEvent {
+            name,
+            frame: self.current_frame,
+            tag: self.current_tag().map(str::to_string),
+        };
+        for listener in &mut self.listeners {
+            listener(&event);
+        }
+        self.events.push(event);
+    }
+
+    /// Maps sprite pixels to positions relative to the point the sprite is drawn at.
+    pub fn local_matrix(&self, params: &SpriteDrawParams) -> Mat3 {
+        let size = self.size();
+        let flip = Mat3::from_translation(vec2(
+            if params.flip_x { size.x } else { 0.0 },
+            if params.flip_y { size.y } else { 0.0 },
+        )) * Mat3::from_scale(vec2(
+            if params.flip_x { -1.0 } else { 1.0 },
+            if params.flip_y { -1.0 } else { 1.0 },
+        ));
+        let anchor = params.anchor * size;
+        let pivot = match &params.pivot {
+            Some(Pivot::Pixel(pixel)) => flip.transform_point2(*pixel),
+            Some(Pivot::Slice(name)) => self
+                .sheet
+                .slices
+                .iter()
+                .find(|slice| slice.name == *name)
+                .and_then(|slice| slice.pivot_at(self.current_frame))
+                .map_or(anchor, |pixel| flip.transform_point2(pixel)),
+            None => anchor,
+        };
+        let pivot = params.scale * (pivot - anchor);
+
+        Mat3::from_translation(pivot)
+            * Mat3::from_angle(params.rotation)
+            * Mat3::from_translation(-pivot)
+            * Mat3::from_scale(params.scale)
+            * Mat3::from_translation(-anchor)
+            * flip
+    }
+
+    /// Draws the current frame with its anchor at `position`.
+    pub fn draw(&self, position: Vec2, params: &SpriteDrawParams) {
+        self.draw_transformed(Mat3::from_translation(position), params);
+    }
 
-        animation.update(get_frame_time());
-        animation.draw(screen_width() / 2.0, screen_height() / 2.0);
+    /// Draws the current frame with its anchor at the origin of `matrix`, e.g.
+    /// [`TransformStack::current`](crate::transform::TransformStack::current).
+    pub fn draw_transformed(&self, matrix: Mat3, params: &SpriteDrawParams) {
+        if params.space == DrawSpace::Screen {
+            push_camera_state();
+            set_default_camera();
+        }
+
+        let matrix = matrix * self.local_matrix(params);
+        let texture = &self.sheet.texture;
+        let texture_size = vec2(texture.width(), texture.height());
+        for (layer, state) in self.sheet.layers.iter().zip(&self.layer_states) {
+            if !state.visible {
+                continue;
+            }
+            let Some(frame) = layer.frames.get(self.current_frame) else {
+                continue;
+            };
+            // Trimmed frames are offset within the untrimmed sprite bounds
+            let offset = vec2(frame.sprite_source_size.x as f32, frame.sprite_source_size.y as f32);
+            let uv_offset = vec2(frame.frame.x as f32, frame.frame.y as f32);
+            let frame_size = vec2(frame.frame.w as f32, frame.frame.h as f32);
+            let color = Color::new(
+                state.tint.r * params.tint.r,
+                state.tint.g * params.tint.g,
+                state.tint.b * params.tint.b,
+                state.tint.a * params.tint.a * layer.opacity,
+            );
+            let vertices = [vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0)]
+                .map(|corner| {
+                    let position = matrix.transform_point2(offset + corner * frame_size);
+                    let uv = (uv_offset + corner * frame_size) / texture_size;
+                    Vertex::new(position.x, position.y, 0.0, uv.x, uv.y, color)
+                })
+                .to_vec();
+
+            let material = self.sheet.blend_materials.get(&layer.blend_mode);
+            if let Some(material) = material {
+                gl_use_material(material);
+            }
+            draw_mesh(&Mesh {
+                vertices,
+                indices: vec![0, 1, 2, 0, 2, 3],
+                texture: Some(texture.clone()),
+            });
+            if material.is_some() {
+                gl_use_default_material();
+            }
+        }
 
-        next_frame().await;
+        if params.space == DrawSpace::Screen {
+            pop_camera_state();
+        }
     }
-}
\ No newline at end of file
+}
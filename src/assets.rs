@@ -0,0 +1,823 @@
+use crate::aseprite::{AsepriteData, EventDefinition, SpriteSheet};
+use crate::atlas::{Atlas, AtlasBuilder, AtlasError, AtlasOptions, RegionId};
+use macroquad::audio::{load_sound, Sound};
+use macroquad::prelude::*;
+use macroquad_tiled as tiled;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+/// Joins `relative` onto the directory containing `base`, resolving `.` and `..`.
+pub fn resolve_path(base: &str, relative: &str) -> String {
+    if relative.starts_with('/') {
+        return relative.to_string();
+    }
+    let mut parts: Vec<&str> = base.split('/').collect();
+    parts.pop();
+    for part in relative.split('/') {
+        match part {
+            "" | "." => {}
+            ".." if parts.last().is_some_and(|last| *last != "..") => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+#[derive(Debug, Clone)]
+pub struct AssetError {
+    pub path: String,
+    /// The file whose contents named `path`, e.g. the sprite sheet using an image.
+    pub referenced_from: Option<String>,
+    pub message: String,
+}
+
+impl AssetError {
+    fn new(path: &str, referenced_from: Option<&str>, message: impl fmt::Display) -> Self {
+        AssetError {
+            path: path.to_string(),
+            referenced_from: referenced_from.map(str::to_string),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to load {}: {}", self.path, self.message)?;
+        if let Some(referenced_from) = &self.referenced_from {
+            write!(f, " (referenced from {})", referenced_from)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+/// Typed reference to an asset in [`Assets`]. Cheap to copy; stays valid while
+/// the asset loads.
+pub struct Handle<T> {
+    index: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    fn new(index: usize) -> Self {
+        Handle {
+            index,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({})", self.index)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ShaderCode {
+    pub vertex: String,
+    pub fragment: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Script {
+    pub path: String,
+    pub source: String,
+}
+
+enum Slot<T> {
+    Loading,
+    Ready(T),
+    Failed(AssetError),
+}
+
+/// Assets of one type, deduplicated by path.
+pub struct Storage<T> {
+    paths: HashMap<String, usize>,
+    entries: Vec<(String, Slot<T>)>,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Storage {
+            paths: HashMap::new(),
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<T> Storage<T> {
+    /// Returns the existing handle for `path`, or a new one and `true` if the
+    /// asset still has to be loaded.
+    fn insert(&mut self, path: &str) -> (Handle<T>, bool) {
+        if let Some(index) = self.paths.get(path) {
+            return (Handle::new(*index), false);
+        }
+        let index = self.entries.len();
+        self.paths.insert(path.to_string(), index);
+        self.entries.push((path.to_string(), Slot::Loading));
+        (Handle::new(index), true)
+    }
+
+    fn set(&mut self, index: usize, result: Result<T, AssetError>) {
+        self.entries[index].1 = match result {
+            Ok(asset) => Slot::Ready(asset),
+            Err(err) => Slot::Failed(err),
+        };
+    }
+
+    fn counts(&self) -> (usize, usize) {
+        let finished = self
+            .entries
+            .iter()
+            .filter(|(_, slot)| !matches!(slot, Slot::Loading))
+            .count();
+        (finished, self.entries.len())
+    }
+
+    fn errors(&self) -> impl Iterator<Item = &AssetError> {
+        self.entries.iter().filter_map(|(_, slot)| match slot {
+            Slot::Failed(err) => Some(err),
+            _ => None,
+        })
+    }
+}
+
+/// Types stored in [`Assets`].
+pub trait Asset: Sized + 'static {
+    #[doc(hidden)]
+    fn storage(assets: &Assets) -> &Storage<Self>;
+    #[doc(hidden)]
+    fn storage_mut(assets: &mut Assets) -> &mut Storage<Self>;
+}
+
+macro_rules! impl_asset {
+    ($type:ty, $field:ident) => {
+        impl Asset for $type {
+            fn storage(assets: &Assets) -> &Storage<Self> {
+                &assets.$field
+            }
+
+            fn storage_mut(assets: &mut Assets) -> &mut Storage<Self> {
+                &mut assets.$field
+            }
+        }
+    };
+}
+
+impl_asset!(Texture2D, textures);
+impl_asset!(Rc<SpriteSheet>, sprite_sheets);
+impl_asset!(tiled::Map, tiled_maps);
+impl_asset!(ShaderCode, shaders);
+impl_asset!(Script, scripts);
+impl_asset!(Sound, sounds);
+
+type LoadFuture = Pin<Box<dyn Future<Output = Result<Loaded, AssetError>>>>;
+
+enum Loaded {
+    Texture(Texture2D),
+    /// The JSON file's path, its contents and the events from the sidecar file.
+    SpriteSheet(String, Box<AsepriteData>, Vec<EventDefinition>),
+    TiledMap(String, Vec<TilesetSource>),
+    Shader(ShaderCode),
+    Script(Script),
+    Sound(Sound),
+}
+
+#[derive(Clone, Copy)]
+enum Target {
+    Texture(usize),
+    SpriteSheet(usize),
+    TiledMap(usize),
+    Shader(usize),
+    Script(usize),
+    Sound(usize),
+}
+
+/// A tileset of a Tiled map: its image as named in the JSON, the file that names
+/// it, and for external tilesets the tileset file's contents.
+struct TilesetSource {
+    image: String,
+    referenced_from: String,
+    external: Option<(String, String)>,
+}
+
+/// An asset waiting on the textures it references.
+enum Dependent {
+    SpriteSheet {
+        index: usize,
+        json_path: String,
+        data: Box<AsepriteData>,
+        events: Vec<EventDefinition>,
+        texture: Handle<Texture2D>,
+    },
+    TiledMap {
+        index: usize,
+        json: String,
+        external_tilesets: Vec<(String, String)>,
+        textures: Vec<(String, Handle<Texture2D>)>,
+    },
+}
+
+/// Loads and caches textures, sprite sheets, Tiled maps, shaders, scripts and
+/// sounds. Requesting an asset returns a [`Handle`] immediately; the file loads
+/// as [`Assets::poll`] is called, e.g. by [`Assets::load_all`] while it draws a
+/// loading screen.
+pub struct Assets {
+    textures: Storage<Texture2D>,
+    sprite_sheets: Storage<Rc<SpriteSheet>>,
+    tiled_maps: Storage<tiled::Map>,
+    shaders: Storage<ShaderCode>,
+    scripts: Storage<Script>,
+    sounds: Storage<Sound>,
+    jobs: Vec<(Target, LoadFuture)>,
+    dependents: Vec<Dependent>,
+    texture_filter: FilterMode,
+    /// Textures requested through [`Assets::texture`] rather than by another asset.
+    direct_textures: HashSet<usize>,
+    atlas: Option<Atlas>,
+    texture_regions: HashMap<usize, RegionId>,
+    /// Packed tilesets by map index and tileset name.
+    tileset_regions: HashMap<(usize, String), RegionId>,
+}
+
+impl Default for Assets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        Assets {
+            textures: Storage::default(),
+            sprite_sheets: Storage::default(),
+            tiled_maps: Storage::default(),
+            shaders: Storage::default(),
+            scripts: Storage::default(),
+            sounds: Storage::default(),
+            jobs: Vec::new(),
+            dependents: Vec::new(),
+            texture_filter: FilterMode::Nearest,
+            direct_textures: HashSet::new(),
+            atlas: None,
+            texture_regions: HashMap::new(),
+            tileset_regions: HashMap::new(),
+        }
+    }
+
+    /// Filter applied to textures as they finish loading. Defaults to `Nearest`
+    /// for pixel art.
+    pub fn set_texture_filter(&mut self, filter: FilterMode) {
+        self.texture_filter = filter;
+    }
+
+    pub fn texture(&mut self, path: &str) -> Handle<Texture2D> {
+        let handle = self.request_texture(path, None);
+        self.direct_textures.insert(handle.index);
+        handle
+    }
+
+    /// An Aseprite JSON export; its image is resolved relative to the JSON file.
+    pub fn sprite_sheet(&mut self, json_path: &str) -> Handle<Rc<SpriteSheet>> {
+        self.request_sprite_sheet(json_path, None)
+    }
+
+    /// An Aseprite JSON export plus a sidecar file of [`EventDefinition`]s.
+    pub fn sprite_sheet_with_events(&mut self, json_path: &str, events_path: &str) -> Handle<Rc<SpriteSheet>> {
+        self.request_sprite_sheet(json_path, Some(events_path))
+    }
+
+    /// A Tiled JSON map; tileset images and external tilesets are resolved relative
+    /// to the file that names them.
+    pub fn tiled_map(&mut self, path: &str) -> Handle<tiled::Map> {
+        let (handle, is_new) = self.tiled_maps.insert(path);
+        if is_new {
+            let path = path.to_string();
+            self.jobs.push((
+                Target::TiledMap(handle.index),
+                Box::pin(async move {
+                    let json = load_text(&path, None).await?;
+                    let tilesets = load_tilesets(&path, &json).await?;
+                    Ok(Loaded::TiledMap(json, tilesets))
+                }),
+            ));
+        }
+        handle
+    }
+
+    pub fn shader(&mut self, vertex_path: &str, fragment_path: &str) -> Handle<ShaderCode> {
+        let (handle, is_new) = self.shaders.insert(&format!("{} + {}", vertex_path, fragment_path));
+        if is_new {
+            let vertex_path = vertex_path.to_string();
+            let fragment_path = fragment_path.to_string();
+            self.jobs.push((
+                Target::Shader(handle.index),
+                Box::pin(async move {
+                    Ok(Loaded::Shader(ShaderCode {
+                        vertex: load_text(&vertex_path, None).await?,
+                        fragment: load_text(&fragment_path, None).await?,
+                    }))
+                }),
+            ));
+        }
+        handle
+    }
+
+    pub fn script(&mut self, path: &str) -> Handle<Script> {
+        let (handle, is_new) = self.scripts.insert(path);
+        if is_new {
+            let path = path.to_string();
+            self.jobs.push((
+                Target::Script(handle.index),
+                Box::pin(async move {
+                    let source = load_text(&path, None).await?;
+                    Ok(Loaded::Script(Script { path, source }))
+                }),
+            ));
+        }
+        handle
+    }
+
+    pub fn sound(&mut self, path: &str) -> Handle<Sound> {
+        let (handle, is_new) = self.sounds.insert(path);
+        if is_new {
+            let path = path.to_string();
+            self.jobs.push((
+                Target::Sound(handle.index),
+                Box::pin(async move {
+                    load_sound(&path)
+                        .await
+                        .map(Loaded::Sound)
+                        .map_err(|err| AssetError::new(&path, None, err))
+                }),
+            ));
+        }
+        handle
+    }
+
+    /// The asset, once it has loaded.
+    pub fn get<T: Asset>(&self, handle: Handle<T>) -> Option<&T> {
+        match &T::storage(self).entries[handle.index].1 {
+            Slot::Ready(asset) => Some(asset),
+            _ => None,
+        }
+    }
+
+    /// The asset, once it has loaded, for editing in place.
+    pub fn get_mut<T: Asset>(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        match &mut T::storage_mut(self).entries[handle.index].1 {
+            Slot::Ready(asset) => Some(asset),
+            _ => None,
+        }
+    }
+
+    /// The asset, or why it isn't available.
+    pub fn try_get<T: Asset>(&self, handle: Handle<T>) -> Result<&T, AssetError> {
+        let (path, slot) = &T::storage(self).entries[handle.index];
+        match slot {
+            Slot::Ready(asset) => Ok(asset),
+            Slot::Failed(err) => Err(err.clone()),
+            Slot::Loading => Err(AssetError::new(path, None, "still loading")),
+        }
+    }
+
+    pub fn path<T: Asset>(&self, handle: Handle<T>) -> &str {
+        &T::storage(self).entries[handle.index].0
+    }
+
+    /// Fraction of requested assets that have finished loading or failed.
+    pub fn progress(&self) -> f32 {
+        let (finished, total) = self.counts();
+        if total == 0 {
+            1.0
+        } else {
+            finished as f32 / total as f32
+        }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        let (finished, total) = self.counts();
+        finished < total
+    }
+
+    pub fn errors(&self) -> Vec<&AssetError> {
+        let mut errors: Vec<&AssetError> = self.textures.errors().collect();
+        errors.extend(self.sprite_sheets.errors());
+        errors.extend(self.tiled_maps.errors());
+        errors.extend(self.shaders.errors());
+        errors.extend(self.scripts.errors());
+        errors.extend(self.sounds.errors());
+        errors
+    }
+
+    /// Advances every in-flight load without blocking. Call once per frame while
+    /// [`Assets::is_loading`].
+    pub fn poll(&mut self) {
+        // macroquad's file futures complete from the main loop rather than through
+        // wakers, so polling them each frame is enough
+        let mut context = Context::from_waker(Waker::noop());
+        let mut index = 0;
+        while index < self.jobs.len() {
+            match self.jobs[index].1.as_mut().poll(&mut context) {
+                Poll::Ready(result) => {
+                    let (target, _) = self.jobs.swap_remove(index);
+                    self.complete(target, result);
+                }
+                Poll::Pending => index += 1,
+            }
+        }
+        self.resolve_dependents();
+    }
+
+    /// Polls until every requested asset has loaded or failed, drawing a progress
+    /// bar between frames. Returns the first error.
+    pub async fn load_all(&mut self) -> Result<(), AssetError> {
+        loop {
+            self.poll();
+            if !self.is_loading() {
+                break;
+            }
+            draw_loading_screen(self.progress());
+            next_frame().await;
+        }
+        match self.errors().first() {
+            Some(err) => Err((*err).clone()),
+            None => Ok(()),
+        }
+    }
+
+    /// Repacks every loaded sprite sheet, Tiled tileset and texture requested with
+    /// [`Assets::texture`] into shared atlas pages, so drawing them doesn't switch
+    /// textures. Call once after loading and before handing out sprite sheets;
+    /// sheets already shared elsewhere keep their own texture.
+    pub fn pack_atlas(&mut self, options: AtlasOptions) -> Result<(), AtlasError> {
+        let mut builder = AtlasBuilder::new(options);
+
+        let mut sheets = Vec::new();
+        for (index, (path, slot)) in self.sprite_sheets.entries.iter().enumerate() {
+            if let Slot::Ready(sheet) = slot {
+                if Rc::strong_count(sheet) == 1 {
+                    sheets.push((index, builder.add_sprite_sheet(sheet)));
+                } else {
+                    warn!("{} is already in use and won't be packed into the atlas", path);
+                }
+            }
+        }
+        let mut tilesets = Vec::new();
+        for (index, (_, slot)) in self.tiled_maps.entries.iter().enumerate() {
+            if let Slot::Ready(map) = slot {
+                for (name, tileset) in &map.tilesets {
+                    tilesets.push((index, name.clone(), builder.add_tileset(tileset)));
+                }
+            }
+        }
+        let mut texture_regions = HashMap::new();
+        for index in &self.direct_textures {
+            if let Slot::Ready(texture) = &self.textures.entries[*index].1 {
+                texture_regions.insert(*index, builder.add_image(&texture.get_texture_data()));
+            }
+        }
+
+        let atlas = builder.build()?;
+        for (index, regions) in sheets {
+            if let Slot::Ready(sheet) = &mut self.sprite_sheets.entries[index].1 {
+                if let Some(sheet) = Rc::get_mut(sheet) {
+                    atlas.apply_to_sprite_sheet(&regions, sheet);
+                }
+            }
+        }
+        let mut tileset_regions = HashMap::new();
+        for (index, name, region) in tilesets {
+            if let Slot::Ready(map) = &mut self.tiled_maps.entries[index].1 {
+                if let Some(tileset) = map.tilesets.get_mut(&name) {
+                    atlas.apply_to_tileset(region, tileset);
+                    tileset_regions.insert((index, name), region);
+                }
+            }
+        }
+        self.texture_regions = texture_regions;
+        self.tileset_regions = tileset_regions;
+        self.atlas = Some(atlas);
+        Ok(())
+    }
+
+    /// The atlas page and source rect of a texture packed by [`Assets::pack_atlas`],
+    /// or the texture itself and its full rect if it wasn't packed.
+    pub fn texture_region(&self, handle: Handle<Texture2D>) -> Option<(&Texture2D, Rect)> {
+        if let (Some(atlas), Some(region)) = (&self.atlas, self.texture_regions.get(&handle.index)) {
+            return Some((atlas.texture(*region), atlas.region(*region).rect));
+        }
+        let texture = self.get(handle)?;
+        Some((texture, Rect::new(0.0, 0.0, texture.width(), texture.height())))
+    }
+
+    /// A source rect in a tileset's original image, as `Map::spr_ex` takes it,
+    /// moved to where [`Assets::pack_atlas`] put that tileset. Unchanged if the
+    /// tileset wasn't packed.
+    pub fn tileset_rect(&self, map: Handle<tiled::Map>, tileset: &str, source: Rect) -> Rect {
+        match (&self.atlas, self.tileset_regions.get(&(map.index, tileset.to_string()))) {
+            (Some(atlas), Some(region)) => atlas.tileset_rect(*region, source),
+            _ => source,
+        }
+    }
+
+    fn counts(&self) -> (usize, usize) {
+        [
+            self.textures.counts(),
+            self.sprite_sheets.counts(),
+            self.tiled_maps.counts(),
+            self.shaders.counts(),
+            self.scripts.counts(),
+            self.sounds.counts(),
+        ]
+        .iter()
+        .fold((0, 0), |(finished, total), counts| (finished + counts.0, total + counts.1))
+    }
+
+    fn request_texture(&mut self, path: &str, referenced_from: Option<&str>) -> Handle<Texture2D> {
+        let (handle, is_new) = self.textures.insert(path);
+        if is_new {
+            let path = path.to_string();
+            let referenced_from = referenced_from.map(str::to_string);
+            self.jobs.push((
+                Target::Texture(handle.index),
+                Box::pin(async move {
+                    load_texture(&path)
+                        .await
+                        .map(Loaded::Texture)
+                        .map_err(|err| AssetError::new(&path, referenced_from.as_deref(), err))
+                }),
+            ));
+        }
+        handle
+    }
+
+    fn request_sprite_sheet(&mut self, json_path: &str, events_path: Option<&str>) -> Handle<Rc<SpriteSheet>> {
+        // Only for telling sheets apart; the paths travel with the load itself
+        let key = match events_path {
+            Some(events_path) => format!("{} + {}", json_path, events_path),
+            None => json_path.to_string(),
+        };
+        let (handle, is_new) = self.sprite_sheets.insert(&key);
+        if is_new {
+            let json_path = json_path.to_string();
+            let events_path = events_path.map(str::to_string);
+            self.jobs.push((
+                Target::SpriteSheet(handle.index),
+                Box::pin(async move {
+                    let json = load_text(&json_path, None).await?;
+                    let data: Box<AsepriteData> = serde_json::from_str(&json).map_err(|err| AssetError::new(&json_path, None, err))?;
+                    let events = match events_path {
+                        Some(events_path) => {
+                            let json = load_text(&events_path, Some(&json_path)).await?;
+                            serde_json::from_str(&json)
+                                .map_err(|err| AssetError::new(&events_path, Some(&json_path), err))?
+                        }
+                        None => Vec::new(),
+                    };
+                    Ok(Loaded::SpriteSheet(json_path, data, events))
+                }),
+            ));
+        }
+        handle
+    }
+
+    fn complete(&mut self, target: Target, result: Result<Loaded, AssetError>) {
+        let loaded = match (target, result) {
+            (Target::Texture(index), result) => {
+                let result = result.map(|loaded| match loaded {
+                    Loaded::Texture(texture) => texture,
+                    _ => unreachable!(),
+                });
+                if let Ok(texture) = &result {
+                    texture.set_filter(self.texture_filter);
+                }
+                self.textures.set(index, result);
+                return;
+            }
+            (_, Ok(loaded)) => loaded,
+            (target, Err(err)) => {
+                self.fail(target, err);
+                return;
+            }
+        };
+
+        match (target, loaded) {
+            (Target::SpriteSheet(index), Loaded::SpriteSheet(json_path, data, events)) => {
+                let image_path = resolve_path(&json_path, &data.meta.image);
+                let texture = self.request_texture(&image_path, Some(&json_path));
+                self.dependents.push(Dependent::SpriteSheet {
+                    index,
+                    json_path,
+                    data,
+                    events,
+                    texture,
+                });
+            }
+            (Target::TiledMap(index), Loaded::TiledMap(json, tilesets)) => {
+                let textures = tilesets
+                    .iter()
+                    .map(|tileset| {
+                        let path = resolve_path(&tileset.referenced_from, &tileset.image);
+                        let texture = self.request_texture(&path, Some(&tileset.referenced_from));
+                        (tileset.image.clone(), texture)
+                    })
+                    .collect();
+                let external_tilesets = tilesets.into_iter().filter_map(|tileset| tileset.external).collect();
+                self.dependents.push(Dependent::TiledMap {
+                    index,
+                    json,
+                    external_tilesets,
+                    textures,
+                });
+            }
+            (Target::Shader(index), Loaded::Shader(shader)) => self.shaders.set(index, Ok(shader)),
+            (Target::Script(index), Loaded::Script(script)) => self.scripts.set(index, Ok(script)),
+            (Target::Sound(index), Loaded::Sound(sound)) => self.sounds.set(index, Ok(sound)),
+            _ => unreachable!("asset loaded into the wrong storage"),
+        }
+    }
+
+    fn fail(&mut self, target: Target, err: AssetError) {
+        match target {
+            Target::Texture(index) => self.textures.set(index, Err(err)),
+            Target::SpriteSheet(index) => self.sprite_sheets.set(index, Err(err)),
+            Target::TiledMap(index) => self.tiled_maps.set(index, Err(err)),
+            Target::Shader(index) => self.shaders.set(index, Err(err)),
+            Target::Script(index) => self.scripts.set(index, Err(err)),
+            Target::Sound(index) => self.sounds.set(index, Err(err)),
+        }
+    }
+
+    /// Builds sprite sheets and maps whose textures have all finished loading.
+    fn resolve_dependents(&mut self) {
+        let dependents = std::mem::take(&mut self.dependents);
+        for dependent in dependents {
+            match dependent {
+                Dependent::SpriteSheet {
+                    index,
+                    json_path,
+                    data,
+                    events,
+                    texture,
+                } => {
+                    let texture = match &self.textures.entries[texture.index].1 {
+                        Slot::Loading => {
+                            self.dependents.push(Dependent::SpriteSheet {
+                                index,
+                                json_path,
+                                data,
+                                events,
+                                texture,
+                            });
+                            continue;
+                        }
+                        Slot::Failed(err) => Err(err.clone()),
+                        Slot::Ready(texture) => Ok(texture.clone()),
+                    };
+                    let result = texture.and_then(|texture| {
+                        let mut sheet = SpriteSheet::from_aseprite(&json_path, *data, texture)
+                            .map_err(|err| AssetError::new(&json_path, None, err))?;
+                        for event in events {
+                            sheet.add_event(event);
+                        }
+                        Ok(Rc::new(sheet))
+                    });
+                    self.sprite_sheets.set(index, result);
+                }
+                Dependent::TiledMap {
+                    index,
+                    json,
+                    external_tilesets,
+                    textures,
+                } => {
+                    let mut loaded = Vec::with_capacity(textures.len());
+                    let mut failed = None;
+                    let mut waiting = false;
+                    for (image, texture) in &textures {
+                        match &self.textures.entries[texture.index].1 {
+                            Slot::Loading => waiting = true,
+                            Slot::Failed(err) => failed = Some(err.clone()),
+                            Slot::Ready(texture) => loaded.push((image.as_str(), texture.clone())),
+                        }
+                    }
+                    if waiting && failed.is_none() {
+                        self.dependents.push(Dependent::TiledMap {
+                            index,
+                            json,
+                            external_tilesets,
+                            textures,
+                        });
+                        continue;
+                    }
+                    let path = self.tiled_maps.entries[index].0.clone();
+                    let result = match failed {
+                        Some(err) => Err(err),
+                        None => {
+                            let external_tilesets: Vec<(&str, &str)> = external_tilesets
+                                .iter()
+                                .map(|(source, json)| (source.as_str(), json.as_str()))
+                                .collect();
+                            // Object layers as Tiled saves them need converting first
+                            crate::level::to_macroquad_json(&json)
+                                .map_err(|err| AssetError::new(&path, None, err))
+                                .and_then(|json| {
+                                    tiled::load_map(&json, &loaded, &external_tilesets)
+                                        .map_err(|err| AssetError::new(&path, None, err))
+                                })
+                        }
+                    };
+                    self.tiled_maps.set(index, result);
+                }
+            }
+        }
+    }
+}
+
+async fn load_text(path: &str, referenced_from: Option<&str>) -> Result<String, AssetError> {
+    load_string(path)
+        .await
+        .map_err(|err| AssetError::new(path, referenced_from, err))
+}
+
+/// Finds the tilesets of a Tiled JSON map, loading any external tileset files.
+async fn load_tilesets(map_path: &str, json: &str) -> Result<Vec<TilesetSource>, AssetError> {
+    let map: serde_json::Value = serde_json::from_str(json).map_err(|err| AssetError::new(map_path, None, err))?;
+    let mut tilesets = Vec::new();
+    for tileset in map["tilesets"].as_array().into_iter().flatten() {
+        match tileset["source"].as_str().filter(|source| !source.is_empty()) {
+            Some(source) => {
+                let tileset_path = resolve_path(map_path, source);
+                let tileset_json = load_text(&tileset_path, Some(map_path)).await?;
+                let external: serde_json::Value = serde_json::from_str(&tileset_json)
+                    .map_err(|err| AssetError::new(&tileset_path, Some(map_path), err))?;
+                let image = external["image"]
+                    .as_str()
+                    .ok_or_else(|| AssetError::new(&tileset_path, Some(map_path), "tileset has no image"))?;
+                tilesets.push(TilesetSource {
+                    image: image.to_string(),
+                    referenced_from: tileset_path,
+                    external: Some((source.to_string(), tileset_json)),
+                });
+            }
+            None => {
+                let image = tileset["image"]
+                    .as_str()
+                    .ok_or_else(|| AssetError::new(map_path, None, "tileset has no image"))?;
+                tilesets.push(TilesetSource {
+                    image: image.to_string(),
+                    referenced_from: map_path.to_string(),
+                    external: None,
+                });
+            }
+        }
+    }
+    Ok(tilesets)
+}
+
+/// A progress bar in the middle of the screen.
+pub fn draw_loading_screen(progress: f32) {
+    const BAR_WIDTH: f32 = 300.0;
+    const BAR_HEIGHT: f32 = 20.0;
+
+    clear_background(BLACK);
+    let x = (screen_width() - BAR_WIDTH) / 2.0;
+    let y = (screen_height() - BAR_HEIGHT) / 2.0;
+    draw_rectangle_lines(x, y, BAR_WIDTH, BAR_HEIGHT, 2.0, WHITE);
+    draw_rectangle(x, y, BAR_WIDTH * progress.clamp(0.0, 1.0), BAR_HEIGHT, WHITE);
+    let label = format!("Loading... {:.0}%", progress * 100.0);
+    draw_text(&label, x, y - 10.0, 20.0, WHITE);
+}
@@ -0,0 +1,447 @@
+use crate::aseprite::{AsepriteRect, SpriteSheet};
+use macroquad::prelude::*;
+use macroquad_tiled::TileSet;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy)]
+pub struct AtlasOptions {
+    /// Width and height of each atlas page in pixels.
+    pub page_size: u32,
+    /// Transparent gap left between packed images.
+    pub padding: u32,
+    /// Edge pixels repeated around each image so filtering and rounding at its
+    /// border sample its own colours rather than a neighbour's.
+    pub extrude: u32,
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        AtlasOptions {
+            page_size: 2048,
+            padding: 1,
+            extrude: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum AtlasError {
+    /// A single image is bigger than a page.
+    ImageTooLarge { width: u32, height: u32, page_size: u32 },
+    /// Images that must share a page, e.g. the frames of one sprite sheet, don't
+    /// fit on one together.
+    GroupTooLarge { images: usize, page_size: u32 },
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtlasError::ImageTooLarge {
+                width,
+                height,
+                page_size,
+            } => write!(f, "{}x{} image doesn't fit on a {}x{} atlas page", width, height, page_size, page_size),
+            AtlasError::GroupTooLarge { images, page_size } => write!(
+                f,
+                "{} images that share a texture don't fit on a {}x{} atlas page",
+                images, page_size, page_size
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegionId(usize);
+
+/// Where a packed image ended up: its page and its pixel rect on that page,
+/// excluding extrusion.
+#[derive(Debug, Clone, Copy)]
+pub struct AtlasRegion {
+    pub page: usize,
+    pub rect: Rect,
+}
+
+/// Regions of a sprite sheet's frames, keyed by their rect in the original texture.
+pub struct SheetRegions {
+    frames: HashMap<AsepriteRect, RegionId>,
+}
+
+#[derive(Clone, Copy)]
+struct PackRect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+impl PackRect {
+    fn right(&self) -> u32 {
+        self.x + self.w
+    }
+
+    fn bottom(&self) -> u32 {
+        self.y + self.h
+    }
+
+    fn intersects(&self, other: &PackRect) -> bool {
+        self.x < other.right() && other.x < self.right() && self.y < other.bottom() && other.y < self.bottom()
+    }
+
+    fn contains(&self, other: &PackRect) -> bool {
+        self.x <= other.x && self.y <= other.y && self.right() >= other.right() && self.bottom() >= other.bottom()
+    }
+}
+
+/// Free space of one page, kept as the maximal empty rectangles (MaxRects).
+#[derive(Clone)]
+struct PageSpace {
+    free: Vec<PackRect>,
+}
+
+impl PageSpace {
+    fn new(size: u32) -> Self {
+        PageSpace {
+            free: vec![PackRect {
+                x: 0,
+                y: 0,
+                w: size,
+                h: size,
+            }],
+        }
+    }
+
+    /// Best short side fit: the free rect that leaves the least room on its
+    /// tighter side.
+    fn find(&self, w: u32, h: u32) -> Option<(u32, u32)> {
+        self.free
+            .iter()
+            .filter(|free| free.w >= w && free.h >= h)
+            .min_by_key(|free| {
+                let (dw, dh) = (free.w - w, free.h - h);
+                (dw.min(dh), dw.max(dh))
+            })
+            .map(|free| (free.x, free.y))
+    }
+
+    /// The lowest free position with equal x and y.
+    fn find_diagonal(&self, w: u32, h: u32) -> Option<(u32, u32)> {
+        self.free
+            .iter()
+            .filter_map(|free| {
+                let d = free.x.max(free.y);
+                (d + w <= free.right() && d + h <= free.bottom()).then_some(d)
+            })
+            .min()
+            .map(|d| (d, d))
+    }
+
+    fn place(&mut self, used: PackRect) {
+        let mut free = Vec::with_capacity(self.free.len() + 4);
+        for rect in &self.free {
+            if !rect.intersects(&used) {
+                free.push(*rect);
+                continue;
+            }
+            if used.x > rect.x {
+                free.push(PackRect {
+                    w: used.x - rect.x,
+                    ..*rect
+                });
+            }
+            if used.right() < rect.right() {
+                free.push(PackRect {
+                    x: used.right(),
+                    w: rect.right() - used.right(),
+                    ..*rect
+                });
+            }
+            if used.y > rect.y {
+                free.push(PackRect {
+                    h: used.y - rect.y,
+                    ..*rect
+                });
+            }
+            if used.bottom() < rect.bottom() {
+                free.push(PackRect {
+                    y: used.bottom(),
+                    h: rect.bottom() - used.bottom(),
+                    ..*rect
+                });
+            }
+        }
+
+        // Drop rects inside other rects, keeping one of any duplicates
+        self.free = free
+            .iter()
+            .enumerate()
+            .filter(|(i, rect)| {
+                !free.iter().enumerate().any(|(j, other)| {
+                    i != &j && other.contains(rect) && (!rect.contains(other) || j < *i)
+                })
+            })
+            .map(|(_, rect)| *rect)
+            .collect();
+    }
+}
+
+/// An image ready to blit, including its extrusion.
+struct Item {
+    region: RegionId,
+    image: Image,
+    /// Grid blocks must sit at equal x and y, as a tileset's margin applies to both.
+    diagonal: bool,
+}
+
+/// Collects images and packs them into as few atlas pages as possible.
+pub struct AtlasBuilder {
+    options: AtlasOptions,
+    groups: Vec<Vec<Item>>,
+    region_count: usize,
+}
+
+impl AtlasBuilder {
+    pub fn new(options: AtlasOptions) -> Self {
+        AtlasBuilder {
+            options,
+            groups: Vec::new(),
+            region_count: 0,
+        }
+    }
+
+    pub fn add_image(&mut self, image: &Image) -> RegionId {
+        let source = PackRect {
+            x: 0,
+            y: 0,
+            w: image.width as u32,
+            h: image.height as u32,
+        };
+        let item = self.extruded_item(image, source);
+        let region = item.region;
+        self.groups.push(vec![item]);
+        region
+    }
+
+    /// Adds each frame of the sheet, all on one page so the sheet keeps a single
+    /// texture.
+    pub fn add_sprite_sheet(&mut self, sheet: &SpriteSheet) -> SheetRegions {
+        let image = sheet.texture().get_texture_data();
+        let mut frames = HashMap::new();
+        let mut group = Vec::new();
+        for rect in sheet.frame_rects() {
+            if rect.w <= 0 || rect.h <= 0 {
+                continue;
+            }
+            let source = PackRect {
+                x: rect.x as u32,
+                y: rect.y as u32,
+                w: rect.w as u32,
+                h: rect.h as u32,
+            };
+            let item = self.extruded_item(&image, source);
+            frames.insert(rect, item.region);
+            group.push(item);
+        }
+        self.groups.push(group);
+        SheetRegions { frames }
+    }
+
+    /// Adds a Tiled tileset as one image, extruded around its outside. Its tiles
+    /// keep their layout, margin and spacing, so tile ids and source rects
+    /// spanning several tiles stay valid once offset by the region's corner.
+    pub fn add_tileset(&mut self, tileset: &TileSet) -> RegionId {
+        let image = tileset.texture.get_texture_data();
+        let source = PackRect {
+            x: 0,
+            y: 0,
+            w: image.width as u32,
+            h: image.height as u32,
+        };
+        let region = self.next_region();
+        self.groups.push(vec![Item {
+            region,
+            image: extrude_image(&image, source, self.options.extrude),
+            diagonal: true,
+        }]);
+        region
+    }
+
+    /// Packs everything added so far into pages, placing each group on the first
+    /// page it fits on.
+    pub fn build(self) -> Result<Atlas, AtlasError> {
+        let AtlasOptions {
+            page_size, padding, ..
+        } = self.options;
+        let mut spaces: Vec<PageSpace> = Vec::new();
+        let mut images: Vec<Image> = Vec::new();
+        let mut regions = vec![
+            AtlasRegion {
+                page: 0,
+                rect: Rect::default(),
+            };
+            self.region_count
+        ];
+
+        for mut group in self.groups {
+            for item in &group {
+                let (width, height) = (item.image.width as u32, item.image.height as u32);
+                if width + padding > page_size || height + padding > page_size {
+                    return Err(AtlasError::ImageTooLarge {
+                        width,
+                        height,
+                        page_size,
+                    });
+                }
+            }
+            // Tileset blocks first while the diagonal is free, then tallest first
+            group.sort_by_key(|item| (!item.diagonal, std::cmp::Reverse(item.image.height)));
+
+            let pack = |space: &PageSpace| {
+                let mut space = space.clone();
+                let mut positions = Vec::with_capacity(group.len());
+                for item in &group {
+                    let (w, h) = (item.image.width as u32 + padding, item.image.height as u32 + padding);
+                    let (x, y) = if item.diagonal {
+                        space.find_diagonal(w, h)?
+                    } else {
+                        space.find(w, h)?
+                    };
+                    space.place(PackRect { x, y, w, h });
+                    positions.push((x, y));
+                }
+                Some((space, positions))
+            };
+
+            let (page, (space, positions)) = match spaces.iter().enumerate().find_map(|(page, space)| Some((page, pack(space)?))) {
+                Some(packed) => packed,
+                None => {
+                    let packed = pack(&PageSpace::new(page_size)).ok_or(AtlasError::GroupTooLarge {
+                        images: group.len(),
+                        page_size,
+                    })?;
+                    spaces.push(PageSpace::new(page_size));
+                    images.push(Image::gen_image_color(page_size as u16, page_size as u16, BLANK));
+                    (spaces.len() - 1, packed)
+                }
+            };
+            spaces[page] = space;
+
+            let extrude = self.options.extrude as f32;
+            for (item, (x, y)) in group.iter().zip(positions) {
+                blit(&mut images[page], &item.image, x, y);
+                regions[item.region.0] = AtlasRegion {
+                    page,
+                    rect: Rect::new(
+                        x as f32 + extrude,
+                        y as f32 + extrude,
+                        item.image.width as f32 - 2.0 * extrude,
+                        item.image.height as f32 - 2.0 * extrude,
+                    ),
+                };
+            }
+        }
+
+        let pages = images
+            .iter()
+            .map(|image| {
+                let texture = Texture2D::from_image(image);
+                texture.set_filter(FilterMode::Nearest);
+                texture
+            })
+            .collect();
+        Ok(Atlas { pages, regions })
+    }
+
+    fn next_region(&mut self) -> RegionId {
+        self.region_count += 1;
+        RegionId(self.region_count - 1)
+    }
+
+    fn extruded_item(&mut self, image: &Image, source: PackRect) -> Item {
+        Item {
+            region: self.next_region(),
+            image: extrude_image(image, source, self.options.extrude),
+            diagonal: false,
+        }
+    }
+}
+
+/// Packed pages plus where each added image went.
+pub struct Atlas {
+    pages: Vec<Texture2D>,
+    regions: Vec<AtlasRegion>,
+}
+
+impl Atlas {
+    pub fn pages(&self) -> &[Texture2D] {
+        &self.pages
+    }
+
+    pub fn region(&self, id: RegionId) -> AtlasRegion {
+        self.regions[id.0]
+    }
+
+    pub fn texture(&self, id: RegionId) -> &Texture2D {
+        &self.pages[self.regions[id.0].page]
+    }
+
+    /// Points the sheet at its frames on the atlas page.
+    pub fn apply_to_sprite_sheet(&self, regions: &SheetRegions, sheet: &mut SpriteSheet) {
+        let Some(page) = regions.frames.values().next().map(|id| self.regions[id.0].page) else {
+            return;
+        };
+        sheet.relocate_frames(self.pages[page].clone(), |rect| {
+            regions.frames.get(rect).map(|id| self.regions[id.0].rect.point())
+        });
+    }
+
+    /// Points the tileset at its packed tiles, so `Map::spr` and `draw_tiles` keep
+    /// working with the same tile ids.
+    pub fn apply_to_tileset(&self, id: RegionId, tileset: &mut TileSet) {
+        let region = self.regions[id.0];
+        tileset.texture = self.pages[region.page].clone();
+        // The block sits on the diagonal, so one margin offsets both axes
+        tileset.margin += region.rect.x as i32;
+    }
+
+    /// Moves a source rect in the tileset's original image, e.g. for `Map::spr_ex`,
+    /// to the same pixels on its atlas page.
+    pub fn tileset_rect(&self, id: RegionId, source: Rect) -> Rect {
+        source.offset(self.regions[id.0].rect.point())
+    }
+}
+
+/// Copies `source` out of `image` with its edge pixels repeated `extrude` times
+/// on every side. Whatever of `source` lies outside the image repeats the
+/// image's edge instead, and an empty image gives a transparent one.
+fn extrude_image(image: &Image, source: PackRect, extrude: u32) -> Image {
+    let width = source.w + 2 * extrude;
+    let height = source.h + 2 * extrude;
+    let mut result = Image::gen_image_color(width as u16, height as u16, BLANK);
+    let (image_width, image_height) = (image.width as u32, image.height as u32);
+    if image_width == 0 || image_height == 0 || source.w == 0 || source.h == 0 {
+        return result;
+    }
+    for y in 0..height {
+        let source_y = (source.y + y.saturating_sub(extrude).min(source.h - 1)).min(image_height - 1);
+        for x in 0..width {
+            let source_x = (source.x + x.saturating_sub(extrude).min(source.w - 1)).min(image_width - 1);
+            let from = ((source_y * image_width + source_x) * 4) as usize;
+            let to = ((y * width + x) * 4) as usize;
+            result.bytes[to..to + 4].copy_from_slice(&image.bytes[from..from + 4]);
+        }
+    }
+    result
+}
+
+fn blit(dest: &mut Image, source: &Image, x: u32, y: u32) {
+    let dest_width = dest.width as usize;
+    let row_bytes = source.width as usize * 4;
+    for row in 0..source.height as usize {
+        let from = row * row_bytes;
+        let to = ((y as usize + row) * dest_width + x as usize) * 4;
+        dest.bytes[to..to + row_bytes].copy_from_slice(&source.bytes[from..from + row_bytes]);
+    }
+}
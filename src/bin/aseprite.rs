@@ -0,0 +1,107 @@
+use macroquad::prelude::*;
+use macroquad_test::aseprite::{AnimationPlayer, SpriteDrawParams};
+use macroquad_test::assets::Assets;
+use macroquad_test::atlas::AtlasOptions;
+use macroquad_test::picking::SpriteMask;
+
+const CROWD_SIZE: usize = 200;
+
+const LAYER_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+#[macroquad::main("Aseprite Animation")]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut assets = Assets::new();
+    let skeletron = assets.sprite_sheet("assets/skeletron.json");
+    assets.load_all().await?;
+    assets.pack_atlas(AtlasOptions::default())?;
+    let sheet = assets.try_get(skeletron)?.clone();
+    let mask = SpriteMask::new(&sheet);
+
+    let mut animation = AnimationPlayer::new(sheet.clone());
+    animation.add_event_listener(|event| info!("animation event {} on frame {}", event.name, event.frame));
+
+    // A crowd of players sharing the one sheet, each with its own speed and start frame
+    let mut crowd: Vec<(Vec2, AnimationPlayer)> = (0..CROWD_SIZE)
+        .map(|_| {
+            let mut player = AnimationPlayer::new(sheet.clone());
+            player.set_speed(rand::gen_range(0.5, 2.0));
+            player.seek(rand::gen_range(0, sheet.frame_count()));
+            let position = vec2(rand::gen_range(0.0, screen_width()), rand::gen_range(0.0, screen_height()));
+            (position, player)
+        })
+        .collect();
+
+    loop {
+        clear_background(Color::new(0.1, 0.2, 0.3, 1.0));
+
+        // Number keys toggle the matching layer
+        for (index, key) in LAYER_KEYS.iter().enumerate() {
+            if is_key_pressed(*key) {
+                if let Some(name) = animation.layers().get(index).map(|layer| layer.name.clone()) {
+                    let visible = animation.layer_state(&name).is_some_and(|state| state.visible);
+                    animation.set_layer_visible(&name, !visible);
+                }
+            }
+        }
+        if is_key_pressed(KeyCode::P) {
+            animation.set_paused(!animation.is_paused());
+        }
+        if is_key_pressed(KeyCode::Up) {
+            animation.set_speed(animation.speed() * 2.0);
+        }
+        if is_key_pressed(KeyCode::Down) {
+            animation.set_speed(animation.speed() / 2.0);
+        }
+        if is_key_pressed(KeyCode::Right) {
+            animation.seek(animation.current_frame() + 1);
+        }
+        if is_key_pressed(KeyCode::Left) {
+            animation.seek(animation.current_frame().saturating_sub(1));
+        }
+
+        for (position, player) in &mut crowd {
+            player.update(get_frame_time());
+            player.draw(*position, &SpriteDrawParams::default());
+        }
+
+        animation.update(get_frame_time());
+        let mut params = SpriteDrawParams {
+            scale: Vec2::splat(10.0),
+            anchor: vec2(0.5, 0.5),
+            flip_x: is_key_down(KeyCode::F),
+            rotation: if is_key_down(KeyCode::R) { get_time() as f32 } else { 0.0 },
+            ..Default::default()
+        };
+        // Highlight the sprite only while the cursor is over one of its opaque pixels
+        let world = Mat3::from_translation(vec2(screen_width() / 2.0, screen_height() / 2.0));
+        if mask.contains(&animation, &params, world, Vec2::from(mouse_position())) {
+            params.tint = YELLOW;
+        }
+        animation.draw_transformed(world, &params);
+
+        for (index, layer) in animation.layers().iter().enumerate() {
+            let visible = animation.layer_state(&layer.name).is_some_and(|state| state.visible);
+            let label = format!("{}: {} ({})", index + 1, layer.name, if visible { "on" } else { "off" });
+            draw_text(&label, 20.0, 30.0 + index as f32 * 20.0, 20.0, WHITE);
+        }
+        let status = format!(
+            "frame {} speed {:.2}x{}",
+            animation.current_frame(),
+            animation.speed(),
+            if animation.is_paused() { " (paused)" } else { "" }
+        );
+        draw_text(&status, 20.0, screen_height() - 20.0, 20.0, WHITE);
+
+        next_frame().await;
+    }
+}
@@ -0,0 +1,201 @@
+use macroquad::prelude::*;
+use macroquad_test::gizmo::{Gizmo, GizmoEvent, GizmoMode, Snapping};
+use macroquad_test::history::{Command, History};
+use macroquad_test::picking::{HitShape, PickEvent, Pickable, Picker, Transformed, ZOrder};
+use macroquad_test::transform::{
+    draw_circle_transformed, draw_rectangle_transformed, draw_triangle_transformed, Transform,
+};
+
+/// A shape around its local origin, placed by its transform.
+struct Item {
+    shape: HitShape,
+    transform: Transform,
+    color: Color,
+}
+
+impl Item {
+    fn draw(&self, hovered: bool) {
+        let color = Color {
+            a: if hovered { 0.7 } else { 1.0 },
+            ..self.color
+        };
+        let matrix = self.transform.to_matrix();
+        match &self.shape {
+            HitShape::Rect(rect) => draw_rectangle_transformed(rect.x, rect.y, rect.w, rect.h, color, matrix),
+            HitShape::Circle { center, radius } => draw_circle_transformed(center.x, center.y, *radius, color, matrix),
+            // Fanned from the local origin, which the star's points surround
+            HitShape::Polygon(points) => {
+                for (index, point) in points.iter().enumerate() {
+                    let next = points[(index + 1) % points.len()];
+                    draw_triangle_transformed(Vec2::ZERO, *point, next, color, matrix);
+                }
+            }
+        }
+    }
+
+    fn pick(&self, point: Vec2) -> Option<Vec2> {
+        Transformed(self.shape.clone(), self.transform.to_matrix()).pick(point)
+    }
+}
+
+/// Changing an item's transform, for undo.
+struct SetTransform {
+    index: usize,
+    before: Transform,
+    after: Transform,
+}
+
+impl Command<[Item; 4]> for SetTransform {
+    fn apply(&mut self, items: &mut [Item; 4]) {
+        items[self.index].transform = self.after;
+    }
+
+    fn revert(&mut self, items: &mut [Item; 4]) {
+        items[self.index].transform = self.before;
+    }
+}
+
+#[macroquad::main("Interactive Shapes")]
+async fn main() {
+    let square = HitShape::Rect(Rect::new(-50.0, -50.0, 100.0, 100.0));
+    let mut items = [
+        Item {
+            shape: square.clone(),
+            transform: Transform::new(vec2(150.0, 150.0), 0.0, Vec2::ONE),
+            color: BLUE,
+        },
+        Item {
+            shape: square,
+            transform: Transform::new(vec2(300.0, 150.0), 0.0, Vec2::ONE),
+            color: RED,
+        },
+        Item {
+            shape: HitShape::Circle {
+                center: Vec2::ZERO,
+                radius: 50.0,
+            },
+            transform: Transform::new(vec2(450.0, 150.0), 0.0, Vec2::ONE),
+            color: GREEN,
+        },
+        Item {
+            // A concave star, picked only inside its points
+            shape: HitShape::Polygon(vec![
+                vec2(0.0, -60.0),
+                vec2(20.0, -20.0),
+                vec2(60.0, 0.0),
+                vec2(20.0, 20.0),
+                vec2(0.0, 60.0),
+                vec2(-20.0, 20.0),
+                vec2(-60.0, 0.0),
+                vec2(-20.0, -20.0),
+            ]),
+            transform: Transform::new(vec2(300.0, 300.0), 0.3, Vec2::ONE),
+            color: ORANGE,
+        },
+    ];
+    let mut z_order = ZOrder::new();
+    for index in 0..items.len() {
+        z_order.push(index);
+    }
+    let mut picker = Picker::new();
+    let mut gizmo = Gizmo::new();
+    let mut selected: Option<usize> = None;
+    let mut history = History::new(100);
+    // The dragged item's transform when it was grabbed
+    let mut grabbed: Option<Transform> = None;
+
+    loop {
+        clear_background(WHITE);
+
+        history.handle_shortcuts(&mut items);
+
+        if is_key_pressed(KeyCode::W) {
+            gizmo.mode = GizmoMode::Translate;
+        }
+        if is_key_pressed(KeyCode::E) {
+            gizmo.mode = GizmoMode::Rotate;
+        }
+        if is_key_pressed(KeyCode::R) {
+            gizmo.mode = GizmoMode::Scale;
+        }
+        if is_key_pressed(KeyCode::G) {
+            gizmo.snapping = if gizmo.snapping == Snapping::default() {
+                Snapping::enabled()
+            } else {
+                Snapping::default()
+            };
+        }
+
+        let mouse = Vec2::from(mouse_position());
+        // The selection's gizmo takes the pointer before the shapes below it,
+        // unless a shape is already being dragged
+        if let (Some(index), None) = (selected, picker.drag()) {
+            let item = &mut items[index];
+            if let Some(GizmoEvent::End { before }) = gizmo.update(&mut item.transform, Mat3::IDENTITY, mouse) {
+                if before != item.transform {
+                    history.record(SetTransform {
+                        index,
+                        before,
+                        after: item.transform,
+                    });
+                }
+            }
+        }
+        if picker.drag().is_none() && gizmo.wants_pointer() {
+            picker.update(mouse, |_| None);
+        } else {
+            if is_mouse_button_pressed(MouseButton::Left) && z_order.pick(|index| items[index].pick(mouse)).is_none() {
+                selected = None;
+                gizmo.cancel();
+            }
+            for event in picker.update(mouse, |point| z_order.pick(|index| items[index].pick(point))) {
+                match *event {
+                    PickEvent::DragStart { id, .. } => {
+                        z_order.raise(id);
+                        selected = Some(id);
+                        grabbed = Some(items[id].transform);
+                    }
+                    PickEvent::DragMove { id, delta, .. } => {
+                        items[id].transform.position += delta;
+                    }
+                    // Each drag is one undo step, covering the whole distance
+                    PickEvent::DragEnd { id, .. } => {
+                        if let Some(before) = grabbed.take().filter(|before| *before != items[id].transform) {
+                            history.record(SetTransform {
+                                index: id,
+                                before,
+                                after: items[id].transform,
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for index in z_order.iter() {
+            items[index].draw(picker.hovered() == Some(index));
+        }
+        if let Some(index) = selected {
+            gizmo.draw(&items[index].transform, Mat3::IDENTITY);
+        }
+
+        draw_text(
+            "Hover over shapes and drag them! Ctrl+Z to undo",
+            20.0,
+            20.0,
+            30.0,
+            BLACK,
+        );
+        let snapping = if gizmo.snapping == Snapping::default() { "off" } else { "on" };
+        draw_text(
+            &format!("W move, E rotate, R scale the selection; G snapping ({})", snapping),
+            20.0,
+            45.0,
+            20.0,
+            DARKGRAY,
+        );
+
+        next_frame().await
+    }
+}
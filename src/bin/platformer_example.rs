@@ -0,0 +1,234 @@
+use macroquad::prelude::*;
+use macroquad_platformer::*;
+use macroquad_test::assets::Assets;
+use macroquad_test::atlas::AtlasOptions;
+use macroquad_test::editor::LevelEditor;
+use macroquad_test::level::{Level, LevelObject};
+use macroquad_test::postprocess::{Effect, PostProcess};
+use macroquad_tiled as tiled;
+
+const MAP_PATH: &str = "assets/map.json";
+
+/// Keys toggling post-processing passes.
+const EFFECT_KEYS: [(KeyCode, Effect); 5] = [
+    (KeyCode::Key1, Effect::Crt),
+    (KeyCode::Key2, Effect::Bloom),
+    (KeyCode::Key3, Effect::Vignette),
+    (KeyCode::Key4, Effect::Palette),
+    (KeyCode::Key5, Effect::Pixelate),
+];
+
+struct Player {
+    collider: Actor,
+    speed: Vec2,
+}
+
+struct Platform {
+    collider: Solid,
+    speed: f32,
+    /// Where it turns around.
+    left: f32,
+    right: f32,
+}
+
+/// Shows a message while the player is inside.
+struct Trigger {
+    rect: Rect,
+    message: String,
+}
+
+struct Game {
+    world: World,
+    player: Player,
+    platforms: Vec<Platform>,
+    triggers: Vec<Trigger>,
+}
+
+/// Sets the level up from the solid tiles on "main layer" and the objects on
+/// "objects", both of which the editor changes.
+fn build(level: &Level, tiled_map: &tiled::Map) -> Game {
+    let mut static_colliders = vec![];
+    for (_x, _y, tile) in tiled_map.tiles("main layer", None) {
+        static_colliders.push(if tile.is_some() {
+            Tile::Solid
+        } else {
+            Tile::Empty
+        });
+    }
+
+    let mut world = World::new();
+    world.add_static_tiled_layer(static_colliders, 8., 8., level.width as usize, 1);
+
+    let objects = level.objects("objects");
+    let number = |object: &LevelObject, name: &str, default: f32| {
+        object.property(name).and_then(|value| value.parse().ok()).unwrap_or(default)
+    };
+    let spawn = objects
+        .iter()
+        .find(|object| object.kind == "spawn")
+        .map_or(vec2(50.0, 80.0), |object| vec2(object.x, object.y));
+    let player = Player {
+        collider: world.add_actor(spawn, 8, 8),
+        speed: vec2(0., 0.),
+    };
+    let platforms = objects
+        .iter()
+        .filter(|object| object.kind == "platform")
+        .map(|object| Platform {
+            collider: world.add_solid(vec2(object.x, object.y), 32, 8),
+            speed: number(object, "speed", 50.),
+            left: number(object, "left", object.x - 20.),
+            right: number(object, "right", object.x + 50.),
+        })
+        .collect();
+    let triggers = objects
+        .iter()
+        .filter(|object| object.kind == "trigger")
+        .map(|object| Trigger {
+            rect: object.rect(),
+            message: object.property("message").unwrap_or_default(),
+        })
+        .collect();
+
+    Game {
+        world,
+        player,
+        platforms,
+        triggers,
+    }
+}
+
+#[macroquad::main("Platformer")]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut assets = Assets::new();
+    let map = assets.tiled_map(MAP_PATH);
+    assets.load_all().await?;
+    assets.pack_atlas(AtlasOptions::default())?;
+
+    // F2 switches between playing and editing; leaving the editor restarts the
+    // level as edited
+    let mut editor = LevelEditor::new(Level::load(MAP_PATH).await?, MAP_PATH);
+    let mut editing = false;
+    let mut game = build(editor.level(), assets.try_get(map)?);
+
+    // The level is drawn at its native 320x152 and upscaled by whole multiples
+    let view = Rect::new(0.0, 0.0, 320.0, 152.0);
+    let mut post_process = PostProcess::load("assets/platformer_postprocess.json").await?;
+
+    loop {
+        if !editor.is_typing() {
+            for (key, effect) in EFFECT_KEYS {
+                if is_key_pressed(key) {
+                    post_process.toggle(effect);
+                }
+            }
+            if is_key_pressed(KeyCode::F2) {
+                editing = !editing;
+                if !editing {
+                    game = build(editor.level(), assets.try_get(map)?);
+                }
+            }
+        }
+        if editing {
+            let mouse = post_process.screen_to_view(Vec2::from(mouse_position()), view);
+            if let Some(tiled_map) = assets.get_mut(map) {
+                editor.update(tiled_map, mouse);
+            }
+        }
+
+        let tiled_map = assets.try_get(map)?;
+        let Game {
+            world,
+            player,
+            platforms,
+            triggers,
+        } = &mut game;
+
+        post_process.begin(view);
+        clear_background(BLACK);
+
+        for layer in editor.level().layers.iter().filter(|layer| layer.data.is_some()) {
+            tiled_map.draw_tiles(&layer.name, Rect::new(0.0, 0.0, 320.0, 152.0), None);
+        }
+
+        // draw platform
+        let platform_source = assets.tileset_rect(map, "tileset", Rect::new(6.0 * 8.0, 0.0, 32.0, 8.0));
+        for platform in platforms.iter() {
+            let pos = world.solid_pos(platform.collider);
+            tiled_map.spr_ex("tileset", platform_source, Rect::new(pos.x, pos.y, 32.0, 8.0));
+        }
+
+        // draw player
+        {
+            // sprite id from tiled
+            const PLAYER_SPRITE: u32 = 120;
+
+            let pos = world.actor_pos(player.collider);
+            if player.speed.x >= 0.0 {
+                tiled_map.spr("tileset", PLAYER_SPRITE, Rect::new(pos.x, pos.y, 8.0, 8.0));
+            } else {
+                tiled_map.spr(
+                    "tileset",
+                    PLAYER_SPRITE,
+                    Rect::new(pos.x + 8.0, pos.y, -8.0, 8.0),
+                );
+            }
+        }
+
+        // The game stands still while it's edited
+        if editing {
+            post_process.end();
+            editor.draw(tiled_map, |point| post_process.view_to_screen(point, view));
+            next_frame().await;
+            continue;
+        }
+
+        // player movement control
+        {
+            let pos = world.actor_pos(player.collider);
+            let on_ground = world.collide_check(player.collider, pos + vec2(0., 1.));
+
+            if !on_ground {
+                player.speed.y += 500. * get_frame_time();
+            }
+
+            if is_key_down(KeyCode::Right) {
+                player.speed.x = 100.0;
+            } else if is_key_down(KeyCode::Left) {
+                player.speed.x = -100.0;
+            } else {
+                player.speed.x = 0.;
+            }
+
+            if is_key_pressed(KeyCode::Space) && on_ground {
+                player.speed.y = -120.;
+            }
+
+            world.move_h(player.collider, player.speed.x * get_frame_time());
+            world.move_v(player.collider, player.speed.y * get_frame_time());
+        }
+
+        // platform movement
+        for platform in platforms.iter_mut() {
+            world.solid_move(platform.collider, platform.speed * get_frame_time(), 0.0);
+            let pos = world.solid_pos(platform.collider);
+            if platform.speed > 1. && pos.x >= platform.right {
+                platform.speed *= -1.;
+            }
+            if platform.speed < -1. && pos.x <= platform.left {
+                platform.speed *= -1.;
+            }
+        }
+
+        post_process.end();
+
+        let pos = world.actor_pos(player.collider);
+        let player_rect = Rect::new(pos.x, pos.y, 8.0, 8.0);
+        for trigger in triggers.iter().filter(|trigger| trigger.rect.overlaps(&player_rect)) {
+            draw_text(&trigger.message, 20.0, screen_height() - 30.0, 30.0, WHITE);
+        }
+        draw_text("F2 to edit the level", 20.0, 30.0, 20.0, GRAY);
+
+        next_frame().await
+    }
+}
@@ -0,0 +1,283 @@
+use macroquad::prelude::*;
+use macroquad_test::gizmo::{Gizmo, GizmoEvent, GizmoMode, Snapping};
+use macroquad_test::history::{History, SceneCommand};
+use macroquad_test::material::MaterialRegistry;
+use macroquad_test::picking::{to_local, HitShape, PickEvent, Picker};
+use macroquad_test::scene::{NodeId, SceneGraph};
+use macroquad_test::transform::{
+    draw_circle_lines_transformed, draw_circle_transformed, draw_line_transformed, draw_rectangle_transformed,
+    draw_text_transformed, Transform,
+};
+use macroquad_test::tween::{Ease, Motion, Timeline, Tweens};
+
+#[derive(Clone, Copy)]
+enum Shape {
+    Rectangle(Color),
+    Ellipse(Color),
+    Label(&'static str),
+}
+
+impl Shape {
+    fn draw(&self, matrix: Mat3, hovered: bool) {
+        let alpha = if hovered { 0.7 } else { 1.0 };
+        match self {
+            Shape::Rectangle(color) => {
+                draw_rectangle_transformed(-50.0, -50.0, 100.0, 100.0, Color { a: alpha, ..*color }, matrix)
+            }
+            Shape::Ellipse(color) => {
+                draw_circle_transformed(0.0, 0.0, 50.0, Color { a: alpha, ..*color }, matrix);
+                draw_circle_lines_transformed(0.0, 0.0, 50.0, 4.0, BLACK, matrix);
+                draw_line_transformed(0.0, 0.0, 50.0, 0.0, 4.0, BLACK, matrix);
+            }
+            Shape::Label(text) => {
+                draw_text_transformed(text, -40.0, 0.0, 32.0, BLACK, matrix);
+            }
+        }
+    }
+
+    fn hit_shape(&self) -> Option<HitShape> {
+        match self {
+            Shape::Rectangle(_) => Some(HitShape::Rect(Rect::new(-50.0, -50.0, 100.0, 100.0))),
+            Shape::Ellipse(_) => Some(HitShape::Circle {
+                center: Vec2::ZERO,
+                radius: 50.0,
+            }),
+            Shape::Label(_) => None,
+        }
+    }
+}
+
+/// The topmost shape under `point`, with the point in that node's local space.
+fn pick(scene: &SceneGraph<Shape>, point: Vec2) -> Option<(NodeId, Vec2)> {
+    scene.pick(point, |_, shape, local| shape.hit_shape().is_some_and(|hit| hit.contains_local(local)))
+}
+
+/// Moves `id` so that `grab`, a point in its local space, sits under `point`.
+fn drag_to(scene: &mut SceneGraph<Shape>, id: NodeId, grab: Vec2, point: Vec2) {
+    let parent_world = scene
+        .parent(id)
+        .and_then(|parent| scene.world_matrix(parent))
+        .unwrap_or(Mat3::IDENTITY);
+    let Some(target) = to_local(parent_world, point) else {
+        return;
+    };
+    scene.update_transform(id, |transform| {
+        let offset = Mat3::from_scale_angle_translation(transform.scale, transform.rotation, Vec2::ZERO)
+            .transform_vector2(grab);
+        transform.position = target - offset;
+    });
+}
+
+/// A node's local transform and its parent's world matrix, as the gizmo edits them.
+fn edit_frame(scene: &SceneGraph<Shape>, id: NodeId) -> Option<(Transform, Mat3)> {
+    let parent = scene
+        .parent(id)
+        .and_then(|parent| scene.world_matrix(parent))
+        .unwrap_or(Mat3::IDENTITY);
+    Some((scene.transform(id)?, parent))
+}
+
+const PALETTE: [Color; 7] = [RED, ORANGE, GOLD, GREEN, SKYBLUE, BLUE, WHITE];
+
+/// Edit mode: gizmo editing of the selected node, with undo.
+struct Editor {
+    gizmo: Gizmo,
+    selected: Option<NodeId>,
+    history: History<SceneGraph<Shape>>,
+}
+
+impl Editor {
+    /// Clicks away from the gizmo select another node.
+    fn update(&mut self, scene: &mut SceneGraph<Shape>, mouse: Vec2) {
+        let gizmo = &mut self.gizmo;
+        if is_key_pressed(KeyCode::W) {
+            gizmo.mode = GizmoMode::Translate;
+        }
+        if is_key_pressed(KeyCode::E) {
+            gizmo.mode = GizmoMode::Rotate;
+        }
+        if is_key_pressed(KeyCode::R) {
+            gizmo.mode = GizmoMode::Scale;
+        }
+        if is_key_pressed(KeyCode::G) {
+            gizmo.snapping = if gizmo.snapping == Snapping::default() {
+                Snapping::enabled()
+            } else {
+                Snapping::default()
+            };
+        }
+
+        if let Some(id) = self.selected {
+            if let Some((mut transform, parent)) = edit_frame(scene, id) {
+                match gizmo.update(&mut transform, parent, mouse) {
+                    Some(GizmoEvent::Change) => {
+                        scene.set_transform(id, transform);
+                    }
+                    // The whole drag is one undo step
+                    Some(GizmoEvent::End { before }) => {
+                        self.history.begin_group();
+                        for command in SceneCommand::transform(id, before, transform) {
+                            self.history.record(command);
+                        }
+                        self.history.end_group();
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if is_mouse_button_pressed(MouseButton::Left) && !gizmo.wants_pointer() {
+            self.selected = pick(scene, mouse).map(|(id, _)| id);
+            gizmo.cancel();
+        }
+
+        // N adds a square under the selection, or at the top level
+        if is_key_pressed(KeyCode::N) {
+            let parent = self.selected.and_then(|id| scene.world_matrix(id)).unwrap_or(Mat3::IDENTITY);
+            let local = Transform::new(to_local(parent, mouse).unwrap_or(mouse), 0.0, Vec2::ONE * 0.5);
+            let id = match self.selected {
+                Some(selected) => scene.add_child(selected, Shape::Rectangle(ORANGE), local),
+                None => Some(scene.add(Shape::Rectangle(ORANGE), local)),
+            };
+            if let Some(id) = id {
+                self.history.record(SceneCommand::create(id));
+                self.selected = Some(id);
+            }
+        }
+        if let Some(id) = self.selected {
+            if is_key_pressed(KeyCode::Delete) || is_key_pressed(KeyCode::Backspace) {
+                self.history.push(SceneCommand::delete(id), scene);
+                self.selected = None;
+                self.gizmo.cancel();
+            } else if is_key_pressed(KeyCode::C) {
+                // Next colour in the palette
+                let recolored = match scene.get(id) {
+                    Some(Shape::Rectangle(color)) => Some(Shape::Rectangle(next_color(*color))),
+                    Some(Shape::Ellipse(color)) => Some(Shape::Ellipse(next_color(*color))),
+                    _ => None,
+                };
+                if let Some(shape) = recolored {
+                    self.history.push(SceneCommand::set_value(id, shape), scene);
+                }
+            }
+        }
+    }
+}
+
+fn next_color(color: Color) -> Color {
+    let index = PALETTE.iter().position(|entry| *entry == color).map_or(0, |index| index + 1);
+    PALETTE[index % PALETTE.len()]
+}
+
+#[macroquad::main("Nested Transform with Shader")]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut scene = SceneGraph::new();
+    let root = scene.add(
+        Shape::Rectangle(RED),
+        Transform::new(vec2(screen_width() * 0.5, screen_height() * 0.5), 0.0, Vec2::ONE),
+    );
+    let child = scene
+        .add_child(root, Shape::Rectangle(WHITE), Transform::new(vec2(100.0, 0.0), 0.0, Vec2::ONE * 0.5))
+        .unwrap();
+    let grandchild = scene
+        .add_child(child, Shape::Rectangle(GREEN), Transform::new(vec2(100.0, 0.0), 0.0, Vec2::ONE * 0.5))
+        .unwrap();
+    // Squashed so the circle draws as an ellipse
+    scene.add_child(grandchild, Shape::Ellipse(BLUE), Transform::new(vec2(0.0, 120.0), 0.0, vec2(1.0, 0.5)));
+    scene.add_child(root, Shape::Label("root"), Transform::new(vec2(0.0, -70.0), 0.0, Vec2::ONE));
+
+    let mut materials = MaterialRegistry::load("assets/materials.json").await?;
+
+    // The spin and the root's colour cycle are keyframed in JSON
+    let mut tweens = Tweens::new();
+    tweens.play(Timeline::load("assets/transform_timeline.json").await?.to_motion());
+    // Tweened nodes, by property prefix, with their resting scale
+    let named = [(root, "root"), (child, "child"), (grandchild, "grandchild")]
+        .map(|(id, name)| (id, name, scene.transform(id).unwrap().scale));
+
+    let mut picker = Picker::new();
+
+    // Tab pauses the animation to edit nodes with the gizmo
+    let mut editing = false;
+    let mut editor = Editor {
+        gizmo: Gizmo::new(),
+        selected: None,
+        history: History::new(100),
+    };
+    // Where the shape being dragged started, for undo
+    let mut drag_start: Option<Transform> = None;
+
+    loop {
+        clear_background(WHITE);
+
+        // Edit the GLSL files while this runs to see the changes
+        materials.update();
+        materials.apply("stars");
+
+        if is_key_pressed(KeyCode::Tab) {
+            editing = !editing;
+            editor.gizmo.cancel();
+        }
+        // Ctrl+Z and Ctrl+Shift+Z, in either mode
+        editor.history.handle_shortcuts(&mut scene);
+        let mouse = Vec2::from(mouse_position());
+        let hovered = if editing {
+            editor.update(&mut scene, mouse);
+            editor.selected
+        } else {
+            // Only local rotations and scales are tweened; children follow their parents
+            tweens.update(get_frame_time());
+            for (id, name, _) in named {
+                scene.update_transform(id, |transform| tweens.apply_transform(name, transform));
+            }
+            if let (Some(Shape::Rectangle(color)), Some(tweened)) = (scene.get_mut(root), tweens.color("root.color")) {
+                *color = tweened;
+            }
+
+            // Click and drag any shape, even mid-spin; the grabbed one comes to the front
+            for event in picker.update(mouse, |point| pick(&scene, point)) {
+                match *event {
+                    PickEvent::DragStart { id, .. } => {
+                        scene.raise_to_front(id);
+                        drag_start = scene.transform(id);
+                    }
+                    // Dropped shapes pop: grow past their size and settle back
+                    PickEvent::DragEnd { id, .. } => {
+                        if let (Some(from), Some(to)) = (drag_start.take(), scene.transform(id)) {
+                            editor.history.record(SceneCommand::Move {
+                                id,
+                                from: from.position,
+                                to: to.position,
+                            });
+                        }
+                        if let Some((_, name, scale)) = named.iter().find(|(named, ..)| *named == id) {
+                            let property = format!("{}.scale", name);
+                            tweens.stop_property(&property);
+                            tweens.play(Motion::tween(&property, *scale, *scale * 1.2, 0.12, Ease::QuadOut).yoyo(Some(2)));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            // Spinning parents move the grabbed point, so follow the cursor every frame
+            if let Some(drag) = picker.drag() {
+                drag_to(&mut scene, drag.id, drag.grab, mouse);
+            }
+            picker.hovered()
+        };
+        scene.draw(|id, shape, matrix| shape.draw(matrix, Some(id) == hovered));
+
+        gl_use_default_material();
+        if let Some((transform, parent)) = editor.selected.filter(|_| editing).and_then(|id| edit_frame(&scene, id)) {
+            editor.gizmo.draw(&transform, parent);
+        }
+        let help = if editing {
+            "Tab: play  W/E/R: move/rotate/scale  G: snapping  N: new  C: colour  Del: delete  Ctrl+(Shift+)Z: undo/redo"
+        } else {
+            "Tab: edit"
+        };
+        draw_text(help, 10.0, screen_height() - 10.0, 20.0, BLACK);
+        materials.draw_errors();
+
+        next_frame().await
+    }
+}
@@ -0,0 +1,756 @@
+use macroquad::prelude::*;
+use macroquad_test::flowfield::FlowFields;
+use macroquad_test::formation::{assign_slots, Formation};
+use macroquad_test::orders::{Order, OrderCommand, OrderQueue, UnitId};
+use macroquad_test::pathfinding::{Grid, PathOptions, PathQueue, PathRequestId, PathStatus};
+use macroquad_test::spatial::{separate, SpatialHash};
+use macroquad_test::steering::{advance_path, steer, Agent, SteeringProfile, SteeringWeights, Target};
+
+const RECT_SIZE: f32 = 20.0;
+/// The most overlapping units are pushed apart per frame.
+const COLLISION_PUSH: f32 = 5.0;
+const CELL_SIZE: f32 = 20.0;
+/// Groups at least this big share a flow field instead of searching a path each.
+const FLOW_GROUP_SIZE: usize = 4;
+/// Units count as there once this close to their target.
+const ARRIVE_DISTANCE: f32 = 2.0;
+/// Waypoints before the last are passed once this close, so units round
+/// corners instead of stopping at each.
+const WAYPOINT_REACH: f32 = CELL_SIZE / 2.0;
+/// How far units look for neighbours to steer by.
+const NEIGHBOUR_RADIUS: f32 = 60.0;
+const FORMATION_SPACING: f32 = RECT_SIZE * 1.5;
+/// Units fire at the nearest enemy this close, unless told to just move.
+const ATTACK_RANGE: f32 = 90.0;
+const DAMAGE_PER_SECOND: f32 = 20.0;
+const MAX_HEALTH: f32 = 100.0;
+/// Following units stop this close to their leader.
+const FOLLOW_DISTANCE: f32 = RECT_SIZE * 2.0;
+const ENEMY_COUNT: u32 = 10;
+
+/// Paths keep this far from walls, just under half a unit.
+const PATH_OPTIONS: PathOptions = PathOptions {
+    jump_points: true,
+    smooth: true,
+    radius: RECT_SIZE * 0.45,
+};
+
+/// How a unit gets to its target.
+#[derive(Clone)]
+enum Route {
+    /// Waiting for a path search.
+    Searching(PathRequestId),
+    /// Waypoints still to reach, ending at the target.
+    Path(Vec<Vec2>),
+    /// Down the flow field to a group's goal cell, stopping within `spread` of
+    /// the target so the group doesn't pile onto one point.
+    Flow { goal: IVec2, spread: f32 },
+}
+
+/// Unit types, each steering its own way.
+#[derive(Clone, Copy, PartialEq)]
+enum UnitKind {
+    /// Quick, and keeps together with nearby scouts.
+    Scout,
+    /// Slow to speed up and turn, and keeps its distance.
+    Tank,
+}
+
+impl UnitKind {
+    fn profile(self) -> SteeringProfile {
+        let base = SteeringProfile {
+            radius: PATH_OPTIONS.radius,
+            neighbour_radius: NEIGHBOUR_RADIUS,
+            separation_radius: RECT_SIZE * 1.2,
+            ..SteeringProfile::default()
+        };
+        match self {
+            UnitKind::Scout => SteeringProfile {
+                max_speed: 130.0,
+                max_force: 500.0,
+                weights: SteeringWeights {
+                    separation: 1.2,
+                    alignment: 0.3,
+                    cohesion: 0.2,
+                    ..SteeringWeights::default()
+                },
+                ..base
+            },
+            UnitKind::Tank => SteeringProfile {
+                max_speed: 70.0,
+                max_force: 200.0,
+                slowing_radius: 40.0,
+                weights: SteeringWeights {
+                    separation: 2.0,
+                    ..SteeringWeights::default()
+                },
+                ..base
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Team {
+    Player,
+    Enemy,
+}
+
+/// What units see of each other when carrying out orders.
+struct UnitView {
+    id: UnitId,
+    team: Team,
+    center: Vec2,
+}
+
+#[derive(Clone)]
+struct Rectangle {
+    id: UnitId,
+    pos: Vec2,
+    velocity: Vec2,
+    kind: UnitKind,
+    team: Team,
+    health: f32,
+    color: Color,
+    selected: bool,
+    orders: OrderQueue,
+    /// Whether the current order has set the unit moving yet.
+    order_started: bool,
+    target: Option<Vec2>,
+    route: Option<Route>,
+}
+
+impl Rectangle {
+    fn new(id: UnitId, x: f32, y: f32, kind: UnitKind, team: Team, color: Color) -> Self {
+        Rectangle {
+            id,
+            pos: Vec2::new(x, y),
+            velocity: Vec2::ZERO,
+            kind,
+            team,
+            health: MAX_HEALTH,
+            color,
+            selected: false,
+            orders: OrderQueue::new(),
+            order_started: false,
+            target: None,
+            route: None,
+        }
+    }
+
+    fn center(&self) -> Vec2 {
+        self.pos + RECT_SIZE / 2.0
+    }
+
+    /// Draws the unit, its route and then its queued orders. `units` are
+    /// where followed units are.
+    fn draw(&self, units: &[UnitView]) {
+        let color = if self.selected {
+            Color::new(self.color.r, self.color.g, self.color.b, 0.5)
+        } else {
+            self.color
+        };
+        draw_rectangle(self.pos.x, self.pos.y, RECT_SIZE, RECT_SIZE, color);
+        if self.kind == UnitKind::Tank {
+            draw_rectangle_lines(self.pos.x, self.pos.y, RECT_SIZE, RECT_SIZE, 2.0, BLACK);
+        }
+        let mut from = self.center();
+        let waypoints = match (&self.route, self.target) {
+            (Some(Route::Path(path)), _) => path.clone(),
+            (Some(Route::Flow { .. }), Some(target)) => vec![target],
+            _ => Vec::new(),
+        };
+        for point in waypoints {
+            draw_line(from.x, from.y, point.x, point.y, 1.0, GRAY);
+            from = point;
+        }
+
+        // The route already reaches the current order's first point
+        for (index, order) in self.orders.iter().enumerate() {
+            let (points, marker) = match order {
+                Order::Move { to, .. } => (vec![*to], GRAY),
+                Order::AttackMove { to, .. } => (vec![*to], RED),
+                // Back round to the start of the loop
+                Order::Patrol { points } => (points.iter().chain(points.first()).copied().collect(), BLUE),
+                Order::Follow { unit } => (
+                    units.iter().filter(|other| other.id == *unit).map(|other| other.center).collect(),
+                    GREEN,
+                ),
+                Order::HoldPosition | Order::Stop => (Vec::new(), GRAY),
+            };
+            let skip = usize::from(index == 0 && self.order_started);
+            for point in points.into_iter().skip(skip) {
+                draw_line(from.x, from.y, point.x, point.y, 1.0, GRAY);
+                draw_circle(point.x, point.y, 3.0, marker);
+                from = point;
+            }
+        }
+
+        if self.health < MAX_HEALTH {
+            draw_rectangle(self.pos.x, self.pos.y - 5.0, RECT_SIZE, 3.0, DARKGRAY);
+            draw_rectangle(self.pos.x, self.pos.y - 5.0, RECT_SIZE * self.health / MAX_HEALTH, 3.0, GREEN);
+        }
+    }
+
+    /// Gives the unit an order, straight away unless it's `queued` behind
+    /// others.
+    fn command(&mut self, order: Order, queued: bool, paths: &mut PathQueue) {
+        let replaces = !queued || self.orders.is_empty() || order == Order::Stop;
+        self.orders.issue(order, queued);
+        if replaces {
+            self.halt(paths);
+            self.order_started = false;
+        }
+    }
+
+    /// Carries out the current order, moving on to the next once it's done.
+    /// Returns the enemy to fire at, if any.
+    fn follow_orders(&mut self, units: &[UnitView], grid: &Grid, paths: &mut PathQueue) -> Option<UnitId> {
+        let center = self.center();
+        let enemy = units
+            .iter()
+            .filter(|unit| unit.team != self.team && unit.center.distance(center) <= ATTACK_RANGE)
+            .min_by(|a, b| a.center.distance_squared(center).total_cmp(&b.center.distance_squared(center)))
+            .map(|unit| unit.id);
+        let Some(order) = self.orders.current().cloned() else {
+            // Idle units stand their ground
+            return enemy;
+        };
+        match order {
+            Order::Move { to, spread } => {
+                if self.walk(to, spread, grid, paths) {
+                    self.next_order();
+                }
+                None
+            }
+            // Fights stop the walk until there's nothing left in range
+            Order::AttackMove { .. } | Order::Patrol { .. } if enemy.is_some() => {
+                if self.order_started {
+                    self.halt(paths);
+                    self.order_started = false;
+                }
+                enemy
+            }
+            Order::AttackMove { to, spread } => {
+                if self.walk(to, spread, grid, paths) {
+                    self.next_order();
+                }
+                None
+            }
+            Order::Patrol { points } => {
+                let Some(&next) = points.first() else {
+                    self.next_order();
+                    return None;
+                };
+                if self.walk(next, 0.0, grid, paths) {
+                    match self.orders.current_mut() {
+                        Some(Order::Patrol { points }) if points.len() > 1 => {
+                            points.rotate_left(1);
+                            self.order_started = false;
+                        }
+                        _ => self.next_order(),
+                    }
+                }
+                None
+            }
+            Order::HoldPosition => {
+                if !self.order_started {
+                    self.halt(paths);
+                    self.order_started = true;
+                }
+                enemy
+            }
+            Order::Follow { unit } => {
+                let Some(leader) = units.iter().find(|other| other.id == unit) else {
+                    self.next_order();
+                    return None;
+                };
+                self.order_started = true;
+                if center.distance(leader.center) <= FOLLOW_DISTANCE {
+                    if self.target.is_some() {
+                        self.halt(paths);
+                    }
+                } else if self.target.is_none_or(|target| target.distance(leader.center) > CELL_SIZE) {
+                    // Only re-plan once the leader's moved on a cell
+                    self.move_to(leader.center, grid, paths);
+                }
+                None
+            }
+            Order::Stop => {
+                self.orders.clear();
+                self.halt(paths);
+                None
+            }
+        }
+    }
+
+    /// Sets off to `to` the first time it's called for an order, along a flow
+    /// field if there's a `spread` to stop within. True once there.
+    fn walk(&mut self, to: Vec2, spread: f32, grid: &Grid, paths: &mut PathQueue) -> bool {
+        if self.order_started {
+            return self.target.is_none();
+        }
+        self.order_started = true;
+        if spread > 0.0 {
+            self.halt(paths);
+            self.target = Some(to);
+            self.route = Some(Route::Flow {
+                goal: grid.cell_at(to),
+                spread,
+            });
+        } else {
+            self.move_to(to, grid, paths);
+        }
+        false
+    }
+
+    fn next_order(&mut self) {
+        self.orders.complete();
+        self.order_started = false;
+    }
+
+    /// Steers along the route, stopping on arrival. `neighbours` are the
+    /// nearby units, not including this one.
+    fn update(&mut self, dt: f32, grid: &Grid, flow_fields: &mut FlowFields, neighbours: &[Agent]) {
+        let profile = self.kind.profile();
+        let mut agent = Agent {
+            id: self.id.0,
+            position: self.center(),
+            velocity: self.velocity,
+        };
+        let center = agent.position;
+        let mut arrived = false;
+        let target = match (&mut self.route, self.target) {
+            (Some(Route::Path(path)), _) => {
+                advance_path(center, path, WAYPOINT_REACH);
+                arrived = match path.as_slice() {
+                    [] => true,
+                    [last] => center.distance(*last) <= ARRIVE_DISTANCE,
+                    _ => false,
+                };
+                Target::Path(path)
+            }
+            (Some(Route::Flow { goal, spread }), Some(target)) => {
+                // Straight there once nothing's in the way, otherwise down the field
+                let field = flow_fields.get(grid, *goal);
+                let direction = field.sample(center);
+                arrived = center.distance(target) <= spread.max(ARRIVE_DISTANCE);
+                if field.is_at_goal(center) || grid.line_of_sight(center, target, PATH_OPTIONS.radius) {
+                    Target::Arrive(target)
+                } else if direction == Vec2::ZERO {
+                    arrived = true;
+                    Target::None
+                } else {
+                    Target::Velocity(direction * profile.max_speed)
+                }
+            }
+            _ => Target::None,
+        };
+        let force = steer(&agent, &profile, target, neighbours, Some(grid));
+        if arrived {
+            self.stop();
+        }
+        agent.integrate(force, &profile, dt);
+        self.pos = agent.position - RECT_SIZE / 2.0;
+        self.velocity = agent.velocity;
+    }
+
+    fn stop(&mut self) {
+        self.target = None;
+        self.route = None;
+    }
+
+    /// Stops, cancelling any path search.
+    fn halt(&mut self, paths: &mut PathQueue) {
+        if let Some(Route::Searching(request)) = self.route {
+            paths.cancel(request);
+        }
+        self.stop();
+    }
+
+    /// Asks for a path to `target`, walking nowhere until it's found.
+    fn move_to(&mut self, target: Vec2, grid: &Grid, paths: &mut PathQueue) {
+        if let Some(Route::Searching(request)) = self.route.take() {
+            paths.cancel(request);
+        }
+        self.target = Some(target);
+        self.route = Some(Route::Searching(paths.request(grid, self.center(), target, PATH_OPTIONS)));
+    }
+}
+
+struct SelectionBox {
+    start: Vec2,
+    end: Vec2,
+}
+
+impl SelectionBox {
+    fn new(start: Vec2) -> Self {
+        SelectionBox { start, end: start }
+    }
+
+    fn draw(&self) {
+        let top_left = Vec2::new(self.start.x.min(self.end.x), self.start.y.min(self.end.y));
+        let size = (self.end - self.start).abs();
+        draw_rectangle_lines(top_left.x, top_left.y, size.x, size.y, 2.0, BLUE);
+    }
+
+    fn contains(&self, point: Vec2) -> bool {
+        let top_left = Vec2::new(self.start.x.min(self.end.x), self.start.y.min(self.end.y));
+        let bottom_right = Vec2::new(self.start.x.max(self.end.x), self.start.y.max(self.end.y));
+        point.x >= top_left.x
+            && point.x <= bottom_right.x
+            && point.y >= top_left.y
+            && point.y <= bottom_right.y
+    }
+}
+
+fn avoid_collisions(rectangles: &mut [Rectangle], hash: &mut SpatialHash) {
+    let mut positions: Vec<Vec2> = rectangles.iter().map(|rect| rect.pos).collect();
+    separate(&mut positions, RECT_SIZE, COLLISION_PUSH, hash);
+    for (rect, pos) in rectangles.iter_mut().zip(positions) {
+        rect.pos = pos;
+    }
+}
+
+/// `point`, or the nearest walkable cell's centre if it's in a wall.
+fn off_walls(grid: &Grid, point: Vec2) -> Vec2 {
+    let cell = grid.cell_at(point);
+    grid.nearest_walkable(cell, 5)
+        .map_or(point, |walkable| if walkable == cell { point } else { grid.center(walkable) })
+}
+
+/// Where each of a group, starting at `starts`, should head for an order to
+/// `target`, and how near counts as there. Formations give each unit a slot of
+/// its own; crowds share a flow field, and a few units each search a path.
+fn group_destinations(starts: &[Vec2], target: Vec2, formation: Option<Formation>, grid: &Grid) -> (Vec<Vec2>, f32) {
+    match formation.filter(|_| starts.len() > 1) {
+        Some(formation) => {
+            let centroid = starts.iter().sum::<Vec2>() / starts.len() as f32;
+            let slots: Vec<Vec2> = formation
+                .place(starts.len(), target, target - centroid, FORMATION_SPACING)
+                .into_iter()
+                .map(|slot| off_walls(grid, slot))
+                .collect();
+            let destinations = assign_slots(starts, &slots).into_iter().map(|slot| slots[slot]).collect();
+            (destinations, 0.0)
+        }
+        // Room for the whole group around the target
+        None if starts.len() >= FLOW_GROUP_SIZE => {
+            (vec![target; starts.len()], RECT_SIZE * (starts.len() as f32).sqrt() * 0.75)
+        }
+        None => (vec![target; starts.len()], 0.0),
+    }
+}
+
+/// The screen as a grid, with two walls to path around.
+fn make_grid() -> Grid {
+    let size = (vec2(screen_width(), screen_height()) / CELL_SIZE).ceil();
+    let mut grid = Grid::new(size.x as u32, size.y as u32, Vec2::splat(CELL_SIZE));
+    let (width, height) = (grid.width() as i32, grid.height() as i32);
+    for y in 3..height * 2 / 3 {
+        grid.set_walkable(ivec2(width / 3, y), false);
+    }
+    for y in height / 3..height - 3 {
+        grid.set_walkable(ivec2(width * 2 / 3, y), false);
+    }
+    grid
+}
+
+fn draw_grid(grid: &Grid) {
+    for y in 0..grid.height() as i32 {
+        for x in 0..grid.width() as i32 {
+            if !grid.is_walkable(ivec2(x, y)) {
+                let corner = vec2(x as f32, y as f32) * CELL_SIZE;
+                draw_rectangle(corner.x, corner.y, CELL_SIZE, CELL_SIZE, DARKGRAY);
+            }
+        }
+    }
+}
+
+#[macroquad::main("Advanced Interactive Rectangles")]
+async fn main() {
+    let mut rectangles: Vec<Rectangle> = (0..50)
+        .map(|index| {
+            Rectangle::new(
+                UnitId(index),
+                rand::gen_range(0.0, screen_width() - RECT_SIZE),
+                rand::gen_range(0.0, screen_height() - RECT_SIZE),
+                if index % 3 == 0 { UnitKind::Tank } else { UnitKind::Scout },
+                Team::Player,
+                Color::new(
+                    rand::gen_range(0.0, 1.0),
+                    rand::gen_range(0.0, 1.0),
+                    rand::gen_range(0.0, 1.0),
+                    1.0,
+                ),
+            )
+        })
+        .collect();
+    // Enemies to attack, waiting beyond the far wall
+    rectangles.extend((0..ENEMY_COUNT).map(|index| {
+        Rectangle::new(
+            UnitId(50 + index),
+            rand::gen_range(screen_width() * 0.8, screen_width() - RECT_SIZE),
+            rand::gen_range(0.0, screen_height() - RECT_SIZE),
+            UnitKind::Tank,
+            Team::Enemy,
+            RED,
+        )
+    }));
+
+    let mut hash = SpatialHash::new(RECT_SIZE);
+    avoid_collisions(&mut rectangles, &mut hash); // Initial collision avoidance
+
+    let mut grid = make_grid();
+    let mut grid_version = grid.version();
+    let mut paths = PathQueue::default();
+    let mut flow_fields = FlowFields::default();
+    // The last group order's field, drawn with F
+    let mut flow_goal = None;
+    let mut show_flow = false;
+    // None moves groups as a crowd
+    let mut formation = Some(Formation::Box);
+    let mut neighbour_hash = SpatialHash::new(NEIGHBOUR_RADIUS);
+    let mut neighbours = Vec::new();
+
+    let mut selection_box: Option<SelectionBox> = None;
+    // Every order goes through a serialisable command before reaching a unit
+    let mut commands: Vec<OrderCommand> = Vec::new();
+    let mut frame: u64 = 0;
+
+    loop {
+        frame += 1;
+        clear_background(WHITE);
+
+        let mouse_pos = Vec2::new(mouse_position().0, mouse_position().1);
+
+        // Middle-click toggles walls
+        if is_mouse_button_pressed(MouseButton::Middle) {
+            let cell = grid.cell_at(mouse_pos);
+            grid.set_walkable(cell, !grid.is_walkable(cell));
+        }
+        if is_key_pressed(KeyCode::F) {
+            show_flow = !show_flow;
+        }
+        for (key, choice) in [
+            (KeyCode::Key1, None),
+            (KeyCode::Key2, Some(Formation::Line)),
+            (KeyCode::Key3, Some(Formation::Box)),
+            (KeyCode::Key4, Some(Formation::Wedge)),
+        ] {
+            if is_key_pressed(key) {
+                formation = choice;
+            }
+        }
+        draw_grid(&grid);
+
+        // Handle selection box
+        if is_mouse_button_pressed(MouseButton::Left) {
+            selection_box = Some(SelectionBox::new(mouse_pos));
+        } else if is_mouse_button_released(MouseButton::Left) {
+            selection_box = None;
+        }
+
+        if let Some(box_) = &mut selection_box {
+            box_.end = mouse_pos;
+            box_.draw();
+
+            // Update selection status in real-time
+            for rect in &mut rectangles {
+                rect.selected = rect.team == Team::Player && box_.contains(rect.pos);
+            }
+        }
+
+        // Handle right-click for ordering the selected rectangles: a move,
+        // attack-move with A held or patrol with P, to a target moved off walls.
+        // Right-clicking one of our own units follows it; Shift queues orders
+        let queued = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let selected: Vec<usize> = (0..rectangles.len()).filter(|&index| rectangles[index].selected).collect();
+        if is_mouse_button_pressed(MouseButton::Right) {
+            let clicked = rectangles
+                .iter()
+                .find(|rect| rect.team == Team::Player && Rect::new(rect.pos.x, rect.pos.y, RECT_SIZE, RECT_SIZE).contains(mouse_pos))
+                .map(|rect| rect.id);
+            if let Some(leader) = clicked {
+                for &index in selected.iter().filter(|&&index| rectangles[index].id != leader) {
+                    commands.push(OrderCommand {
+                        frame,
+                        unit: rectangles[index].id,
+                        order: Order::Follow { unit: leader },
+                        queued,
+                    });
+                }
+            } else {
+                let target = off_walls(&grid, mouse_pos);
+                // Queued orders start from where the queue leaves off
+                let starts: Vec<Vec2> = selected
+                    .iter()
+                    .map(|&index| {
+                        let rect = &rectangles[index];
+                        if queued {
+                            rect.orders.end(rect.center())
+                        } else {
+                            rect.center()
+                        }
+                    })
+                    .collect();
+                let (destinations, spread) = group_destinations(&starts, target, formation, &grid);
+                if spread > 0.0 {
+                    flow_goal = Some(grid.cell_at(target));
+                }
+                for ((&index, start), to) in selected.iter().zip(starts).zip(destinations) {
+                    let order = if is_key_down(KeyCode::A) {
+                        Order::AttackMove { to, spread }
+                    } else if is_key_down(KeyCode::P) {
+                        Order::Patrol { points: vec![start, to] }
+                    } else {
+                        Order::Move { to, spread }
+                    };
+                    commands.push(OrderCommand {
+                        frame,
+                        unit: rectangles[index].id,
+                        order,
+                        queued,
+                    });
+                }
+            }
+        }
+        for (key, order) in [(KeyCode::H, Order::HoldPosition), (KeyCode::S, Order::Stop)] {
+            if is_key_pressed(key) {
+                for &index in &selected {
+                    commands.push(OrderCommand {
+                        frame,
+                        unit: rectangles[index].id,
+                        order: order.clone(),
+                        queued,
+                    });
+                }
+            }
+        }
+        for command in commands.drain(..) {
+            if let Some(rect) = rectangles.iter_mut().find(|rect| rect.id == command.unit) {
+                rect.command(command.order, command.queued, &mut paths);
+            }
+        }
+
+        // Carry out orders, firing at whatever they pick
+        let units: Vec<UnitView> = rectangles
+            .iter()
+            .map(|rect| UnitView {
+                id: rect.id,
+                team: rect.team,
+                center: rect.center(),
+            })
+            .collect();
+        let mut shots = Vec::new();
+        for rect in &mut rectangles {
+            if let Some(enemy) = rect.follow_orders(&units, &grid, &mut paths) {
+                shots.push((rect.center(), enemy));
+            }
+        }
+        for (from, enemy) in shots {
+            if let Some(target) = rectangles.iter_mut().find(|rect| rect.id == enemy) {
+                target.health -= DAMAGE_PER_SECOND * get_frame_time();
+                let to = target.center();
+                draw_line(from.x, from.y, to.x, to.y, 1.0, RED);
+            }
+        }
+        for rect in rectangles.iter_mut().filter(|rect| rect.health <= 0.0) {
+            rect.halt(&mut paths);
+        }
+        rectangles.retain(|rect| rect.health > 0.0);
+
+        // Re-plan paths a changed wall now blocks; flow fields rebuild themselves
+        if grid.version() != grid_version {
+            grid_version = grid.version();
+            for rect in &mut rectangles {
+                let (Some(target), Some(Route::Path(path))) = (rect.target, &rect.route) else {
+                    continue;
+                };
+                let mut remaining = vec![rect.center()];
+                remaining.extend(path);
+                if !grid.path_clear(&remaining, PATH_OPTIONS.radius) {
+                    rect.move_to(target, &grid, &mut paths);
+                }
+            }
+        }
+
+        // Searches are spread over frames, so many units can ask at once
+        paths.update(&grid);
+        for rect in &mut rectangles {
+            let Some(Route::Searching(request)) = rect.route else {
+                continue;
+            };
+            match paths.take(request) {
+                PathStatus::Pending => {}
+                PathStatus::Found(path) => rect.route = Some(Route::Path(path.into_iter().skip(1).collect())),
+                PathStatus::NotFound => rect.stop(),
+            }
+        }
+
+        if show_flow {
+            if let Some(field) = flow_goal.and_then(|goal| flow_fields.cached(&grid, goal)) {
+                field.draw(LIGHTGRAY);
+            }
+        }
+
+        // Steer each unit by the others around it, as they were at the start
+        // of the frame
+        let agents: Vec<Agent> = rectangles
+            .iter()
+            .map(|rect| Agent {
+                id: rect.id.0,
+                position: rect.center(),
+                velocity: rect.velocity,
+            })
+            .collect();
+        let centers: Vec<Vec2> = agents.iter().map(|agent| agent.position).collect();
+        neighbour_hash.rebuild(&centers);
+
+        // Update and draw rectangles
+        for (index, rect) in rectangles.iter_mut().enumerate() {
+            neighbours.clear();
+            neighbours.extend(
+                neighbour_hash
+                    .within(&centers, centers[index], NEIGHBOUR_RADIUS)
+                    .into_iter()
+                    .filter(|&other| other != index)
+                    .map(|other| agents[other]),
+            );
+            rect.update(get_frame_time(), &grid, &mut flow_fields, &neighbours);
+            rect.draw(&units);
+        }
+
+        // Avoid collisions
+        avoid_collisions(&mut rectangles, &mut hash);
+
+        // Draw instructions
+        draw_text(
+            "Left-click and drag to select. Right-click to move selected rectangles, or on one to follow it.",
+            10.0,
+            20.0,
+            20.0,
+            BLACK,
+        );
+        draw_text("Middle-click to add or remove walls. F shows the group flow field.", 10.0, 40.0, 20.0, BLACK);
+        draw_text(
+            &format!(
+                "1: crowd, 2: line, 3: box, 4: wedge. Groups move as: {}",
+                formation.map_or("crowd", Formation::name)
+            ),
+            10.0,
+            60.0,
+            20.0,
+            BLACK,
+        );
+        draw_text(
+            "Shift queues orders. Hold A to attack-move or P to patrol. H holds position, S stops.",
+            10.0,
+            80.0,
+            20.0,
+            BLACK,
+        );
+
+        next_frame().await
+    }
+}
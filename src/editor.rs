@@ -0,0 +1,546 @@
+use crate::history::History;
+use crate::level::{Level, LevelCommand, LevelObject};
+use crate::picking::{PickEvent, Pickable, Picker};
+use macroquad::prelude::*;
+use macroquad_tiled as tiled;
+
+/// Something the editor can place on an object layer, saved as the object's
+/// Tiled `type`.
+#[derive(Debug, Clone)]
+pub struct ObjectKind {
+    pub name: String,
+    /// Size of new objects, in map pixels.
+    pub size: Vec2,
+    pub color: Color,
+}
+
+impl ObjectKind {
+    pub fn new(name: &str, size: Vec2, color: Color) -> Self {
+        ObjectKind {
+            name: name.to_string(),
+            size,
+            color,
+        }
+    }
+}
+
+const PALETTE_CELL: f32 = 12.0;
+const MARGIN: f32 = 10.0;
+const PANEL_COLOR: Color = Color::new(0.0, 0.0, 0.0, 0.7);
+const SELECTED_COLOR: Color = Color::new(1.0, 0.8, 0.1, 1.0);
+const KEYS: &str = "[ ] layer  G snap  Ctrl+Z undo  Ctrl+S save";
+const TILE_KEYS: &str = "LMB paint  RMB erase  MMB pick tile  T tileset";
+const OBJECT_KEYS: &str = "RMB place  Tab kind  drag move  Del delete  Enter set property";
+
+/// Edits a [`Level`] in place while the game shows it: paints tiles from a
+/// tileset palette, places, moves and deletes objects and edits their
+/// properties. Every edit is undoable and copied into the loaded map, so the
+/// game's own drawing shows it at once.
+///
+/// Positions passed in are in map pixels; the palette and overlays are drawn in
+/// screen space, on top of any post-processing.
+pub struct LevelEditor {
+    /// Object kinds to place, cycled with Tab.
+    pub kinds: Vec<ObjectKind>,
+    /// Snap placed and moved objects to the tile grid.
+    pub snap: bool,
+    level: Level,
+    path: String,
+    history: History<Level>,
+    layer: usize,
+    /// The global tile id painted.
+    brush: u32,
+    tileset: usize,
+    kind: usize,
+    picker: Picker<u32>,
+    selected: Option<u32>,
+    /// Where the dragged object was when grabbed.
+    drag_from: Vec2,
+    /// The pointer on the last frame of a paint stroke.
+    stroke: Option<Vec2>,
+    pointer: Vec2,
+    prompt: Option<String>,
+    status: String,
+}
+
+struct Palette {
+    rect: Rect,
+    tileset: String,
+    firstgid: u32,
+    columns: u32,
+    count: u32,
+}
+
+impl Palette {
+    fn gid_at(&self, point: Vec2) -> Option<u32> {
+        if !self.rect.contains(point) {
+            return None;
+        }
+        let cell = ((point - self.rect.point()) / PALETTE_CELL).floor();
+        let index = cell.y as u32 * self.columns + cell.x as u32;
+        (index < self.count).then_some(self.firstgid + index)
+    }
+
+    fn cell(&self, index: u32) -> Rect {
+        let x = self.rect.x + (index % self.columns) as f32 * PALETTE_CELL;
+        let y = self.rect.y + (index / self.columns) as f32 * PALETTE_CELL;
+        Rect::new(x, y, PALETTE_CELL, PALETTE_CELL)
+    }
+}
+
+impl LevelEditor {
+    /// Edits `level`, saving it to `path`.
+    pub fn new(level: Level, path: &str) -> Self {
+        let layer = level.layers.iter().position(|layer| layer.data.is_some()).unwrap_or(0);
+        LevelEditor {
+            kinds: vec![
+                ObjectKind::new("spawn", vec2(8.0, 8.0), GREEN),
+                ObjectKind::new("platform", vec2(32.0, 8.0), SKYBLUE),
+                ObjectKind::new("trigger", vec2(16.0, 16.0), PINK),
+            ],
+            snap: true,
+            level,
+            path: path.to_string(),
+            history: History::new(200),
+            layer,
+            brush: 1,
+            tileset: 0,
+            kind: 0,
+            picker: Picker::new(),
+            selected: None,
+            drag_from: Vec2::ZERO,
+            stroke: None,
+            pointer: Vec2::ZERO,
+            prompt: None,
+            status: String::new(),
+        }
+    }
+
+    pub fn level(&self) -> &Level {
+        &self.level
+    }
+
+    /// The name of the layer being edited.
+    pub fn layer(&self) -> Option<&str> {
+        self.level.layers.get(self.layer).map(|layer| layer.name.as_str())
+    }
+
+    /// Whether a property is being typed, so the game should ignore keys.
+    pub fn is_typing(&self) -> bool {
+        self.prompt.is_some()
+    }
+
+    /// Handles input with the pointer at `point` in map pixels, and copies any
+    /// edit into `map`.
+    pub fn update(&mut self, map: &mut tiled::Map, point: Vec2) {
+        self.pointer = point;
+        let changed = if self.prompt.is_some() {
+            self.update_prompt()
+        } else {
+            self.update_keys() | self.update_layer(map, point)
+        };
+        if changed {
+            self.level.sync(map);
+        }
+    }
+
+    fn update_keys(&mut self) -> bool {
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        if ctrl && is_key_pressed(KeyCode::S) {
+            self.status = match self.level.save(&self.path) {
+                Ok(()) => format!("Saved {}", self.path),
+                Err(err) => format!("Couldn't save {}: {err}", self.path),
+            };
+        }
+        if is_key_pressed(KeyCode::G) {
+            self.snap = !self.snap;
+        }
+
+        let step = if is_key_pressed(KeyCode::RightBracket) {
+            1
+        } else if is_key_pressed(KeyCode::LeftBracket) {
+            self.level.layers.len().saturating_sub(1)
+        } else {
+            0
+        };
+        if step != 0 && self.stroke.is_none() && self.picker.drag().is_none() {
+            // Only tile and object layers can be edited
+            let count = self.level.layers.len();
+            self.layer = (1..=count)
+                .map(|offset| (self.layer + offset * step) % count)
+                .find(|&index| {
+                    let layer = &self.level.layers[index];
+                    layer.data.is_some() || layer.objects.is_some()
+                })
+                .unwrap_or(self.layer);
+            self.selected = None;
+        }
+
+        // Not mid-drag, so the dragged object can't be moved from under the pointer
+        if self.picker.drag().is_some() || !self.history.handle_shortcuts(&mut self.level) {
+            return false;
+        }
+        if let (Some(id), Some(layer)) = (self.selected, self.layer()) {
+            if self.level.object(layer, id).is_none() {
+                self.picker.forget(id);
+                self.selected = None;
+            }
+        }
+        true
+    }
+
+    fn update_layer(&mut self, map: &tiled::Map, point: Vec2) -> bool {
+        let Some(layer) = self.level.layers.get(self.layer) else {
+            return false;
+        };
+        let name = layer.name.clone();
+        if layer.data.is_some() {
+            self.update_tiles(map, &name, point)
+        } else if layer.objects.is_some() {
+            self.update_objects(&name, point)
+        } else {
+            false
+        }
+    }
+
+    fn update_tiles(&mut self, map: &tiled::Map, layer: &str, point: Vec2) -> bool {
+        let tileset_count = map.raw_tiled_map.tilesets.len();
+        if is_key_pressed(KeyCode::T) && tileset_count > 0 {
+            self.tileset = (self.tileset + 1) % tileset_count;
+        }
+
+        let mouse = Vec2::from(mouse_position());
+        let palette = self.palette(map);
+        let over_palette = palette.as_ref().is_some_and(|palette| palette.rect.contains(mouse));
+        if over_palette && self.stroke.is_none() {
+            if is_mouse_button_pressed(MouseButton::Left) {
+                if let Some(gid) = palette.and_then(|palette| palette.gid_at(mouse)) {
+                    self.brush = gid;
+                }
+            }
+            return false;
+        }
+
+        if is_mouse_button_pressed(MouseButton::Middle) {
+            let picked = self.level.cell_at(point).and_then(|(x, y)| self.level.tile(layer, x, y));
+            if let Some(gid) = picked.filter(|&gid| gid != 0) {
+                self.brush = gid;
+            }
+        }
+
+        let gid = if is_mouse_button_down(MouseButton::Left) {
+            self.brush
+        } else if is_mouse_button_down(MouseButton::Right) {
+            0
+        } else {
+            if self.stroke.take().is_some() {
+                self.history.end_group();
+            }
+            return false;
+        };
+        // Each stroke is one undo step
+        let from = match self.stroke {
+            Some(from) => from,
+            None if is_mouse_button_pressed(MouseButton::Left) || is_mouse_button_pressed(MouseButton::Right) => {
+                self.history.begin_group();
+                point
+            }
+            None => return false,
+        };
+        self.stroke = Some(point);
+
+        // Paints every cell crossed since the last frame, not just where the
+        // pointer landed
+        let tile_size = vec2(self.level.tilewidth as f32, self.level.tileheight as f32);
+        let steps = ((point - from).length() / (tile_size.min_element() / 2.0)).ceil().max(1.0) as u32;
+        let mut changed = false;
+        for step in 0..=steps {
+            let Some((x, y)) = self.level.cell_at(from.lerp(point, step as f32 / steps as f32)) else {
+                continue;
+            };
+            match self.level.tile(layer, x, y) {
+                Some(old) if old != gid => {
+                    let command = LevelCommand::SetTile {
+                        layer: layer.to_string(),
+                        x,
+                        y,
+                        from: old,
+                        to: gid,
+                    };
+                    self.history.push(command, &mut self.level);
+                    changed = true;
+                }
+                _ => {}
+            }
+        }
+        changed
+    }
+
+    fn update_objects(&mut self, layer: &str, point: Vec2) -> bool {
+        if is_key_pressed(KeyCode::Tab) && !self.kinds.is_empty() {
+            self.kind = (self.kind + 1) % self.kinds.len();
+        }
+
+        let objects = self.level.objects(layer);
+        let events = self
+            .picker
+            .update(point, |point| {
+                objects
+                    .iter()
+                    .rev()
+                    .find_map(|object| Some((object.id, object.rect().pick(point)?)))
+            })
+            .to_vec();
+        let mut changed = false;
+        for event in events {
+            match event {
+                PickEvent::DragStart { id, .. } => {
+                    self.selected = Some(id);
+                    if let Some(object) = self.level.object(layer, id) {
+                        self.drag_from = vec2(object.x, object.y);
+                    }
+                }
+                PickEvent::DragMove { id, point, .. } => {
+                    let Some(drag) = self.picker.drag() else {
+                        continue;
+                    };
+                    let position = self.snapped(point - drag.grab);
+                    if let Some(object) = self.level.object_mut(layer, id) {
+                        object.x = position.x;
+                        object.y = position.y;
+                        changed = true;
+                    }
+                }
+                // Each drag is one undo step, from where it was grabbed
+                PickEvent::DragEnd { id, .. } => {
+                    let Some(object) = self.level.object(layer, id) else {
+                        continue;
+                    };
+                    let to = vec2(object.x, object.y);
+                    if to != self.drag_from {
+                        self.history.record(LevelCommand::MoveObject {
+                            layer: layer.to_string(),
+                            id,
+                            from: self.drag_from,
+                            to,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        if is_mouse_button_pressed(MouseButton::Left) && self.picker.hovered().is_none() {
+            self.selected = None;
+        }
+
+        if is_mouse_button_pressed(MouseButton::Right) && self.picker.drag().is_none() {
+            if let Some(kind) = self.kinds.get(self.kind) {
+                let position = self.snapped(point);
+                let object = LevelObject::new(&kind.name, Rect::new(position.x, position.y, kind.size.x, kind.size.y));
+                if let Some(id) = self.level.add_object(layer, object) {
+                    self.history.record(LevelCommand::AddObject {
+                        layer: layer.to_string(),
+                        id,
+                        removed: None,
+                    });
+                    self.selected = Some(id);
+                    changed = true;
+                }
+            }
+        }
+
+        let Some(id) = self.selected else {
+            return changed;
+        };
+        if (is_key_pressed(KeyCode::Delete) || is_key_pressed(KeyCode::Backspace)) && self.picker.drag().is_none() {
+            let command = LevelCommand::RemoveObject {
+                layer: layer.to_string(),
+                id,
+                removed: None,
+            };
+            self.history.push(command, &mut self.level);
+            self.picker.forget(id);
+            self.selected = None;
+            changed = true;
+        } else if is_key_pressed(KeyCode::Enter) {
+            // Drops what was typed before the prompt opened
+            while get_char_pressed().is_some() {}
+            self.prompt = Some(String::new());
+        }
+        changed
+    }
+
+    /// Types `name=value` for the selected object; an empty value removes the
+    /// property.
+    fn update_prompt(&mut self) -> bool {
+        let Some(text) = &mut self.prompt else {
+            return false;
+        };
+        while let Some(character) = get_char_pressed() {
+            if !character.is_control() {
+                text.push(character);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            text.pop();
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            self.prompt = None;
+            return false;
+        }
+        if !is_key_pressed(KeyCode::Enter) {
+            return false;
+        }
+
+        let text = self.prompt.take().unwrap_or_default();
+        let (Some(id), Some(layer)) = (self.selected, self.layer()) else {
+            return false;
+        };
+        let Some((name, value)) = text.split_once('=').filter(|(name, _)| !name.trim().is_empty()) else {
+            self.status = "Properties are set as name=value".to_string();
+            return false;
+        };
+        let value = value.trim();
+        let command = LevelCommand::SetProperty {
+            layer: layer.to_string(),
+            id,
+            name: name.trim().to_string(),
+            value: (!value.is_empty()).then(|| value.to_string()),
+        };
+        self.history.push(command, &mut self.level);
+        true
+    }
+
+    fn snapped(&self, point: Vec2) -> Vec2 {
+        if !self.snap {
+            return point;
+        }
+        let tile_size = vec2(self.level.tilewidth as f32, self.level.tileheight as f32);
+        (point / tile_size).round() * tile_size
+    }
+
+    /// The tileset being painted from, laid out in the top right corner.
+    fn palette(&self, map: &tiled::Map) -> Option<Palette> {
+        let tileset = map.raw_tiled_map.tilesets.get(self.tileset)?;
+        let columns = tileset.columns.max(1) as u32;
+        let rows = tileset.tilecount.div_ceil(columns);
+        let size = vec2(columns as f32, rows as f32) * PALETTE_CELL;
+        Some(Palette {
+            rect: Rect::new(screen_width() - size.x - MARGIN, MARGIN, size.x, size.y),
+            tileset: tileset.name.clone(),
+            firstgid: tileset.firstgid,
+            columns,
+            count: tileset.tilecount,
+        })
+    }
+
+    /// Draws the overlays, with `to_screen` mapping map pixels to the screen.
+    pub fn draw(&self, map: &tiled::Map, to_screen: impl Fn(Vec2) -> Vec2) {
+        let screen_rect = |rect: Rect| {
+            let min = to_screen(rect.point());
+            let max = to_screen(rect.point() + rect.size());
+            Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+        };
+        let current = self.level.layers.get(self.layer);
+
+        // Objects on every layer, the edited one on top and the rest faded
+        let object_layers = self.level.layers.iter().filter(|layer| layer.objects.is_some());
+        let (edited, others): (Vec<_>, Vec<_>) = object_layers.partition(|layer| Some(*layer) == current);
+        for (layer, faded) in others.iter().map(|layer| (layer, true)).chain(edited.iter().map(|layer| (layer, false))) {
+            for object in self.level.objects(&layer.name) {
+                let rect = screen_rect(object.rect());
+                let color = self
+                    .kinds
+                    .iter()
+                    .find(|kind| kind.name == object.kind)
+                    .map_or(WHITE, |kind| kind.color);
+                let alpha = if faded { 0.3 } else { 1.0 };
+                let selected = !faded && self.selected == Some(object.id);
+                let hovered = !faded && self.picker.hovered() == Some(object.id);
+                draw_rectangle(rect.x, rect.y, rect.w, rect.h, Color { a: alpha * 0.3, ..color });
+                let outline = if selected { SELECTED_COLOR } else { Color { a: alpha, ..color } };
+                let thickness = if selected || hovered { 3.0 } else { 1.0 };
+                draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, thickness, outline);
+                let label = if object.name.is_empty() { &object.kind } else { &object.name };
+                draw_text(label, rect.x, rect.y - 3.0, 16.0, Color { a: alpha, ..WHITE });
+            }
+        }
+
+        let mut lines = vec![
+            format!(
+                "EDITOR  layer: {}  snap: {}",
+                current.map_or("none", |layer| layer.name.as_str()),
+                if self.snap { "on" } else { "off" }
+            ),
+            KEYS.to_string(),
+        ];
+        match current {
+            Some(layer) if layer.data.is_some() => {
+                lines.push(format!("{TILE_KEYS}  brush: {}", self.brush));
+                self.draw_tile_overlay(map, &screen_rect);
+            }
+            Some(layer) if layer.objects.is_some() => {
+                let kind = self.kinds.get(self.kind).map_or("none", |kind| kind.name.as_str());
+                lines.push(format!("{OBJECT_KEYS}  placing: {kind}"));
+                if let Some(object) = self.selected.and_then(|id| self.level.object(&layer.name, id)) {
+                    lines.push(format!("#{} {} at {}, {}", object.id, object.kind, object.x, object.y));
+                    for property in &object.properties {
+                        let value = object.property(&property.name).unwrap_or_default();
+                        lines.push(format!("  {} = {}", property.name, value));
+                    }
+                }
+            }
+            _ => {}
+        }
+        if let Some(text) = &self.prompt {
+            lines.push(format!("name=value, empty value removes: {text}_"));
+        }
+        if !self.status.is_empty() {
+            lines.push(self.status.clone());
+        }
+
+        let width = lines
+            .iter()
+            .map(|line| measure_text(line, None, 18, 1.0).width)
+            .fold(0.0, f32::max);
+        draw_rectangle(MARGIN, MARGIN, width + 12.0, lines.len() as f32 * 20.0 + 8.0, PANEL_COLOR);
+        for (index, line) in lines.iter().enumerate() {
+            draw_text(line, MARGIN + 6.0, MARGIN + 20.0 + index as f32 * 20.0, 18.0, WHITE);
+        }
+    }
+
+    /// The brush under the pointer and the palette.
+    fn draw_tile_overlay(&self, map: &tiled::Map, screen_rect: &impl Fn(Rect) -> Rect) {
+        let tile_size = vec2(self.level.tilewidth as f32, self.level.tileheight as f32);
+        if let Some((x, y)) = self.level.cell_at(self.pointer) {
+            let cell = screen_rect(Rect::new(x as f32 * tile_size.x, y as f32 * tile_size.y, tile_size.x, tile_size.y));
+            if let Some((tileset, id)) = tile_source(map, self.brush) {
+                map.spr(tileset, id, cell);
+            }
+            draw_rectangle_lines(cell.x, cell.y, cell.w, cell.h, 2.0, SELECTED_COLOR);
+        }
+
+        let Some(palette) = self.palette(map) else {
+            return;
+        };
+        let rect = palette.rect;
+        draw_rectangle(rect.x - 4.0, rect.y - 4.0, rect.w + 8.0, rect.h + 8.0, PANEL_COLOR);
+        for index in 0..palette.count {
+            map.spr(&palette.tileset, index, palette.cell(index));
+        }
+        if let Some(index) = self.brush.checked_sub(palette.firstgid).filter(|&index| index < palette.count) {
+            let cell = palette.cell(index);
+            draw_rectangle_lines(cell.x, cell.y, cell.w, cell.h, 2.0, SELECTED_COLOR);
+        }
+    }
+}
+
+/// The tileset and id within it of a global tile id.
+fn tile_source(map: &tiled::Map, gid: u32) -> Option<(&str, u32)> {
+    let tileset = map
+        .raw_tiled_map
+        .tilesets
+        .iter()
+        .find(|tileset| gid >= tileset.firstgid && gid < tileset.firstgid + tileset.tilecount)?;
+    Some((tileset.name.as_str(), gid - tileset.firstgid))
+}
@@ -0,0 +1,208 @@
+use crate::pathfinding::{octile, Grid, Open, DIRECTIONS};
+use macroquad::prelude::*;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+/// Every cell's way to one goal: the integration field holds the cost of the
+/// cheapest walk from each cell, and the direction field which neighbour to
+/// step to next. Built once per goal, it steers any number of units there.
+#[derive(Debug, Clone)]
+pub struct FlowField {
+    goal: IVec2,
+    version: u64,
+    width: i32,
+    cell_size: Vec2,
+    /// Infinite where the goal can't be reached.
+    cost: Vec<f32>,
+    directions: Vec<Vec2>,
+}
+
+impl FlowField {
+    /// Integrates outwards from `goal` over the walkable cells of `grid`.
+    pub fn new(grid: &Grid, goal: IVec2) -> Self {
+        let cells = (grid.width() * grid.height()) as usize;
+        let mut field = FlowField {
+            goal,
+            version: grid.version(),
+            width: grid.width() as i32,
+            cell_size: grid.cell_size(),
+            cost: vec![f32::INFINITY; cells],
+            directions: vec![Vec2::ZERO; cells],
+        };
+        let Some(goal_index) = grid.index(goal).filter(|_| grid.is_walkable(goal)) else {
+            return field;
+        };
+
+        // Dijkstra from the goal; moves are symmetric, so the cost from the goal
+        // to a cell is the cost back
+        field.cost[goal_index] = 0.0;
+        let mut open = BinaryHeap::from([Open {
+            estimate: 0.0,
+            cost: 0.0,
+            index: goal_index as u32,
+        }]);
+        while let Some(current) = open.pop() {
+            let index = current.index as usize;
+            if current.cost > field.cost[index] {
+                continue;
+            }
+            let cell = grid.cell_of_index(index);
+            for direction in DIRECTIONS {
+                if !grid.can_step(cell, direction) {
+                    continue;
+                }
+                let next = cell + direction;
+                let Some(next_index) = grid.index(next) else {
+                    continue;
+                };
+                let cost = current.cost + octile(cell, next);
+                if cost < field.cost[next_index] {
+                    field.cost[next_index] = cost;
+                    open.push(Open {
+                        estimate: cost,
+                        cost,
+                        index: next_index as u32,
+                    });
+                }
+            }
+        }
+
+        // Each cell points at its cheapest neighbour; straight moves come first
+        // in the list, so they win ties. Units pushed into a wall are pointed
+        // back out of it
+        for index in 0..cells {
+            let cell = grid.cell_of_index(index);
+            let escaping = !grid.is_walkable(cell);
+            if index == goal_index || field.cost[index].is_infinite() && !escaping {
+                continue;
+            }
+            let best = DIRECTIONS
+                .iter()
+                .filter(|&&direction| escaping || grid.can_step(cell, direction))
+                .filter_map(|&direction| Some((direction, field.cost[grid.index(cell + direction)?])))
+                .filter(|(_, cost)| cost.is_finite())
+                .fold(None, |best: Option<(IVec2, f32)>, candidate| match best {
+                    Some((_, cost)) if cost <= candidate.1 => best,
+                    _ => Some(candidate),
+                });
+            if let Some((direction, _)) = best {
+                field.directions[index] = direction.as_vec2().normalize();
+            }
+        }
+        field
+    }
+
+    pub fn goal(&self) -> IVec2 {
+        self.goal
+    }
+
+    /// Whether the field still matches the grid's walls.
+    pub fn is_current(&self, grid: &Grid) -> bool {
+        self.version == grid.version()
+    }
+
+    fn index(&self, cell: IVec2) -> Option<usize> {
+        let height = self.cost.len() as i32 / self.width.max(1);
+        (cell.x >= 0 && cell.y >= 0 && cell.x < self.width && cell.y < height).then(|| (cell.y * self.width + cell.x) as usize)
+    }
+
+    fn cell_at(&self, point: Vec2) -> IVec2 {
+        (point / self.cell_size).floor().as_ivec2()
+    }
+
+    /// The cost of walking from a cell to the goal, `None` if it can't be
+    /// reached.
+    pub fn cost(&self, cell: IVec2) -> Option<f32> {
+        self.index(cell).map(|index| self.cost[index]).filter(|cost| cost.is_finite())
+    }
+
+    /// The unit direction to move from a cell, zero at the goal or where the
+    /// goal can't be reached.
+    pub fn direction(&self, cell: IVec2) -> Vec2 {
+        self.index(cell).map_or(Vec2::ZERO, |index| self.directions[index])
+    }
+
+    /// The direction to move from a world point, zero once in the goal cell.
+    pub fn sample(&self, point: Vec2) -> Vec2 {
+        self.direction(self.cell_at(point))
+    }
+
+    pub fn is_at_goal(&self, point: Vec2) -> bool {
+        self.cell_at(point) == self.goal
+    }
+
+    /// Draws the direction field as arrows, for debugging.
+    pub fn draw(&self, color: Color) {
+        let half = self.cell_size / 2.0;
+        for (index, direction) in self.directions.iter().enumerate() {
+            if *direction == Vec2::ZERO {
+                continue;
+            }
+            let cell = ivec2(index as i32 % self.width, index as i32 / self.width);
+            let center = cell.as_vec2() * self.cell_size + half;
+            let tip = center + *direction * half.min_element() * 0.8;
+            draw_line(center.x, center.y, tip.x, tip.y, 1.0, color);
+            draw_circle(tip.x, tip.y, 1.5, color);
+        }
+    }
+}
+
+/// Flow fields by goal cell, so every order to the same place shares one.
+/// All of them are dropped when the grid's walls change, and the oldest once
+/// there are more than `capacity`.
+#[derive(Debug, Clone)]
+pub struct FlowFields {
+    pub capacity: usize,
+    fields: HashMap<IVec2, FlowField>,
+    /// Goals from oldest to newest.
+    order: VecDeque<IVec2>,
+}
+
+impl Default for FlowFields {
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+impl FlowFields {
+    pub fn new(capacity: usize) -> Self {
+        FlowFields {
+            capacity,
+            fields: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// The field to `goal`, built now if there isn't a current one.
+    pub fn get(&mut self, grid: &Grid, goal: IVec2) -> &FlowField {
+        if self.fields.values().any(|field| !field.is_current(grid)) {
+            self.invalidate();
+        }
+        if !self.fields.contains_key(&goal) {
+            while self.order.len() >= self.capacity.max(1) {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.fields.remove(&oldest);
+                }
+            }
+            self.order.push_back(goal);
+        }
+        self.fields.entry(goal).or_insert_with(|| FlowField::new(grid, goal))
+    }
+
+    /// The field to `goal` if it's been built since the walls last changed.
+    pub fn cached(&self, grid: &Grid, goal: IVec2) -> Option<&FlowField> {
+        self.fields.get(&goal).filter(|field| field.is_current(grid))
+    }
+
+    pub fn invalidate(&mut self) {
+        self.fields.clear();
+        self.order.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
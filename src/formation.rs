@@ -0,0 +1,116 @@
+use macroquad::prelude::*;
+use serde::Deserialize;
+
+/// The shapes a group can be ordered to move in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Formation {
+    /// One rank, side by side.
+    Line,
+    /// As square as the group allows, filled rank by rank.
+    Box,
+    /// A V with its point leading.
+    Wedge,
+}
+
+impl Formation {
+    pub const ALL: [Formation; 3] = [Formation::Line, Formation::Box, Formation::Wedge];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Formation::Line => "line",
+            Formation::Box => "box",
+            Formation::Wedge => "wedge",
+        }
+    }
+
+    /// Slot offsets for `count` units `spacing` apart, with x across the
+    /// formation and y towards the back, centred on the origin.
+    pub fn offsets(self, count: usize, spacing: f32) -> Vec<Vec2> {
+        let mut offsets: Vec<Vec2> = match self {
+            Formation::Line => rank(count, 0.0).collect(),
+            Formation::Box => {
+                let columns = (count as f32).sqrt().ceil().max(1.0) as usize;
+                (0..count.div_ceil(columns))
+                    .flat_map(|row| rank(columns.min(count - row * columns), row as f32))
+                    .collect()
+            }
+            Formation::Wedge => {
+                // Row n has n + 1 units, so each row overhangs the one ahead
+                let mut offsets = Vec::with_capacity(count);
+                let mut row = 0;
+                while offsets.len() < count {
+                    offsets.extend(rank((row + 1).min(count - offsets.len()), row as f32));
+                    row += 1;
+                }
+                offsets
+            }
+        };
+        let centre = offsets.iter().sum::<Vec2>() / count.max(1) as f32;
+        for offset in &mut offsets {
+            *offset = (*offset - centre) * spacing;
+        }
+        offsets
+    }
+
+    /// World positions of the slots for a formation standing on `target`,
+    /// facing `facing`.
+    pub fn place(self, count: usize, target: Vec2, facing: Vec2, spacing: f32) -> Vec<Vec2> {
+        let forward = facing.try_normalize().unwrap_or(Vec2::X);
+        let across = forward.perp();
+        self.offsets(count, spacing)
+            .into_iter()
+            .map(|offset| target + across * offset.x - forward * offset.y)
+            .collect()
+    }
+}
+
+/// `count` slots side by side, centred across, at depth `row`.
+fn rank(count: usize, row: f32) -> impl Iterator<Item = Vec2> {
+    let half = (count as f32 - 1.0) / 2.0;
+    (0..count).map(move |index| vec2(index as f32 - half, row))
+}
+
+/// Which slot each unit takes, as indices into `slots`: nearest pairs first,
+/// then swapped while that shortens the total walk, so paths rarely cross.
+/// There should be a slot for every unit.
+pub fn assign_slots(units: &[Vec2], slots: &[Vec2]) -> Vec<usize> {
+    let mut pairs: Vec<(f32, usize, usize)> = units
+        .iter()
+        .enumerate()
+        .flat_map(|(unit, from)| slots.iter().enumerate().map(move |(slot, to)| (from.distance_squared(*to), unit, slot)))
+        .collect();
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut assigned = vec![usize::MAX; units.len()];
+    let mut taken = vec![false; slots.len()];
+    for (_, unit, slot) in pairs {
+        if assigned[unit] == usize::MAX && !taken[slot] {
+            assigned[unit] = slot;
+            taken[slot] = true;
+        }
+    }
+
+    // Greedy leaves the last few units long walks across the group; a few
+    // passes of pairwise swaps untangle them
+    let cost = |unit: usize, slot: usize| units[unit].distance(slots[slot]);
+    for _ in 0..8 {
+        let mut improved = false;
+        for first in 0..units.len() {
+            for second in first + 1..units.len() {
+                let (a, b) = (assigned[first], assigned[second]);
+                if a == usize::MAX || b == usize::MAX {
+                    continue;
+                }
+                if cost(first, b) + cost(second, a) + 1e-3 < cost(first, a) + cost(second, b) {
+                    assigned.swap(first, second);
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+    assigned
+}
@@ -0,0 +1,327 @@
+use crate::picking::to_local;
+use crate::transform::Transform;
+use macroquad::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+/// A part of the gizmo that can be grabbed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoHandle {
+    /// The centre square; moves freely.
+    Move,
+    MoveX,
+    MoveY,
+    /// The ring.
+    Rotate,
+    ScaleX,
+    ScaleY,
+    /// The centre square in scale mode.
+    ScaleUniform,
+}
+
+/// Increments edits snap to. `None` leaves that kind of edit continuous.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Snapping {
+    /// In the parent's units.
+    pub grid: Option<f32>,
+    /// In radians.
+    pub angle: Option<f32>,
+    pub scale: Option<f32>,
+}
+
+impl Snapping {
+    pub fn enabled() -> Self {
+        Snapping {
+            grid: Some(10.0),
+            angle: Some(15f32.to_radians()),
+            scale: Some(0.1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GizmoEvent {
+    /// A handle was grabbed.
+    Begin,
+    /// The transform was edited.
+    Change,
+    /// The handle was let go. `before` is the transform when it was grabbed, so a
+    /// whole drag can be recorded as one edit.
+    End { before: Transform },
+}
+
+struct ActiveDrag {
+    handle: GizmoHandle,
+    /// The pointer where the drag started, in the parent's space.
+    start: Vec2,
+    before: Transform,
+}
+
+/// On-screen handles for translating, rotating and scaling a [`Transform`].
+///
+/// The transform is local to `parent`, the world matrix of whatever it's nested
+/// in, so the same gizmo edits top-level objects (with `Mat3::IDENTITY`) and
+/// scene graph nodes. Handles keep a constant size on screen.
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    pub snapping: Snapping,
+    /// Length of the handles in pixels.
+    pub size: f32,
+    hovered: Option<GizmoHandle>,
+    active: Option<ActiveDrag>,
+}
+
+impl Default for Gizmo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const GRAB_DISTANCE: f32 = 6.0;
+const CENTER_HALF_SIZE: f32 = 7.0;
+const X_COLOR: Color = Color::new(0.9, 0.2, 0.2, 1.0);
+const Y_COLOR: Color = Color::new(0.2, 0.7, 0.2, 1.0);
+const CENTER_COLOR: Color = Color::new(0.2, 0.4, 0.9, 1.0);
+const ACTIVE_COLOR: Color = Color::new(1.0, 0.8, 0.1, 1.0);
+
+impl Gizmo {
+    pub fn new() -> Self {
+        Gizmo {
+            mode: GizmoMode::Translate,
+            snapping: Snapping::default(),
+            size: 70.0,
+            hovered: None,
+            active: None,
+        }
+    }
+
+    /// The handle under the pointer or being dragged.
+    pub fn hovered(&self) -> Option<GizmoHandle> {
+        self.active.as_ref().map(|active| active.handle).or(self.hovered)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Whether a click should go to the gizmo rather than picking what's below.
+    pub fn wants_pointer(&self) -> bool {
+        self.hovered().is_some()
+    }
+
+    /// Abandons a drag, e.g. when the edited object is deselected.
+    pub fn cancel(&mut self) {
+        self.active = None;
+    }
+
+    /// Updates from the left mouse button with the pointer at `point`.
+    pub fn update(&mut self, transform: &mut Transform, parent: Mat3, point: Vec2) -> Option<GizmoEvent> {
+        self.handle(
+            transform,
+            parent,
+            point,
+            is_mouse_button_pressed(MouseButton::Left),
+            is_mouse_button_down(MouseButton::Left),
+        )
+    }
+
+    /// As [`Gizmo::update`], with the button state supplied.
+    pub fn handle(
+        &mut self,
+        transform: &mut Transform,
+        parent: Mat3,
+        point: Vec2,
+        pressed: bool,
+        down: bool,
+    ) -> Option<GizmoEvent> {
+        let Some(local) = to_local(parent, point) else {
+            self.hovered = None;
+            return None;
+        };
+
+        if let Some(active) = &self.active {
+            if !down {
+                let before = active.before;
+                self.active = None;
+                self.hovered = self.hit(transform, parent, point);
+                return Some(GizmoEvent::End { before });
+            }
+            let edited = self.drag(active, local);
+            if edited == *transform {
+                return None;
+            }
+            *transform = edited;
+            return Some(GizmoEvent::Change);
+        }
+
+        self.hovered = self.hit(transform, parent, point);
+        match self.hovered {
+            Some(handle) if pressed => {
+                self.active = Some(ActiveDrag {
+                    handle,
+                    start: local,
+                    before: *transform,
+                });
+                Some(GizmoEvent::Begin)
+            }
+            _ => None,
+        }
+    }
+
+    /// The edited transform for the pointer at `local`, in the parent's space.
+    fn drag(&self, active: &ActiveDrag, local: Vec2) -> Transform {
+        let before = active.before;
+        let mut transform = before;
+        let delta = local - active.start;
+        let snap = |value: f32, step: Option<f32>| match step {
+            Some(step) if step > 0.0 => (value / step).round() * step,
+            _ => value,
+        };
+        match active.handle {
+            GizmoHandle::Move => {
+                transform.position = before.position + delta;
+                transform.position.x = snap(transform.position.x, self.snapping.grid);
+                transform.position.y = snap(transform.position.y, self.snapping.grid);
+            }
+            GizmoHandle::MoveX => transform.position.x = snap(before.position.x + delta.x, self.snapping.grid),
+            GizmoHandle::MoveY => transform.position.y = snap(before.position.y + delta.y, self.snapping.grid),
+            GizmoHandle::Rotate => {
+                let from = active.start - before.position;
+                let to = local - before.position;
+                if from != Vec2::ZERO && to != Vec2::ZERO {
+                    transform.rotation = snap(before.rotation + from.angle_between(to), self.snapping.angle);
+                }
+            }
+            GizmoHandle::ScaleX | GizmoHandle::ScaleY | GizmoHandle::ScaleUniform => {
+                // Ratio of the pointer's distance from the origin along the axis,
+                // now against when grabbed
+                let axis = match active.handle {
+                    GizmoHandle::ScaleX => Vec2::from_angle(before.rotation),
+                    GizmoHandle::ScaleY => Vec2::from_angle(before.rotation).perp(),
+                    _ => (active.start - before.position).normalize_or_zero(),
+                };
+                let start = (active.start - before.position).dot(axis);
+                if start.abs() <= f32::EPSILON {
+                    return before;
+                }
+                let ratio = (local - before.position).dot(axis) / start;
+                let scale = |value: f32| snap(value * ratio, self.snapping.scale);
+                match active.handle {
+                    GizmoHandle::ScaleX => transform.scale.x = scale(before.scale.x),
+                    GizmoHandle::ScaleY => transform.scale.y = scale(before.scale.y),
+                    _ => transform.scale = vec2(scale(before.scale.x), scale(before.scale.y)),
+                }
+            }
+        }
+        transform
+    }
+
+    /// The handle at the world-space `point`, nearest the centre first.
+    fn hit(&self, transform: &Transform, parent: Mat3, point: Vec2) -> Option<GizmoHandle> {
+        let frame = Frame::new(transform, parent);
+        let offset = point - frame.origin;
+        let in_center = offset.x.abs() <= CENTER_HALF_SIZE && offset.y.abs() <= CENTER_HALF_SIZE;
+        let near_axis = |axis: Vec2| {
+            let along = offset.dot(axis);
+            along >= 0.0 && along <= self.size + GRAB_DISTANCE && (offset - axis * along).length() <= GRAB_DISTANCE
+        };
+        match self.mode {
+            GizmoMode::Translate if in_center => Some(GizmoHandle::Move),
+            GizmoMode::Translate if near_axis(frame.parent_x) => Some(GizmoHandle::MoveX),
+            GizmoMode::Translate if near_axis(frame.parent_y) => Some(GizmoHandle::MoveY),
+            GizmoMode::Rotate if (offset.length() - self.size).abs() <= GRAB_DISTANCE => Some(GizmoHandle::Rotate),
+            GizmoMode::Scale if in_center => Some(GizmoHandle::ScaleUniform),
+            GizmoMode::Scale if near_axis(frame.local_x) => Some(GizmoHandle::ScaleX),
+            GizmoMode::Scale if near_axis(frame.local_y) => Some(GizmoHandle::ScaleY),
+            _ => None,
+        }
+    }
+
+    /// Draws the handles for the current mode and, while dragging, the edited
+    /// values next to the pointer.
+    pub fn draw(&self, transform: &Transform, parent: Mat3) {
+        let frame = Frame::new(transform, parent);
+        let highlight = self.hovered();
+        let color = |handle: GizmoHandle, color: Color| if highlight == Some(handle) { ACTIVE_COLOR } else { color };
+        let origin = frame.origin;
+        let center = |handle: GizmoHandle| {
+            let size = CENTER_HALF_SIZE * 2.0;
+            draw_rectangle(origin.x - CENTER_HALF_SIZE, origin.y - CENTER_HALF_SIZE, size, size, color(handle, CENTER_COLOR));
+        };
+
+        match self.mode {
+            GizmoMode::Translate => {
+                for (handle, axis, axis_color) in [
+                    (GizmoHandle::MoveX, frame.parent_x, X_COLOR),
+                    (GizmoHandle::MoveY, frame.parent_y, Y_COLOR),
+                ] {
+                    let tip = origin + axis * self.size;
+                    let axis_color = color(handle, axis_color);
+                    draw_line(origin.x, origin.y, tip.x, tip.y, 3.0, axis_color);
+                    let side = axis.perp() * 6.0;
+                    draw_triangle(tip + axis * 12.0, tip + side, tip - side, axis_color);
+                }
+                center(GizmoHandle::Move);
+            }
+            GizmoMode::Rotate => {
+                draw_circle_lines(origin.x, origin.y, self.size, 3.0, color(GizmoHandle::Rotate, CENTER_COLOR));
+                let marker = origin + frame.local_x * self.size;
+                draw_line(origin.x, origin.y, marker.x, marker.y, 2.0, X_COLOR);
+            }
+            GizmoMode::Scale => {
+                for (handle, axis, axis_color) in [
+                    (GizmoHandle::ScaleX, frame.local_x, X_COLOR),
+                    (GizmoHandle::ScaleY, frame.local_y, Y_COLOR),
+                ] {
+                    let tip = origin + axis * self.size;
+                    let axis_color = color(handle, axis_color);
+                    draw_line(origin.x, origin.y, tip.x, tip.y, 3.0, axis_color);
+                    draw_rectangle(tip.x - 5.0, tip.y - 5.0, 10.0, 10.0, axis_color);
+                }
+                center(GizmoHandle::ScaleUniform);
+            }
+        }
+
+        if self.active.is_some() {
+            let readout = match self.mode {
+                GizmoMode::Translate => format!("x {:.1}  y {:.1}", transform.position.x, transform.position.y),
+                GizmoMode::Rotate => format!("{:.1} deg", transform.rotation.to_degrees()),
+                GizmoMode::Scale => format!("{:.2} x {:.2}", transform.scale.x, transform.scale.y),
+            };
+            let (x, y) = mouse_position();
+            let dimensions = measure_text(&readout, None, 20, 1.0);
+            draw_rectangle(x + 14.0, y + 6.0, dimensions.width + 8.0, 24.0, Color::new(0.0, 0.0, 0.0, 0.6));
+            draw_text(&readout, x + 18.0, y + 24.0, 20.0, WHITE);
+        }
+    }
+}
+
+/// Where the gizmo sits on screen and which way its handles point.
+struct Frame {
+    origin: Vec2,
+    /// The axes positions are measured along.
+    parent_x: Vec2,
+    parent_y: Vec2,
+    /// The object's own axes, which scale along.
+    local_x: Vec2,
+    local_y: Vec2,
+}
+
+impl Frame {
+    fn new(transform: &Transform, parent: Mat3) -> Self {
+        let direction = |local: Vec2, fallback: Vec2| parent.transform_vector2(local).try_normalize().unwrap_or(fallback);
+        let rotated = Vec2::from_angle(transform.rotation);
+        Frame {
+            origin: parent.transform_point2(transform.position),
+            parent_x: direction(Vec2::X, Vec2::X),
+            parent_y: direction(Vec2::Y, Vec2::Y),
+            local_x: direction(rotated, Vec2::X),
+            local_y: direction(rotated.perp(), Vec2::Y),
+        }
+    }
+}
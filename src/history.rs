@@ -0,0 +1,445 @@
+use crate::scene::{Detached, NodeId, SceneGraph};
+use crate::transform::Transform;
+use macroquad::prelude::*;
+use std::collections::VecDeque;
+
+/// A reversible edit of some state `S`. `apply` and `revert` alternate, starting
+/// with `apply` for commands given to [`History::push`] and with `revert` for
+/// those given to [`History::record`].
+pub trait Command<S> {
+    fn apply(&mut self, state: &mut S);
+    fn revert(&mut self, state: &mut S);
+}
+
+type Group<S> = Vec<Box<dyn Command<S>>>;
+
+/// Undo and redo stacks of command groups. Each group is undone as one step,
+/// e.g. every change made during a single drag.
+pub struct History<S> {
+    undo: VecDeque<Group<S>>,
+    redo: Vec<Group<S>>,
+    open: Option<Group<S>>,
+    /// Most groups kept; the oldest are forgotten first.
+    limit: usize,
+}
+
+impl<S> History<S> {
+    pub fn new(limit: usize) -> Self {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open: None,
+            limit: limit.max(1),
+        }
+    }
+
+    /// Applies a command and records it.
+    pub fn push(&mut self, mut command: impl Command<S> + 'static, state: &mut S) {
+        command.apply(state);
+        self.record(command);
+    }
+
+    /// Records a command whose change has already been made, e.g. once a drag ends.
+    pub fn record(&mut self, command: impl Command<S> + 'static) {
+        self.redo.clear();
+        match &mut self.open {
+            Some(group) => group.push(Box::new(command)),
+            None => self.close(vec![Box::new(command)]),
+        }
+    }
+
+    /// Starts collecting commands into one undo step, until [`History::end_group`].
+    pub fn begin_group(&mut self) {
+        if self.open.is_none() {
+            self.open = Some(Vec::new());
+        }
+    }
+
+    pub fn end_group(&mut self) {
+        if let Some(group) = self.open.take() {
+            if !group.is_empty() {
+                self.close(group);
+            }
+        }
+    }
+
+    pub fn is_grouping(&self) -> bool {
+        self.open.is_some()
+    }
+
+    fn close(&mut self, group: Group<S>) {
+        self.undo.push_back(group);
+        while self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Reverts the latest group. Ends any open group first.
+    pub fn undo(&mut self, state: &mut S) -> bool {
+        self.end_group();
+        let Some(mut group) = self.undo.pop_back() else {
+            return false;
+        };
+        for command in group.iter_mut().rev() {
+            command.revert(state);
+        }
+        self.redo.push(group);
+        true
+    }
+
+    /// Reapplies the latest undone group.
+    pub fn redo(&mut self, state: &mut S) -> bool {
+        self.end_group();
+        let Some(mut group) = self.redo.pop() else {
+            return false;
+        };
+        for command in group.iter_mut() {
+            command.apply(state);
+        }
+        self.close(group);
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Groups that can be undone.
+    pub fn len(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.undo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.open = None;
+    }
+
+    /// Ctrl+Z undoes and Ctrl+Shift+Z redoes, ignored mid-group so a drag can't be
+    /// undone from under the pointer. Returns whether anything changed.
+    pub fn handle_shortcuts(&mut self, state: &mut S) -> bool {
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        if !ctrl || self.is_grouping() || !is_key_pressed(KeyCode::Z) {
+            return false;
+        }
+        if is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) {
+            self.redo(state)
+        } else {
+            self.undo(state)
+        }
+    }
+}
+
+/// Edits of a [`SceneGraph`]. Commands for nodes that no longer exist do nothing.
+pub enum SceneCommand<T> {
+    Move { id: NodeId, from: Vec2, to: Vec2 },
+    Rotate { id: NodeId, from: f32, to: f32 },
+    Scale { id: NodeId, from: Vec2, to: Vec2 },
+    /// Record after adding the node. Undoing detaches it, keeping its id.
+    Create { id: NodeId, detached: Option<Detached<T>> },
+    /// Push to delete the node and its subtree.
+    Delete { id: NodeId, detached: Option<Detached<T>> },
+    /// Push with the new value; holds the other one, swapping on every step.
+    SetValue { id: NodeId, value: T },
+}
+
+impl<T> SceneCommand<T> {
+    pub fn create(id: NodeId) -> Self {
+        SceneCommand::Create { id, detached: None }
+    }
+
+    pub fn delete(id: NodeId) -> Self {
+        SceneCommand::Delete { id, detached: None }
+    }
+
+    pub fn set_value(id: NodeId, value: T) -> Self {
+        SceneCommand::SetValue { id, value }
+    }
+
+    /// A command for each part of the transform that differs, to record after
+    /// an edit such as a gizmo drag.
+    pub fn transform(id: NodeId, from: Transform, to: Transform) -> Vec<Self> {
+        let mut commands = Vec::new();
+        if from.position != to.position {
+            commands.push(SceneCommand::Move {
+                id,
+                from: from.position,
+                to: to.position,
+            });
+        }
+        if from.rotation != to.rotation {
+            commands.push(SceneCommand::Rotate {
+                id,
+                from: from.rotation,
+                to: to.rotation,
+            });
+        }
+        if from.scale != to.scale {
+            commands.push(SceneCommand::Scale {
+                id,
+                from: from.scale,
+                to: to.scale,
+            });
+        }
+        commands
+    }
+
+    fn step(&mut self, scene: &mut SceneGraph<T>, forward: bool) {
+        match self {
+            SceneCommand::Move { id, from, to } => {
+                let position = if forward { *to } else { *from };
+                scene.update_transform(*id, |transform| transform.position = position);
+            }
+            SceneCommand::Rotate { id, from, to } => {
+                let rotation = if forward { *to } else { *from };
+                scene.update_transform(*id, |transform| transform.rotation = rotation);
+            }
+            SceneCommand::Scale { id, from, to } => {
+                let scale = if forward { *to } else { *from };
+                scene.update_transform(*id, |transform| transform.scale = scale);
+            }
+            SceneCommand::Create { id, detached } => set_present(scene, *id, detached, forward),
+            SceneCommand::Delete { id, detached } => set_present(scene, *id, detached, !forward),
+            SceneCommand::SetValue { id, value } => {
+                if let Some(current) = scene.get_mut(*id) {
+                    std::mem::swap(current, value);
+                }
+            }
+        }
+    }
+}
+
+/// Restores or detaches a node, keeping the detached subtree in `detached`.
+fn set_present<T>(scene: &mut SceneGraph<T>, id: NodeId, detached: &mut Option<Detached<T>>, present: bool) {
+    if present {
+        if let Some(taken) = detached.take() {
+            *detached = scene.restore(taken).err();
+        }
+    } else if detached.is_none() {
+        *detached = scene.detach(id);
+    }
+}
+
+impl<T> Command<SceneGraph<T>> for SceneCommand<T> {
+    fn apply(&mut self, scene: &mut SceneGraph<T>) {
+        self.step(scene, true);
+    }
+
+    fn revert(&mut self, scene: &mut SceneGraph<T>) {
+        self.step(scene, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Small deterministic generator so failures reproduce from the seed.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound as u64) as usize
+        }
+
+        fn value(&mut self) -> f32 {
+            self.below(200) as f32 - 100.0
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct NodeState {
+        id: NodeId,
+        value: u32,
+        local: Transform,
+        world: Mat3,
+        parent: Option<NodeId>,
+        children: Vec<NodeId>,
+    }
+
+    /// Everything observable about the graph, including sibling order and the
+    /// cached world matrices.
+    #[derive(Debug, PartialEq)]
+    struct Snapshot {
+        roots: Vec<NodeId>,
+        nodes: Vec<NodeState>,
+    }
+
+    fn snapshot(scene: &SceneGraph<u32>) -> Snapshot {
+        Snapshot {
+            roots: scene.roots().to_vec(),
+            nodes: scene
+                .ids()
+                .map(|id| NodeState {
+                    id,
+                    value: *scene.get(id).unwrap(),
+                    local: scene.transform(id).unwrap(),
+                    world: scene.world_matrix(id).unwrap(),
+                    parent: scene.parent(id),
+                    children: scene.children(id).to_vec(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Makes one random edit through `history`.
+    fn random_edit(rng: &mut Rng, scene: &mut SceneGraph<u32>, history: &mut History<SceneGraph<u32>>) {
+        let ids: Vec<NodeId> = scene.ids().collect();
+        let target = (!ids.is_empty()).then(|| ids[rng.below(ids.len())]);
+        let Some(id) = target.filter(|_| rng.below(6) != 0) else {
+            let value = rng.below(1000) as u32;
+            let local = Transform::new(vec2(rng.value(), rng.value()), 0.0, Vec2::ONE);
+            let id = match target {
+                Some(parent) if rng.below(2) == 0 => scene.add_child(parent, value, local).unwrap(),
+                _ => scene.add(value, local),
+            };
+            history.record(SceneCommand::create(id));
+            return;
+        };
+
+        let current = scene.transform(id).unwrap();
+        match rng.below(5) {
+            0 => {
+                let to = vec2(rng.value(), rng.value());
+                scene.set_transform(id, Transform { position: to, ..current });
+                history.record(SceneCommand::Move {
+                    id,
+                    from: current.position,
+                    to,
+                });
+            }
+            1 => history.push(
+                SceneCommand::Rotate {
+                    id,
+                    from: current.rotation,
+                    to: rng.value() / 10.0,
+                },
+                scene,
+            ),
+            2 => history.push(
+                SceneCommand::Scale {
+                    id,
+                    from: current.scale,
+                    to: vec2(rng.value(), rng.value()) / 50.0,
+                },
+                scene,
+            ),
+            3 => history.push(SceneCommand::delete(id), scene),
+            _ => history.push(SceneCommand::set_value(id, rng.below(1000) as u32), scene),
+        }
+    }
+
+    /// Makes a group of one or more edits, returning the snapshot after it.
+    fn random_group(rng: &mut Rng, scene: &mut SceneGraph<u32>, history: &mut History<SceneGraph<u32>>) -> Snapshot {
+        let edits = 1 + rng.below(4);
+        if edits > 1 {
+            history.begin_group();
+        }
+        for _ in 0..edits {
+            random_edit(rng, scene, history);
+        }
+        history.end_group();
+        snapshot(scene)
+    }
+
+    #[test]
+    fn undo_and_redo_restore_every_state() {
+        for seed in 1..=20u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let mut scene = SceneGraph::new();
+            let mut history = History::new(1000);
+            let mut states = vec![snapshot(&scene)];
+            for _ in 0..100 {
+                states.push(random_group(&mut rng, &mut scene, &mut history));
+            }
+
+            for expected in states.iter().rev().skip(1) {
+                assert!(history.undo(&mut scene));
+                assert_eq!(&snapshot(&scene), expected, "seed {}", seed);
+            }
+            assert!(!history.undo(&mut scene));
+
+            for expected in states.iter().skip(1) {
+                assert!(history.redo(&mut scene));
+                assert_eq!(&snapshot(&scene), expected, "seed {}", seed);
+            }
+            assert!(!history.redo(&mut scene));
+        }
+    }
+
+    #[test]
+    fn interleaved_edits_undos_and_redos_match_a_timeline() {
+        for seed in 1..=20u64 {
+            let mut rng = Rng(seed.wrapping_mul(0xD1B5_4A32_D192_ED03));
+            let mut scene = SceneGraph::new();
+            let mut history = History::new(1000);
+            // States reachable by undo and redo, and the one currently shown
+            let mut timeline = vec![snapshot(&scene)];
+            let mut current: usize = 0;
+            for _ in 0..300 {
+                match rng.below(4) {
+                    0 => {
+                        assert_eq!(history.undo(&mut scene), current > 0);
+                        current = current.saturating_sub(1);
+                    }
+                    1 => {
+                        assert_eq!(history.redo(&mut scene), current + 1 < timeline.len());
+                        current = (current + 1).min(timeline.len() - 1);
+                    }
+                    _ => {
+                        // A new edit discards whatever could have been redone
+                        timeline.truncate(current + 1);
+                        timeline.push(random_group(&mut rng, &mut scene, &mut history));
+                        current += 1;
+                    }
+                }
+                assert_eq!(snapshot(&scene), timeline[current], "seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn history_forgets_the_oldest_groups_past_its_limit() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        let mut scene = SceneGraph::new();
+        let mut history = History::new(10);
+        let mut states = vec![snapshot(&scene)];
+        for _ in 0..25 {
+            states.push(random_group(&mut rng, &mut scene, &mut history));
+        }
+
+        assert_eq!(history.len(), 10);
+        while history.undo(&mut scene) {}
+        assert_eq!(snapshot(&scene), states[15]);
+    }
+
+    #[test]
+    fn restored_nodes_keep_their_ids() {
+        let mut scene = SceneGraph::new();
+        let mut history = History::new(10);
+        let parent = scene.add(1, Transform::default());
+        let child = scene.add_child(parent, 2, Transform::default()).unwrap();
+        let sibling = scene.add_child(parent, 3, Transform::default()).unwrap();
+
+        history.push(SceneCommand::delete(parent), &mut scene);
+        assert!(!scene.contains(child));
+        history.undo(&mut scene);
+        assert_eq!(scene.get(child), Some(&2));
+        assert_eq!(scene.children(parent), &[child, sibling]);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::aseprite::{AnimationPlayer, SpriteDrawParams};
+use macroquad::prelude::Vec2;
+use rapier2d::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HitboxKind {
+    /// Deals hits.
+    Hitbox,
+    /// Receives hits.
+    Hurtbox,
+}
+
+impl HitboxKind {
+    /// Slices named `hitbox`/`hurtbox`, optionally with a suffix such as
+    /// `hitbox_sword`, become sensors.
+    pub fn from_slice_name(name: &str) -> Option<Self> {
+        if name.starts_with("hitbox") {
+            Some(HitboxKind::Hitbox)
+        } else if name.starts_with("hurtbox") {
+            Some(HitboxKind::Hurtbox)
+        } else {
+            None
+        }
+    }
+}
+
+struct SliceSensor {
+    slice: String,
+    kind: HitboxKind,
+    collider: ColliderHandle,
+}
+
+/// Sensor colliders on a rigid body that follow an animation's hitbox and
+/// hurtbox slices frame by frame.
+pub struct Hitboxes {
+    body: RigidBodyHandle,
+    draw_offset: Vec2,
+    sensors: Vec<SliceSensor>,
+}
+
+impl Hitboxes {
+    /// Creates one sensor per hitbox/hurtbox slice of `animation`, attached to
+    /// `body`. `draw_offset` is where the sprite is drawn relative to the body.
+    pub fn new(
+        animation: &AnimationPlayer,
+        body: RigidBodyHandle,
+        draw_offset: Vec2,
+        rigid_body_set: &mut RigidBodySet,
+        collider_set: &mut ColliderSet,
+    ) -> Self {
+        let sensors = animation
+            .slices()
+            .iter()
+            .filter_map(|slice| {
+                let kind = HitboxKind::from_slice_name(&slice.name)?;
+                let collider = ColliderBuilder::cuboid(0.5, 0.5)
+                    .sensor(true)
+                    .density(0.0)
+                    .enabled(false)
+                    .build();
+                Some(SliceSensor {
+                    slice: slice.name.clone(),
+                    kind,
+                    collider: collider_set.insert_with_parent(collider, body, rigid_body_set),
+                })
+            })
+            .collect();
+
+        Hitboxes {
+            body,
+            draw_offset,
+            sensors,
+        }
+    }
+
+    pub fn body(&self) -> RigidBodyHandle {
+        self.body
+    }
+
+    /// Reshapes the sensors to the slices on the animation's current frame, placed
+    /// as the sprite is drawn with `params`. Sensors whose slice is absent on this
+    /// frame are disabled.
+    pub fn sync(&self, animation: &AnimationPlayer, params: &SpriteDrawParams, collider_set: &mut ColliderSet) {
+        let local = animation.local_matrix(params);
+        let scale = params.scale.abs();
+        for sensor in &self.sensors {
+            let Some(collider) = collider_set.get_mut(sensor.collider) else {
+                continue;
+            };
+            let Some(bounds) = animation.slice_bounds(&sensor.slice) else {
+                collider.set_enabled(false);
+                continue;
+            };
+            let center = self.draw_offset + local.transform_point2(bounds.center());
+            collider.set_enabled(true);
+            collider.set_shape(SharedShape::cuboid(bounds.w * scale.x / 2.0, bounds.h * scale.y / 2.0));
+            collider.set_position_wrt_parent(Isometry::new(vector![center.x, center.y], params.rotation));
+        }
+    }
+
+    /// Removes the sensors from the collider set.
+    pub fn remove(
+        self,
+        collider_set: &mut ColliderSet,
+        island_manager: &mut IslandManager,
+        rigid_body_set: &mut RigidBodySet,
+    ) {
+        for sensor in self.sensors {
+            collider_set.remove(sensor.collider, island_manager, rigid_body_set, false);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitPhase {
+    Started,
+    Ended,
+}
+
+/// A hitbox of one body overlapping a hurtbox of another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HitEvent {
+    pub phase: HitPhase,
+    pub attacker: RigidBodyHandle,
+    pub target: RigidBodyHandle,
+    pub hitbox: String,
+    pub hurtbox: String,
+}
+
+/// Turns sensor overlaps between [`Hitboxes`] into [`HitEvent`]s.
+#[derive(Default)]
+pub struct HitDetector {
+    overlaps: HashSet<(ColliderHandle, ColliderHandle)>,
+}
+
+impl HitDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call after each physics step; reports overlaps that started or ended since
+    /// the previous call.
+    pub fn detect(&mut self, hitboxes: &[&Hitboxes], narrow_phase: &NarrowPhase) -> Vec<HitEvent> {
+        let owners: HashMap<ColliderHandle, (&Hitboxes, &SliceSensor)> = hitboxes
+            .iter()
+            .flat_map(|owner| owner.sensors.iter().map(move |sensor| (sensor.collider, (*owner, sensor))))
+            .collect();
+
+        let overlaps: HashSet<(ColliderHandle, ColliderHandle)> = narrow_phase
+            .intersection_pairs()
+            .filter(|(_, _, intersecting)| *intersecting)
+            .filter_map(|(collider1, collider2, _)| {
+                let (owner1, sensor1) = owners.get(&collider1)?;
+                let (owner2, sensor2) = owners.get(&collider2)?;
+                if owner1.body == owner2.body {
+                    return None;
+                }
+                match (sensor1.kind, sensor2.kind) {
+                    (HitboxKind::Hitbox, HitboxKind::Hurtbox) => Some((collider1, collider2)),
+                    (HitboxKind::Hurtbox, HitboxKind::Hitbox) => Some((collider2, collider1)),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        let event = |phase, (hitbox, hurtbox): &(ColliderHandle, ColliderHandle)| {
+            let (attacker, hitbox) = owners.get(hitbox)?;
+            let (target, hurtbox) = owners.get(hurtbox)?;
+            Some(HitEvent {
+                phase,
+                attacker: attacker.body,
+                target: target.body,
+                hitbox: hitbox.slice.clone(),
+                hurtbox: hurtbox.slice.clone(),
+            })
+        };
+        let mut events: Vec<HitEvent> = overlaps
+            .difference(&self.overlaps)
+            .filter_map(|pair| event(HitPhase::Started, pair))
+            .collect();
+        events.extend(
+            self.overlaps
+                .difference(&overlaps)
+                .filter_map(|pair| event(HitPhase::Ended, pair)),
+        );
+
+        self.overlaps = overlaps;
+        events
+    }
+}
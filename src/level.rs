@@ -0,0 +1,533 @@
+use crate::history::Command;
+use macroquad::prelude::*;
+use macroquad_tiled as tiled;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map as JsonMap, Value};
+use std::collections::HashMap;
+
+/// A Tiled JSON map as an editable document. Only what the editor touches is
+/// modelled; every other field is kept as is, so saving writes back everything
+/// Tiled wrote.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Level {
+    pub width: u32,
+    pub height: u32,
+    pub tilewidth: u32,
+    pub tileheight: u32,
+    pub layers: Vec<LevelLayer>,
+    #[serde(default)]
+    pub nextlayerid: u32,
+    #[serde(default)]
+    pub nextobjectid: u32,
+    #[serde(flatten)]
+    pub extra: JsonMap<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelLayer {
+    #[serde(default)]
+    pub id: u32,
+    pub name: String,
+    /// `"tilelayer"`, `"objectgroup"`, `"imagelayer"` or `"group"`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Global tile ids, row by row; 0 is empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Vec<u32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub objects: Option<Vec<LevelObject>>,
+    #[serde(flatten)]
+    pub extra: JsonMap<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelObject {
+    pub id: u32,
+    #[serde(default)]
+    pub name: String,
+    /// What the game spawns for it, e.g. `"spawn"` or `"platform"`.
+    #[serde(rename = "type", default)]
+    pub kind: String,
+    #[serde(serialize_with = "whole_numbers")]
+    pub x: f32,
+    #[serde(serialize_with = "whole_numbers")]
+    pub y: f32,
+    #[serde(default, serialize_with = "whole_numbers")]
+    pub width: f32,
+    #[serde(default, serialize_with = "whole_numbers")]
+    pub height: f32,
+    #[serde(default, serialize_with = "whole_numbers")]
+    pub rotation: f32,
+    #[serde(default = "visible_by_default")]
+    pub visible: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<LevelProperty>,
+    #[serde(flatten)]
+    pub extra: JsonMap<String, Value>,
+}
+
+/// Writes whole numbers without a fraction, as Tiled does.
+fn whole_numbers<S: serde::Serializer>(value: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f32 {
+        serializer.serialize_i64(*value as i64)
+    } else {
+        serializer.serialize_f32(*value)
+    }
+}
+
+fn visible_by_default() -> bool {
+    true
+}
+
+/// A Tiled custom property. Values set by the editor keep the property's type,
+/// or for new properties are numbers or bools when they read as one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelProperty {
+    pub name: String,
+    #[serde(rename = "type", default = "string_type")]
+    pub kind: String,
+    pub value: Value,
+}
+
+fn string_type() -> String {
+    "string".to_string()
+}
+
+/// A property's type and value for `text`: as `kind` if the text fits it, else
+/// an int, float or bool if it reads as one, else a string.
+fn typed_value(kind: Option<&str>, text: &str) -> (String, Value) {
+    let int = || text.parse::<i64>().ok().map(Value::from);
+    let float = || text.parse::<f64>().ok().filter(|value| value.is_finite()).map(Value::from);
+    let bool = || text.parse::<bool>().ok().map(Value::from);
+    let fitted = match kind {
+        Some(kind @ ("int" | "object")) => int().map(|value| (kind, value)),
+        Some("float") => float().map(|value| ("float", value)),
+        Some("bool") => bool().map(|value| ("bool", value)),
+        Some(kind @ ("string" | "color" | "file")) => Some((kind, Value::from(text))),
+        _ => None,
+    };
+    let (kind, value) = fitted
+        .or_else(|| int().map(|value| ("int", value)))
+        .or_else(|| float().map(|value| ("float", value)))
+        .or_else(|| bool().map(|value| ("bool", value)))
+        .unwrap_or(("string", Value::from(text)));
+    (kind.to_string(), value)
+}
+
+impl LevelObject {
+    pub fn new(kind: &str, rect: Rect) -> Self {
+        LevelObject {
+            id: 0,
+            name: String::new(),
+            kind: kind.to_string(),
+            x: rect.x,
+            y: rect.y,
+            width: rect.w,
+            height: rect.h,
+            rotation: 0.0,
+            visible: true,
+            properties: Vec::new(),
+            extra: JsonMap::new(),
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
+
+    /// The property's value, with non-string values written as JSON.
+    pub fn property(&self, name: &str) -> Option<String> {
+        let property = self.properties.iter().find(|property| property.name == name)?;
+        Some(match &property.value {
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        })
+    }
+
+    /// Sets a property from text, adding it if needed, and returns the old value.
+    /// An existing property keeps its type if the text fits it; otherwise the
+    /// type follows the text, as for a new property.
+    pub fn set_property(&mut self, name: &str, value: &str) -> Option<String> {
+        let old = self.property(name);
+        match self.properties.iter_mut().find(|property| property.name == name) {
+            Some(property) => {
+                let (kind, value) = typed_value(Some(&property.kind), value);
+                property.kind = kind;
+                property.value = value;
+            }
+            None => {
+                let (kind, value) = typed_value(None, value);
+                self.properties.push(LevelProperty {
+                    name: name.to_string(),
+                    kind,
+                    value,
+                });
+            }
+        }
+        old
+    }
+
+    pub fn remove_property(&mut self, name: &str) -> Option<String> {
+        let old = self.property(name);
+        self.properties.retain(|property| property.name != name);
+        old
+    }
+}
+
+impl Level {
+    pub async fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let json = load_string(path).await?;
+        Ok(Self::from_json(&json)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Tiled JSON, indented as Tiled writes it.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("levels always serialize")
+    }
+
+    /// Writes the level to disk; not available on the web.
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&LevelLayer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut LevelLayer> {
+        self.layers.iter_mut().find(|layer| layer.name == name)
+    }
+
+    /// The tile cell containing a point in map pixels.
+    pub fn cell_at(&self, point: Vec2) -> Option<(u32, u32)> {
+        let x = (point.x / self.tilewidth as f32).floor();
+        let y = (point.y / self.tileheight as f32).floor();
+        (x >= 0.0 && y >= 0.0 && x < self.width as f32 && y < self.height as f32).then_some((x as u32, y as u32))
+    }
+
+    /// The global tile id at a cell of a tile layer, 0 if empty.
+    pub fn tile(&self, layer: &str, x: u32, y: u32) -> Option<u32> {
+        let index = self.cell_index(x, y)?;
+        self.layer(layer)?.data.as_ref()?.get(index).copied()
+    }
+
+    /// Sets a cell's global tile id and returns the old one, or `None` if there's
+    /// no such cell.
+    pub fn set_tile(&mut self, layer: &str, x: u32, y: u32, gid: u32) -> Option<u32> {
+        let index = self.cell_index(x, y)?;
+        let cell = self.layer_mut(layer)?.data.as_mut()?.get_mut(index)?;
+        Some(std::mem::replace(cell, gid))
+    }
+
+    fn cell_index(&self, x: u32, y: u32) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| (y * self.width + x) as usize)
+    }
+
+    pub fn objects(&self, layer: &str) -> &[LevelObject] {
+        self.layer(layer).and_then(|layer| layer.objects.as_deref()).unwrap_or(&[])
+    }
+
+    pub fn object(&self, layer: &str, id: u32) -> Option<&LevelObject> {
+        self.objects(layer).iter().find(|object| object.id == id)
+    }
+
+    pub fn object_mut(&mut self, layer: &str, id: u32) -> Option<&mut LevelObject> {
+        self.layer_mut(layer)?.objects.as_mut()?.iter_mut().find(|object| object.id == id)
+    }
+
+    /// Adds an object on top of the layer with a new id, which is returned.
+    pub fn add_object(&mut self, layer: &str, mut object: LevelObject) -> Option<u32> {
+        let id = self.nextobjectid.max(1);
+        let objects = self.layer_mut(layer)?.objects.as_mut()?;
+        object.id = id;
+        objects.push(object);
+        self.nextobjectid = id + 1;
+        Some(id)
+    }
+
+    /// Removes an object, returning it and its position in the layer.
+    pub fn remove_object(&mut self, layer: &str, id: u32) -> Option<(usize, LevelObject)> {
+        let objects = self.layer_mut(layer)?.objects.as_mut()?;
+        let index = objects.iter().position(|object| object.id == id)?;
+        Some((index, objects.remove(index)))
+    }
+
+    /// Puts a removed object back where it was.
+    pub fn insert_object(&mut self, layer: &str, index: usize, object: LevelObject) -> bool {
+        let Some(objects) = self.layer_mut(layer).and_then(|layer| layer.objects.as_mut()) else {
+            return false;
+        };
+        objects.insert(index.min(objects.len()), object);
+        true
+    }
+
+    /// Copies tiles and objects into a map loaded from this level, keeping its
+    /// tileset textures, including atlas-packed ones.
+    pub fn sync(&self, map: &mut tiled::Map) {
+        // Tilesets in their own files aren't resolved in the raw map, so only
+        // embedded ones can be found by gid
+        let tilesets = map.raw_tiled_map.tilesets.clone();
+        for layer in &self.layers {
+            let Some(target) = map.layers.get_mut(&layer.name) else {
+                continue;
+            };
+            if let Some(data) = &layer.data {
+                target.data = data
+                    .iter()
+                    .map(|gid| {
+                        let tileset = tilesets
+                            .iter()
+                            .find(|tileset| *gid >= tileset.firstgid && *gid < tileset.firstgid + tileset.tilecount)?;
+                        let id = gid - tileset.firstgid;
+                        Some(tiled::Tile {
+                            id,
+                            tileset: tileset.name.clone(),
+                            attrs: tileset
+                                .tiles
+                                .iter()
+                                .find(|tile| tile.id as u32 == id)
+                                .and_then(|tile| tile.ty.clone())
+                                .unwrap_or_default(),
+                        })
+                    })
+                    .collect();
+            }
+            if let Some(objects) = &layer.objects {
+                let tile_size = vec2(self.tilewidth as f32, self.tileheight as f32);
+                target.objects = objects
+                    .iter()
+                    .map(|object| tiled::Object {
+                        gid: object.extra.get("gid").and_then(Value::as_u64).map(|gid| gid as u32),
+                        world_x: object.x,
+                        world_y: object.y,
+                        world_w: object.width,
+                        world_h: object.height,
+                        tile_x: (object.x / tile_size.x) as u32,
+                        tile_y: (object.y / tile_size.y) as u32,
+                        tile_w: (object.width / tile_size.x) as u32,
+                        tile_h: (object.height / tile_size.y) as u32,
+                        name: object.name.clone(),
+                        properties: object
+                            .properties
+                            .iter()
+                            .filter_map(|property| Some((property.name.clone(), object.property(&property.name)?)))
+                            .collect::<HashMap<_, _>>(),
+                    })
+                    .collect();
+            }
+        }
+    }
+}
+
+/// Converts Tiled JSON to what `macroquad_tiled::load_map` accepts: object
+/// layers are `"objectlayer"` rather than Tiled's `"objectgroup"`, layer
+/// properties a name to value map, and every property value a string.
+pub fn to_macroquad_json(json: &str) -> Result<String, serde_json::Error> {
+    let mut map: Value = serde_json::from_str(json)?;
+    stringify_properties(&mut map);
+    for layer in map.get_mut("layers").and_then(Value::as_array_mut).into_iter().flatten() {
+        if layer["type"] == "objectgroup" {
+            layer["type"] = Value::from("objectlayer");
+        }
+        stringify_properties(layer);
+        if let Some(properties) = layer.get("properties").and_then(Value::as_array) {
+            let properties: JsonMap<String, Value> = properties
+                .iter()
+                .filter_map(|property| Some((property["name"].as_str()?.to_string(), property["value"].clone())))
+                .collect();
+            layer["properties"] = Value::Object(properties);
+        }
+        for object in layer.get_mut("objects").and_then(Value::as_array_mut).into_iter().flatten() {
+            stringify_properties(object);
+        }
+    }
+    for tileset in map.get_mut("tilesets").and_then(Value::as_array_mut).into_iter().flatten() {
+        stringify_properties(tileset);
+        for tile in tileset.get_mut("tiles").and_then(Value::as_array_mut).into_iter().flatten() {
+            stringify_properties(tile);
+        }
+    }
+    serde_json::to_string(&map)
+}
+
+/// Rewrites the values in a Tiled `properties` array as strings.
+fn stringify_properties(owner: &mut Value) {
+    for property in owner
+        .get_mut("properties")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+    {
+        let value = &mut property["value"];
+        if !value.is_string() {
+            *value = Value::String(value.to_string());
+        }
+    }
+}
+
+/// An undoable edit of a [`Level`], for [`History`](crate::history::History).
+pub enum LevelCommand {
+    SetTile {
+        layer: String,
+        x: u32,
+        y: u32,
+        from: u32,
+        to: u32,
+    },
+    MoveObject {
+        layer: String,
+        id: u32,
+        from: Vec2,
+        to: Vec2,
+    },
+    /// Record after adding the object.
+    AddObject {
+        layer: String,
+        id: u32,
+        removed: Option<(usize, LevelObject)>,
+    },
+    /// Push to remove the object.
+    RemoveObject {
+        layer: String,
+        id: u32,
+        removed: Option<(usize, LevelObject)>,
+    },
+    /// Push with the new value, `None` to remove the property; holds the other
+    /// value, swapping on every step.
+    SetProperty {
+        layer: String,
+        id: u32,
+        name: String,
+        value: Option<String>,
+    },
+}
+
+impl LevelCommand {
+    fn step(&mut self, level: &mut Level, forward: bool) {
+        match self {
+            LevelCommand::SetTile { layer, x, y, from, to } => {
+                level.set_tile(layer, *x, *y, if forward { *to } else { *from });
+            }
+            LevelCommand::MoveObject { layer, id, from, to } => {
+                let position = if forward { *to } else { *from };
+                if let Some(object) = level.object_mut(layer, *id) {
+                    object.x = position.x;
+                    object.y = position.y;
+                }
+            }
+            LevelCommand::AddObject { layer, id, removed } => set_present(level, layer, *id, removed, forward),
+            LevelCommand::RemoveObject { layer, id, removed } => set_present(level, layer, *id, removed, !forward),
+            LevelCommand::SetProperty { layer, id, name, value } => {
+                if let Some(object) = level.object_mut(layer, *id) {
+                    let old = match value.take() {
+                        Some(new) => object.set_property(name, &new),
+                        None => object.remove_property(name),
+                    };
+                    *value = old;
+                }
+            }
+        }
+    }
+}
+
+fn set_present(level: &mut Level, layer: &str, id: u32, removed: &mut Option<(usize, LevelObject)>, present: bool) {
+    if present {
+        if let Some((index, object)) = removed.take() {
+            level.insert_object(layer, index, object);
+        }
+    } else if removed.is_none() {
+        *removed = level.remove_object(layer, id);
+    }
+}
+
+impl Command<Level> for LevelCommand {
+    fn apply(&mut self, level: &mut Level) {
+        self.step(level, true);
+    }
+
+    fn revert(&mut self, level: &mut Level) {
+        self.step(level, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::miniquad::{RawId, TextureId};
+
+    fn platform(level: &mut Level) -> &mut LevelObject {
+        let objects = level.layer_mut("objects").and_then(|layer| layer.objects.as_mut()).unwrap();
+        objects.iter_mut().find(|object| object.kind == "platform").unwrap()
+    }
+
+    #[test]
+    fn set_property_keeps_or_infers_types() {
+        let mut object = LevelObject::new("platform", Rect::new(0.0, 0.0, 32.0, 8.0));
+        object.properties.push(LevelProperty {
+            name: "label".to_string(),
+            kind: string_type(),
+            value: Value::from("a"),
+        });
+        object.properties.push(LevelProperty {
+            name: "speed".to_string(),
+            kind: "float".to_string(),
+            value: Value::from(50.0),
+        });
+
+        object.set_property("label", "12");
+        object.set_property("speed", "75");
+        object.set_property("count", "3");
+        object.set_property("solid", "true");
+        object.set_property("title", "ledge");
+        let kinds: Vec<(&str, &str, &Value)> = object
+            .properties
+            .iter()
+            .map(|property| (property.name.as_str(), property.kind.as_str(), &property.value))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("label", "string", &Value::from("12")),
+                ("speed", "float", &Value::from(75.0)),
+                ("count", "int", &Value::from(3)),
+                ("solid", "bool", &Value::from(true)),
+                ("title", "string", &Value::from("ledge")),
+            ]
+        );
+
+        // Text that doesn't fit the old type changes it
+        object.set_property("speed", "fast");
+        assert_eq!(object.properties[1].kind, "string");
+    }
+
+    #[test]
+    fn edited_map_loads_in_macroquad_tiled() {
+        let json = std::fs::read_to_string("assets/map.json").unwrap();
+        let mut level = Level::from_json(&json).unwrap();
+        platform(&mut level).set_property("speed", "80");
+        platform(&mut level).set_property("delay", "0.5");
+
+        let path = std::env::temp_dir().join("level_round_trip.json");
+        let path = path.to_str().unwrap();
+        level.save(path).unwrap();
+        let saved = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let mut reloaded = Level::from_json(&saved).unwrap();
+        assert_eq!(reloaded, level);
+        let speed = platform(&mut reloaded).properties.iter().find(|property| property.name == "speed").unwrap();
+        assert_eq!((speed.kind.as_str(), &speed.value), ("float", &Value::from(80.0)));
+
+        // An unmanaged texture needs no graphics context, and isn't drawn here
+        let texture = Texture2D::from_miniquad_texture(TextureId::from_raw_id(RawId::OpenGl(0)));
+        let map = tiled::load_map(&to_macroquad_json(&saved).unwrap(), &[("tileset.png", texture)], &[]).unwrap();
+        let objects = &map.layers["objects"].objects;
+        let platform = objects.iter().find(|object| object.properties.contains_key("delay")).unwrap();
+        assert_eq!(platform.properties["speed"], "80.0");
+        assert_eq!(platform.properties["delay"], "0.5");
+    }
+}
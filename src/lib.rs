@@ -0,0 +1,21 @@
+pub mod animator;
+pub mod aseprite;
+pub mod assets;
+pub mod atlas;
+pub mod editor;
+pub mod flowfield;
+pub mod formation;
+pub mod gizmo;
+pub mod history;
+pub mod hitbox;
+pub mod level;
+pub mod material;
+pub mod orders;
+pub mod pathfinding;
+pub mod picking;
+pub mod postprocess;
+pub mod scene;
+pub mod spatial;
+pub mod steering;
+pub mod transform;
+pub mod tween;
@@ -1,5 +1,15 @@
 use macroquad::prelude::*;
+use macroquad_test::animator::{Animator, AnimatorConfig};
+use macroquad_test::aseprite::{AnimationPlayer, SpriteDrawParams, SpriteSheet};
+use macroquad_test::assets::Assets;
+use macroquad_test::atlas::AtlasOptions;
+use macroquad_test::hitbox::{HitDetector, HitPhase, Hitboxes};
+use macroquad_test::material::{MaterialRegistry, UniformValue};
+use macroquad_test::picking::{BodyDrag, ColliderPick, PickEvent, Pickable, Picker, SpriteMask, SpritePick, ZOrder};
+use macroquad_test::tween::{Ease, Motion, TweenEvent, Tweens};
 use rapier2d::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 const PLAYER_SIZE: f32 = 30.0;
 const JUMP_FORCE: f32 = 100000.0;
@@ -7,12 +17,23 @@ const MOVE_SPEED: f32 = 100.0;
 const PLATFORM_WIDTH: f32 = 100.0;
 const PLATFORM_HEIGHT: f32 = 20.0;
 const PLATFORM_SPEED: f32 = 500.0;
+const PLAYER_SPRITE_SCALE: f32 = 1.5;
+const HIT_FLASH_TIME: f32 = 0.2;
 
 struct Player {
     body: RigidBodyHandle,
     collider: ColliderHandle,
     is_on_platform: bool,
     platform_velocity: Vector<Real>,
+    facing_left: bool,
+}
+
+/// A target standing on the ground that flashes when the player's hitbox hits it.
+struct Dummy {
+    body: RigidBodyHandle,
+    animation: AnimationPlayer,
+    hitboxes: Hitboxes,
+    flash_time: f32,
 }
 
 struct MovingPlatform {
@@ -23,6 +44,33 @@ struct MovingPlatform {
     direction: f32,
 }
 
+/// Things the mouse can pick up and drag around.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Grabbable {
+    Player,
+    Dummy,
+    Platform(usize),
+}
+
+impl Grabbable {
+    /// As passed to scripts.
+    fn name(self) -> &'static str {
+        match self {
+            Grabbable::Player => "player",
+            Grabbable::Dummy => "dummy",
+            Grabbable::Platform(_) => "platform",
+        }
+    }
+
+    fn body(self, player: &Player, dummy: &Dummy, platforms: &[MovingPlatform]) -> RigidBodyHandle {
+        match self {
+            Grabbable::Player => player.body,
+            Grabbable::Dummy => dummy.body,
+            Grabbable::Platform(index) => platforms[index].body,
+        }
+    }
+}
+
 struct PhysicsContext {
     gravity: Vector<Real>,
     integration_parameters: IntegrationParameters,
@@ -79,6 +127,22 @@ fn setup_physics() -> (RigidBodySet, ColliderSet, PhysicsContext) {
     (rigid_body_set, collider_set, physics_context)
 }
 
+/// True when the player rests on something: a contact whose normal points down
+/// from the player's collider.
+fn is_grounded(narrow_phase: &NarrowPhase, collider: ColliderHandle) -> bool {
+    narrow_phase.contacts_with(collider).any(|pair| {
+        pair.has_any_active_contact
+            && pair.manifolds.iter().any(|manifold| {
+                let normal = if pair.collider1 == collider {
+                    manifold.data.normal
+                } else {
+                    -manifold.data.normal
+                };
+                normal.y > 0.5
+            })
+    })
+}
+
 fn setup_player(rigid_body_set: &mut RigidBodySet, collider_set: &mut ColliderSet) -> Player {
     let player_body = RigidBodyBuilder::dynamic()
         .translation(vector![screen_width() / 2.0, screen_height() / 2.0])
@@ -98,6 +162,37 @@ fn setup_player(rigid_body_set: &mut RigidBodySet, collider_set: &mut ColliderSe
         collider: player_collider_handle,
         is_on_platform: false,
         platform_velocity: vector![0.0, 0.0],
+        facing_left: false,
+    }
+}
+
+/// Sprites are drawn with their feet on the bottom of the body's collider.
+fn sprite_offset() -> Vec2 {
+    vec2(0.0, PLAYER_SIZE / 2.0)
+}
+
+fn sprite_params(flip_x: bool) -> SpriteDrawParams {
+    SpriteDrawParams {
+        scale: Vec2::splat(PLAYER_SPRITE_SCALE),
+        anchor: vec2(0.5, 1.0),
+        flip_x,
+        ..Default::default()
+    }
+}
+
+fn setup_dummy(sheet: Rc<SpriteSheet>, rigid_body_set: &mut RigidBodySet, collider_set: &mut ColliderSet) -> Dummy {
+    let mut animation = AnimationPlayer::new(sheet);
+    animation.play_tag("idle");
+    let dummy_body = RigidBodyBuilder::fixed()
+        .translation(vector![screen_width() * 0.75, screen_height() - 20.0 - PLAYER_SIZE / 2.0])
+        .build();
+    let body = rigid_body_set.insert(dummy_body);
+    let hitboxes = Hitboxes::new(&animation, body, sprite_offset(), rigid_body_set, collider_set);
+    Dummy {
+        body,
+        animation,
+        hitboxes,
+        flash_time: 0.0,
     }
 }
 
@@ -135,28 +230,154 @@ fn text(s: rhai::ImmutableString, x: f32, y: f32, size: f32, color: Color) {
 }
 
 
-fn setup_rhai() -> rhai::Engine {
+fn setup_rhai(
+    animator: Rc<RefCell<Animator>>,
+    materials: Rc<RefCell<MaterialRegistry>>,
+    tweens: Rc<RefCell<Tweens>>,
+) -> rhai::Engine {
     let mut engine = rhai::Engine::new();
     engine.register_fn("text", text);
     engine.register_fn("fps", get_fps);
     engine.register_fn("screen_width",  screen_width);
     engine.register_fn("screen_height",  screen_height);
+
+    let player_animator = animator.clone();
+    engine.register_fn("player_animator", move |config: rhai::Map| -> Result<(), Box<rhai::EvalAltResult>> {
+        let config: AnimatorConfig = rhai::serde::from_dynamic(&config.into())?;
+        let new_animator = Animator::new(config).map_err(|err| err.to_string())?;
+        *player_animator.borrow_mut() = new_animator;
+        Ok(())
+    });
+    let anim_float = animator.clone();
+    engine.register_fn("anim_float", move |name: &str, value: f32| anim_float.borrow_mut().set_float(name, value));
+    let anim_bool = animator.clone();
+    engine.register_fn("anim_bool", move |name: &str, value: bool| anim_bool.borrow_mut().set_bool(name, value));
+    let anim_trigger = animator.clone();
+    engine.register_fn("anim_trigger", move |name: &str| anim_trigger.borrow_mut().set_trigger(name));
+    engine.register_fn("anim_state", move || animator.borrow().state().to_string());
+
+    // Materials: select one for the following draws, then tweak its declared uniforms
+    let use_material = materials.clone();
+    engine.register_fn("use_material", move |name: &str| -> Result<(), Box<rhai::EvalAltResult>> {
+        if use_material.borrow().apply(name) {
+            Ok(())
+        } else {
+            Err(format!("no usable material named {:?}", name).into())
+        }
+    });
+    engine.register_fn("reset_material", gl_use_default_material);
+    let set_uniform = |materials: &Rc<RefCell<MaterialRegistry>>, material: &str, uniform: &str, value| {
+        materials
+            .borrow_mut()
+            .set_uniform(material, uniform, value)
+            .map_err(Box::<rhai::EvalAltResult>::from)
+    };
+    let material_float = materials.clone();
+    engine.register_fn("material_float", move |material: &str, uniform: &str, value: f32| {
+        set_uniform(&material_float, material, uniform, UniformValue::Float(value))
+    });
+    let material_vec2 = materials.clone();
+    engine.register_fn("material_vec2", move |material: &str, uniform: &str, x: f32, y: f32| {
+        set_uniform(&material_vec2, material, uniform, UniformValue::Vec2(vec2(x, y)))
+    });
+    let material_vec4 = materials.clone();
+    engine.register_fn(
+        "material_vec4",
+        move |material: &str, uniform: &str, x: f32, y: f32, z: f32, w: f32| {
+            set_uniform(&material_vec4, material, uniform, UniformValue::Vec4(vec4(x, y, z, w)))
+        },
+    );
+    let material_color = materials.clone();
+    engine.register_fn("material_color", move |material: &str, uniform: &str, color: Color| {
+        set_uniform(&material_color, material, uniform, UniformValue::Vec4(color.to_vec()))
+    });
+    let material_texture = materials.clone();
+    engine.register_fn("material_texture", move |material: &str, sampler: &str, path: &str| {
+        material_texture
+            .borrow_mut()
+            .set_texture_path(material, sampler, path)
+            .map_err(Box::<rhai::EvalAltResult>::from)
+    });
+    engine.register_fn("material_reset", move |material: &str| -> Result<(), Box<rhai::EvalAltResult>> {
+        if materials.borrow_mut().reset_uniforms(material) {
+            Ok(())
+        } else {
+            Err(format!("no material named {:?}", material).into())
+        }
+    });
+
+    // Tweens: scripts start motions and read back the animated values
+    let tween = tweens.clone();
+    engine.register_fn(
+        "tween",
+        move |property: &str, from: f32, to: f32, duration: f32, ease: &str| -> Result<rhai::INT, Box<rhai::EvalAltResult>> {
+            let ease: Ease = rhai::serde::from_dynamic(&ease.into())?;
+            let id = tween.borrow_mut().play(Motion::tween(property, from, to, duration, ease));
+            Ok(id.to_bits() as rhai::INT)
+        },
+    );
+    let play_tween = tweens.clone();
+    engine.register_fn("play_tween", move |motion: rhai::Map| -> Result<rhai::INT, Box<rhai::EvalAltResult>> {
+        let motion: Motion = rhai::serde::from_dynamic(&motion.into())?;
+        Ok(play_tween.borrow_mut().play(motion).to_bits() as rhai::INT)
+    });
+    let stop_tween = tweens.clone();
+    engine.register_fn("stop_tween", move |property: &str| stop_tween.borrow_mut().stop_property(property));
+    engine.register_fn("tween_float", move |property: &str, default: f32| {
+        tweens.borrow().float(property).unwrap_or(default)
+    });
     engine
 }
 
 
 #[macroquad::main("Platformer")]
-async fn main() -> Result<(), Box<rhai::EvalAltResult>>  {
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut assets = Assets::new();
+    let player_sheet = assets.sprite_sheet_with_events("assets/player.json", "assets/player_events.json");
+    let dummy_sheet = assets.sprite_sheet("assets/skeletron.json");
+    let script = assets.script("script/input.rhai");
+    assets.load_all().await?;
+    assets.pack_atlas(AtlasOptions::default())?;
+
     let (mut rigid_body_set, mut collider_set, mut physics_context) = setup_physics();
     let mut player = setup_player(&mut rigid_body_set, &mut collider_set);
     let mut platforms = setup_platforms(&mut rigid_body_set, &mut collider_set);
-    let mut engine = setup_rhai();
+
+    let mut player_animation = AnimationPlayer::new(assets.try_get(player_sheet)?.clone());
+    let player_animator = Rc::new(RefCell::new(Animator::load("assets/player_animator.json").await?));
+    let player_hitboxes = Hitboxes::new(&player_animation, player.body, sprite_offset(), &mut rigid_body_set, &mut collider_set);
+    let mut dummy = setup_dummy(assets.try_get(dummy_sheet)?.clone(), &mut rigid_body_set, &mut collider_set);
+    let mut hit_detector = HitDetector::new();
+
+    // Sprites and platforms can be picked up with the mouse; the grabbed one draws on top
+    let player_mask = SpriteMask::new(assets.try_get(player_sheet)?);
+    let dummy_mask = SpriteMask::new(assets.try_get(dummy_sheet)?);
+    let mut z_order = ZOrder::new();
+    for index in 0..platforms.len() {
+        z_order.push(Grabbable::Platform(index));
+    }
+    z_order.push(Grabbable::Dummy);
+    z_order.push(Grabbable::Player);
+    let mut picker = Picker::new();
+    let mut body_drag: Option<BodyDrag> = None;
+
+    let materials = Rc::new(RefCell::new(MaterialRegistry::load("assets/materials.json").await?));
+    let tweens = Rc::new(RefCell::new(Tweens::new()));
+    let mut engine = setup_rhai(player_animator.clone(), materials.clone(), tweens.clone());
     let mut scope = rhai::Scope::new();
     let state = rhai::Map::new();
     scope.push_constant("BLACK", BLACK)
     .push("state", state);
 
-    let source = load_string("script/input.rhai").await.unwrap();
+    let ast = engine.compile(assets.try_get(script)?.source.as_str())?;
+    let has_function = |name: &str| ast.iter_functions().any(|function| function.name == name);
+    let has_anim_event_handler = has_function("on_anim_event");
+    let has_tween_event_handler = has_function("on_tween_event");
+    let has_pick_event_handler = has_function("on_pick_event");
+    if has_function("init") {
+        let options = rhai::CallFnOptions::new().eval_ast(false);
+        engine.call_fn_with_options::<()>(options, &mut scope, &ast, "init", ())?;
+    }
 
     loop {
         // Render
@@ -170,13 +391,60 @@ async fn main() -> Result<(), Box<rhai::EvalAltResult>>  {
         if is_key_down(KeyCode::Right) {
             x_movement += MOVE_SPEED;
         }
+        if x_movement != 0.0 {
+            player.facing_left = x_movement < 0.0;
+        }
         if is_key_pressed(KeyCode::Space) {
             if let Some(player_body) = rigid_body_set.get_mut(player.body) {
                 player_body.apply_impulse(vector![0.0, -JUMP_FORCE], true);
             }
+            player_animator.borrow_mut().set_trigger("jump");
+        }
+
+        // Hit test sprites as drawn last frame and platform colliders, topmost first
+        let mouse = Vec2::from(mouse_position());
+        let pick_events = picker
+            .update(mouse, |point| {
+                z_order.pick(|grabbable| {
+                    let sprite_pick = |body, player, mask, params: &SpriteDrawParams| {
+                        let position = rigid_body_set.get(body)?.translation();
+                        let world = Mat3::from_translation(vec2(position.x, position.y) + sprite_offset());
+                        SpritePick { mask, player, params, world }.pick(point)
+                    };
+                    match grabbable {
+                        Grabbable::Player => sprite_pick(
+                            player.body,
+                            &player_animation,
+                            &player_mask,
+                            &sprite_params(player.facing_left),
+                        ),
+                        Grabbable::Dummy => sprite_pick(dummy.body, &dummy.animation, &dummy_mask, &sprite_params(false)),
+                        Grabbable::Platform(index) => ColliderPick {
+                            colliders: &collider_set,
+                            handle: platforms[index].collider,
+                        }
+                        .pick(point),
+                    }
+                })
+            })
+            .to_vec();
+        for event in &pick_events {
+            match *event {
+                PickEvent::DragStart { id, point, .. } => {
+                    z_order.raise(id);
+                    body_drag = BodyDrag::new(&rigid_body_set, id.body(&player, &dummy, &platforms), point);
+                }
+                PickEvent::DragEnd { .. } => body_drag = None,
+                _ => {}
+            }
         }
 
-        engine.run_with_scope(&mut scope,source.as_str())?;
+        materials.borrow_mut().update();
+        // Advance tweens before the script reads their values
+        let tween_events = tweens.borrow_mut().update(get_frame_time());
+        engine.run_ast_with_scope(&mut scope, &ast)?;
+        // Scripts may leave a material selected
+        gl_use_default_material();
 
         // Reset player's platform state
         player.is_on_platform = false;
@@ -200,8 +468,9 @@ async fn main() -> Result<(), Box<rhai::EvalAltResult>>  {
             }
         }
 
-        // Apply movement to player
-        if let Some(player_body) = rigid_body_set.get_mut(player.body) {
+        // Apply movement to player, unless the drag spring is in control
+        let player_held = picker.drag().is_some_and(|drag| drag.id == Grabbable::Player);
+        if let Some(player_body) = rigid_body_set.get_mut(player.body).filter(|_| !player_held) {
             let mut new_velocity = vector![x_movement, player_body.linvel().y];
             if player.is_on_platform {
                 new_velocity += player.platform_velocity;
@@ -209,8 +478,14 @@ async fn main() -> Result<(), Box<rhai::EvalAltResult>>  {
             player_body.set_linvel(new_velocity, true);
         }
 
-        // Move platforms
-        for platform in &mut platforms {
+        // Move platforms, except one held by the mouse
+        for (index, platform) in platforms.iter_mut().enumerate() {
+            if picker.drag().is_some_and(|drag| drag.id == Grabbable::Platform(index)) {
+                if let Some(platform_body) = rigid_body_set.get_mut(platform.body) {
+                    platform_body.set_linvel(vector![0.0, 0.0], true);
+                }
+                continue;
+            }
             if let Some(platform_body) = rigid_body_set.get_mut(platform.body) {
                 let position = platform_body.translation();
                 if position.x < platform.start_x || position.x > platform.end_x {
@@ -220,8 +495,91 @@ async fn main() -> Result<(), Box<rhai::EvalAltResult>>  {
             }
         }
 
+        // The player is pulled by a spring; the dummy and platforms follow the mouse exactly
+        if let Some(drag) = &body_drag {
+            drag.update(&mut rigid_body_set, mouse, physics_context.integration_parameters.dt);
+        }
+
         update_physics(&mut rigid_body_set, &mut collider_set, &mut physics_context);
 
+        for hit in hit_detector.detect(&[&player_hitboxes, &dummy.hitboxes], &physics_context.narrow_phase) {
+            if hit.phase == HitPhase::Started && hit.target == dummy.body {
+                dummy.flash_time = HIT_FLASH_TIME;
+            }
+        }
+
+        // Feed gameplay state to the player's animation
+        if let Some(player_body) = rigid_body_set.get(player.body) {
+            let mut animator = player_animator.borrow_mut();
+            animator.set_float("speed", x_movement.abs());
+            animator.set_float("vertical_velocity", player_body.linvel().y);
+            animator.set_bool("grounded", is_grounded(&physics_context.narrow_phase, player.collider));
+            animator.update(&mut player_animation);
+        }
+        player_animation.update(get_frame_time());
+        player_hitboxes.sync(&player_animation, &sprite_params(player.facing_left), &mut collider_set);
+
+        dummy.flash_time = (dummy.flash_time - get_frame_time()).max(0.0);
+        dummy.animation.update(get_frame_time());
+        dummy.hitboxes.sync(&dummy.animation, &sprite_params(false), &mut collider_set);
+
+        // Scripts receive animation events with `state` bound as `this`
+        if has_anim_event_handler {
+            for event in player_animation.events() {
+                let mut this: rhai::Dynamic = scope.get_value("state").unwrap();
+                let options = rhai::CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut this);
+                engine.call_fn_with_options::<()>(
+                    options,
+                    &mut scope,
+                    &ast,
+                    "on_anim_event",
+                    (event.name.clone(), event.frame as rhai::INT),
+                )?;
+                scope.set_value("state", this);
+            }
+        }
+        // Hover and drag of the player, dummy and platforms, by name
+        if has_pick_event_handler {
+            for event in pick_events {
+                let (kind, grabbable) = match event {
+                    PickEvent::HoverEnter(id) => ("hover_enter", id),
+                    PickEvent::HoverLeave(id) => ("hover_leave", id),
+                    PickEvent::DragStart { id, .. } => ("drag_start", id),
+                    PickEvent::DragMove { id, .. } => ("drag_move", id),
+                    PickEvent::DragEnd { id, .. } => ("drag_end", id),
+                };
+                let mut this: rhai::Dynamic = scope.get_value("state").unwrap();
+                let options = rhai::CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut this);
+                engine.call_fn_with_options::<()>(
+                    options,
+                    &mut scope,
+                    &ast,
+                    "on_pick_event",
+                    (kind.to_string(), grabbable.name().to_string(), mouse.x, mouse.y),
+                )?;
+                scope.set_value("state", this);
+            }
+        }
+        // Named motion events and completions, the latter as "complete"
+        if has_tween_event_handler {
+            for event in tween_events {
+                let (name, tween) = match event {
+                    TweenEvent::Event { tween, name } => (name, tween),
+                    TweenEvent::Completed { tween } => ("complete".to_string(), tween),
+                };
+                let mut this: rhai::Dynamic = scope.get_value("state").unwrap();
+                let options = rhai::CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut this);
+                engine.call_fn_with_options::<()>(
+                    options,
+                    &mut scope,
+                    &ast,
+                    "on_tween_event",
+                    (name, tween.to_bits() as rhai::INT),
+                )?;
+                scope.set_value("state", this);
+            }
+        }
+
         // Draw ground
         draw_rectangle(
             0.0,
@@ -231,31 +589,32 @@ async fn main() -> Result<(), Box<rhai::EvalAltResult>>  {
             GRAY,
         );
 
-        // Draw platforms
-        for platform in &platforms {
-            if let Some(platform_body) = rigid_body_set.get(platform.body) {
-                let position = platform_body.translation();
-                draw_rectangle(
+        // Draw platforms, the player and dummy in pick order
+        for grabbable in z_order.iter() {
+            let Some(body) = rigid_body_set.get(grabbable.body(&player, &dummy, &platforms)) else {
+                continue;
+            };
+            let position = vec2(body.translation().x, body.translation().y);
+            match grabbable {
+                Grabbable::Player => player_animation.draw(position + sprite_offset(), &sprite_params(player.facing_left)),
+                Grabbable::Dummy => {
+                    let params = SpriteDrawParams {
+                        tint: if dummy.flash_time > 0.0 { RED } else { WHITE },
+                        ..sprite_params(false)
+                    };
+                    dummy.animation.draw(position + sprite_offset(), &params);
+                }
+                Grabbable::Platform(_) => draw_rectangle(
                     position.x - PLATFORM_WIDTH / 2.0,
                     position.y - PLATFORM_HEIGHT / 2.0,
                     PLATFORM_WIDTH,
                     PLATFORM_HEIGHT,
                     GREEN,
-                );
+                ),
             }
         }
 
-        // Draw player
-        if let Some(player_body) = rigid_body_set.get(player.body) {
-            let position = player_body.translation();
-            draw_rectangle(
-                position.x - PLAYER_SIZE / 2.0,
-                position.y - PLAYER_SIZE / 2.0,
-                PLAYER_SIZE,
-                PLAYER_SIZE,
-                BLUE,
-            );
-        }
+        materials.borrow().draw_errors();
 
         next_frame().await
     }
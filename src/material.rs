@@ -0,0 +1,510 @@
+use crate::aseprite::{BlendMode, SPRITE_VERTEX_SHADER};
+use crate::assets::resolve_path;
+use macroquad::prelude::*;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use std::time::SystemTime;
+
+/// Fed with the seconds since startup whenever a material is applied.
+pub const TIME_UNIFORM: &str = "iTime";
+/// Fed with the screen size in pixels whenever a material is applied.
+pub const RESOLUTION_UNIFORM: &str = "iResolution";
+
+/// How often [`MaterialRegistry::hot_reload`] checks shader files for changes.
+const RELOAD_INTERVAL: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UniformKind {
+    Float,
+    Vec2,
+    Vec3,
+    Vec4,
+    /// A `vec4` whose default alpha is 1.
+    Color,
+    Int,
+}
+
+impl UniformKind {
+    fn uniform_type(self) -> UniformType {
+        match self {
+            UniformKind::Float => UniformType::Float1,
+            UniformKind::Vec2 => UniformType::Float2,
+            UniformKind::Vec3 => UniformType::Float3,
+            UniformKind::Vec4 | UniformKind::Color => UniformType::Float4,
+            UniformKind::Int => UniformType::Int1,
+        }
+    }
+
+    /// Builds a value from up to four components, missing ones being zero.
+    fn value(self, components: &[f32]) -> UniformValue {
+        let at = |index: usize| components.get(index).copied().unwrap_or(0.0);
+        match self {
+            UniformKind::Float => UniformValue::Float(at(0)),
+            UniformKind::Vec2 => UniformValue::Vec2(vec2(at(0), at(1))),
+            UniformKind::Vec3 => UniformValue::Vec3(vec3(at(0), at(1), at(2))),
+            UniformKind::Vec4 => UniformValue::Vec4(vec4(at(0), at(1), at(2), at(3))),
+            UniformKind::Color => UniformValue::Vec4(vec4(at(0), at(1), at(2), components.get(3).copied().unwrap_or(1.0))),
+            UniformKind::Int => UniformValue::Int(at(0) as i32),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            UniformKind::Float => "float",
+            UniformKind::Vec2 => "vec2",
+            UniformKind::Vec3 => "vec3",
+            UniformKind::Vec4 => "vec4",
+            UniformKind::Color => "color",
+            UniformKind::Int => "int",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UniformValue {
+    Float(f32),
+    Vec2(Vec2),
+    Vec3(Vec3),
+    Vec4(Vec4),
+    Int(i32),
+}
+
+impl UniformValue {
+    fn fits(self, kind: UniformKind) -> bool {
+        matches!(
+            (self, kind),
+            (UniformValue::Float(_), UniformKind::Float)
+                | (UniformValue::Vec2(_), UniformKind::Vec2)
+                | (UniformValue::Vec3(_), UniformKind::Vec3)
+                | (UniformValue::Vec4(_), UniformKind::Vec4 | UniformKind::Color)
+                | (UniformValue::Int(_), UniformKind::Int)
+        )
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            UniformValue::Float(_) => "float",
+            UniformValue::Vec2(_) => "vec2",
+            UniformValue::Vec3(_) => "vec3",
+            UniformValue::Vec4(_) => "vec4",
+            UniformValue::Int(_) => "int",
+        }
+    }
+
+    fn apply(self, material: &Material, name: &str) {
+        match self {
+            UniformValue::Float(value) => material.set_uniform(name, value),
+            UniformValue::Vec2(value) => material.set_uniform(name, value),
+            UniformValue::Vec3(value) => material.set_uniform(name, value),
+            UniformValue::Vec4(value) => material.set_uniform(name, value),
+            UniformValue::Int(value) => material.set_uniform(name, value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UniformConfig {
+    #[serde(rename = "type")]
+    pub kind: UniformKind,
+    #[serde(default)]
+    pub default: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MaterialConfig {
+    /// Defaults to a vertex shader passing `uv` and `color` to the fragment shader.
+    #[serde(default)]
+    pub vertex: Option<String>,
+    pub fragment: String,
+    /// `iTime` and `iResolution` are always declared and needn't be listed.
+    #[serde(default)]
+    pub uniforms: BTreeMap<String, UniformConfig>,
+    /// Sampler name to image path.
+    #[serde(default)]
+    pub textures: BTreeMap<String, String>,
+    #[serde(default)]
+    pub blend: BlendMode,
+}
+
+struct ShaderFile {
+    path: String,
+    modified: Option<SystemTime>,
+}
+
+impl ShaderFile {
+    fn new(path: String) -> Self {
+        let modified = modified_time(&path);
+        ShaderFile { path, modified }
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+struct MaterialEntry {
+    config: MaterialConfig,
+    vertex: Option<ShaderFile>,
+    fragment: ShaderFile,
+    textures: Vec<(String, Texture2D)>,
+    material: Option<Material>,
+    /// The vertex and fragment sources `material` was built from.
+    source: Option<(String, String)>,
+    values: BTreeMap<String, UniformValue>,
+    error: Option<String>,
+}
+
+impl MaterialEntry {
+    fn default_values(&self) -> BTreeMap<String, UniformValue> {
+        self.config
+            .uniforms
+            .iter()
+            .map(|(name, uniform)| (name.clone(), uniform.kind.value(&uniform.default)))
+            .collect()
+    }
+
+    /// Builds the material from shader sources. On failure the previous material,
+    /// if any, stays in use and the error is kept for display.
+    ///
+    /// Every material takes one of macroquad's 32 pipelines, so sources that are
+    /// already compiled reuse the current material. A replaced material frees its
+    /// pipeline once nothing else holds a clone of it.
+    fn compile(&mut self, name: &str, vertex: &str, fragment: &str) {
+        let source = (vertex.to_string(), fragment.to_string());
+        if self.material.is_some() && self.source.as_ref() == Some(&source) {
+            self.error = None;
+            return;
+        }
+        let mut uniforms = vec![
+            UniformDesc::new(TIME_UNIFORM, UniformType::Float1),
+            UniformDesc::new(RESOLUTION_UNIFORM, UniformType::Float2),
+        ];
+        uniforms.extend(
+            self.config
+                .uniforms
+                .iter()
+                .filter(|(uniform, _)| *uniform != TIME_UNIFORM && *uniform != RESOLUTION_UNIFORM)
+                .map(|(uniform, config)| UniformDesc::new(uniform, config.kind.uniform_type())),
+        );
+        let params = MaterialParams {
+            pipeline_params: PipelineParams {
+                color_blend: Some(self.config.blend.blend_state()),
+                ..Default::default()
+            },
+            uniforms,
+            textures: self.config.textures.keys().cloned().collect(),
+        };
+
+        match load_material(ShaderSource::Glsl { vertex, fragment }, params) {
+            Ok(material) => {
+                for (sampler, texture) in &self.textures {
+                    material.set_texture(sampler, texture.clone());
+                }
+                self.material = Some(material);
+                self.source = Some(source);
+                self.error = None;
+            }
+            Err(err) => {
+                let message = format!("material {:?} ({}): {}", name, self.fragment.path, err);
+                warn!("{}", message);
+                self.error = Some(message);
+            }
+        }
+    }
+}
+
+type FileFuture = Pin<Box<dyn Future<Output = Result<Vec<u8>, macroquad::Error>>>>;
+
+/// An image file requested by [`MaterialRegistry::set_texture_path`], bound to
+/// its sampler once it has loaded.
+struct PendingTexture {
+    material: String,
+    sampler: String,
+    path: String,
+    file: FileFuture,
+}
+
+/// Named materials defined in a JSON file, with their uniforms' current values.
+/// Shader files are watched and recompiled when they change.
+pub struct MaterialRegistry {
+    materials: BTreeMap<String, MaterialEntry>,
+    /// Every texture loaded so far, by path.
+    textures: HashMap<String, Texture2D>,
+    pending_textures: Vec<PendingTexture>,
+    last_check: f64,
+}
+
+impl MaterialRegistry {
+    /// Loads the materials in `json_path`, a map of name to [`MaterialConfig`] with
+    /// paths relative to the JSON file. Shaders that fail to compile are reported
+    /// by [`MaterialRegistry::errors`] rather than failing the load.
+    pub async fn load(json_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let json_data = load_string(json_path).await?;
+        let configs: BTreeMap<String, MaterialConfig> = serde_json::from_str(&json_data)?;
+
+        let mut materials = BTreeMap::new();
+        let mut loaded_textures: HashMap<String, Texture2D> = HashMap::new();
+        for (name, config) in configs {
+            let mut textures = Vec::new();
+            for (sampler, path) in &config.textures {
+                let path = resolve_path(json_path, path);
+                let texture = match loaded_textures.get(&path) {
+                    Some(texture) => texture.clone(),
+                    None => load_texture(&path).await?,
+                };
+                loaded_textures.insert(path, texture.clone());
+                textures.push((sampler.clone(), texture));
+            }
+            let vertex = config
+                .vertex
+                .as_ref()
+                .map(|path| ShaderFile::new(resolve_path(json_path, path)));
+            let fragment = ShaderFile::new(resolve_path(json_path, &config.fragment));
+            let vertex_source = match &vertex {
+                Some(vertex) => load_string(&vertex.path).await?,
+                None => SPRITE_VERTEX_SHADER.to_string(),
+            };
+            let fragment_source = load_string(&fragment.path).await?;
+
+            let mut entry = MaterialEntry {
+                config,
+                vertex,
+                fragment,
+                textures,
+                material: None,
+                source: None,
+                values: BTreeMap::new(),
+                error: None,
+            };
+            entry.values = entry.default_values();
+            entry.compile(&name, &vertex_source, &fragment_source);
+            materials.insert(name, entry);
+        }
+
+        Ok(MaterialRegistry {
+            materials,
+            textures: loaded_textures,
+            pending_textures: Vec::new(),
+            last_check: get_time(),
+        })
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.materials.keys().map(String::as_str)
+    }
+
+    /// The last successfully compiled version of the material.
+    pub fn get(&self, name: &str) -> Option<&Material> {
+        self.materials.get(name)?.material.as_ref()
+    }
+
+    /// Makes the material current for following draw calls, with `iTime`,
+    /// `iResolution` and its uniform values set. Returns false if it doesn't exist
+    /// or has never compiled.
+    pub fn apply(&self, name: &str) -> bool {
+        let Some(entry) = self.materials.get(name) else {
+            return false;
+        };
+        let Some(material) = &entry.material else {
+            return false;
+        };
+        material.set_uniform(TIME_UNIFORM, get_time() as f32);
+        material.set_uniform(RESOLUTION_UNIFORM, vec2(screen_width(), screen_height()));
+        for (uniform, value) in &entry.values {
+            value.apply(material, uniform);
+        }
+        gl_use_material(material);
+        true
+    }
+
+    pub fn uniform(&self, material: &str, uniform: &str) -> Option<UniformValue> {
+        self.materials.get(material)?.values.get(uniform).copied()
+    }
+
+    /// Sets a uniform declared in the material's config; the value is applied
+    /// on the next [`MaterialRegistry::apply`].
+    pub fn set_uniform(&mut self, material: &str, uniform: &str, value: UniformValue) -> Result<(), String> {
+        let entry = self
+            .materials
+            .get_mut(material)
+            .ok_or_else(|| format!("no material named {:?}", material))?;
+        let Some(config) = entry.config.uniforms.get(uniform) else {
+            let declared: Vec<&str> = entry.config.uniforms.keys().map(String::as_str).collect();
+            return Err(format!(
+                "material {:?} has no uniform {:?} (declared: {})",
+                material,
+                uniform,
+                declared.join(", ")
+            ));
+        };
+        if !value.fits(config.kind) {
+            return Err(format!(
+                "uniform {:?} of material {:?} is a {}, not a {}",
+                uniform,
+                material,
+                config.kind.name(),
+                value.name()
+            ));
+        }
+        entry.values.insert(uniform.to_string(), value);
+        Ok(())
+    }
+
+    /// Binds `texture` to a sampler declared in the material's config.
+    pub fn set_texture(&mut self, material: &str, sampler: &str, texture: Texture2D) -> Result<(), String> {
+        let entry = self.sampler_entry(material, sampler)?;
+        if let Some(material) = &entry.material {
+            material.set_texture(sampler, texture.clone());
+        }
+        entry.textures.retain(|(name, _)| name != sampler);
+        entry.textures.push((sampler.to_string(), texture));
+        Ok(())
+    }
+
+    /// Like [`MaterialRegistry::set_texture`] with an image file, e.g. from a
+    /// script. A file not loaded before is bound once [`MaterialRegistry::update`]
+    /// has finished loading it; failing to read or decode it shows as an error.
+    pub fn set_texture_path(&mut self, material: &str, sampler: &str, path: &str) -> Result<(), String> {
+        if let Some(texture) = self.textures.get(path) {
+            return self.set_texture(material, sampler, texture.clone());
+        }
+        self.sampler_entry(material, sampler)?;
+        let file_path = path.to_string();
+        self.pending_textures.push(PendingTexture {
+            material: material.to_string(),
+            sampler: sampler.to_string(),
+            path: path.to_string(),
+            file: Box::pin(async move { load_file(&file_path).await }),
+        });
+        Ok(())
+    }
+
+    fn sampler_entry(&mut self, material: &str, sampler: &str) -> Result<&mut MaterialEntry, String> {
+        let entry = self
+            .materials
+            .get_mut(material)
+            .ok_or_else(|| format!("no material named {:?}", material))?;
+        if !entry.config.textures.contains_key(sampler) {
+            let declared: Vec<&str> = entry.config.textures.keys().map(String::as_str).collect();
+            return Err(format!(
+                "material {:?} has no texture {:?} (declared: {})",
+                material,
+                sampler,
+                declared.join(", ")
+            ));
+        }
+        Ok(entry)
+    }
+
+    /// Restores every uniform of the material to its default from the config.
+    pub fn reset_uniforms(&mut self, material: &str) -> bool {
+        let Some(entry) = self.materials.get_mut(material) else {
+            return false;
+        };
+        entry.values = entry.default_values();
+        true
+    }
+
+    /// Compile errors of materials whose latest shader source failed to build.
+    pub fn errors(&self) -> impl Iterator<Item = &str> {
+        self.materials.values().filter_map(|entry| entry.error.as_deref())
+    }
+
+    /// Binds textures whose files have finished loading and hot reloads shaders.
+    /// Call once per frame.
+    pub fn update(&mut self) {
+        // macroquad's file futures complete from the main loop rather than through
+        // wakers, so polling them each frame is enough
+        let mut context = Context::from_waker(Waker::noop());
+        let mut index = 0;
+        while index < self.pending_textures.len() {
+            let Poll::Ready(result) = self.pending_textures[index].file.as_mut().poll(&mut context) else {
+                index += 1;
+                continue;
+            };
+            let pending = self.pending_textures.swap_remove(index);
+            let texture = match self.textures.get(&pending.path) {
+                Some(texture) => Ok(texture.clone()),
+                None => result
+                    .and_then(|bytes| Image::from_file_with_format(&bytes, None))
+                    .map(|image| {
+                        let texture = Texture2D::from_image(&image);
+                        texture.set_filter(FilterMode::Nearest);
+                        self.textures.insert(pending.path.clone(), texture.clone());
+                        texture
+                    }),
+            };
+            let bound = texture
+                .map_err(|err| format!("failed to load {}: {}", pending.path, err))
+                .and_then(|texture| self.set_texture(&pending.material, &pending.sampler, texture));
+            if let Err(err) = bound {
+                warn!("{}", err);
+                if let Some(entry) = self.materials.get_mut(&pending.material) {
+                    entry.error = Some(format!("material {:?}: {}", pending.material, err));
+                }
+            }
+        }
+        self.hot_reload();
+    }
+
+    /// Recompiles materials whose shader files changed on disk. Cheap to call every
+    /// frame; files are only checked a couple of times a second. Does nothing
+    /// where there is no file system, e.g. on the web.
+    pub fn hot_reload(&mut self) {
+        if get_time() - self.last_check < RELOAD_INTERVAL {
+            return;
+        }
+        self.last_check = get_time();
+
+        for (name, entry) in &mut self.materials {
+            let mut changed = false;
+            for file in entry.vertex.iter_mut().chain([&mut entry.fragment]) {
+                let modified = modified_time(&file.path);
+                if modified.is_some() && modified != file.modified {
+                    file.modified = modified;
+                    changed = true;
+                }
+            }
+            if !changed {
+                continue;
+            }
+
+            let vertex = match &entry.vertex {
+                Some(vertex) => std::fs::read_to_string(&vertex.path).map_err(|err| (vertex.path.clone(), err)),
+                None => Ok(SPRITE_VERTEX_SHADER.to_string()),
+            };
+            let fragment = std::fs::read_to_string(&entry.fragment.path).map_err(|err| (entry.fragment.path.clone(), err));
+            match (vertex, fragment) {
+                (Ok(vertex), Ok(fragment)) => {
+                    entry.compile(name, &vertex, &fragment);
+                    if entry.error.is_none() {
+                        info!("reloaded material {:?}", name);
+                    }
+                }
+                (Err((path, err)), _) | (_, Err((path, err))) => {
+                    entry.error = Some(format!("material {:?}: failed to read {}: {}", name, path, err));
+                }
+            }
+        }
+    }
+
+    /// Draws any compile errors over the top of the screen.
+    pub fn draw_errors(&self) {
+        let lines: Vec<&str> = self.errors().flat_map(str::lines).collect();
+        if lines.is_empty() {
+            return;
+        }
+        push_camera_state();
+        set_default_camera();
+        gl_use_default_material();
+        draw_rectangle(0.0, 0.0, screen_width(), 10.0 + lines.len() as f32 * 18.0, Color::new(0.0, 0.0, 0.0, 0.8));
+        for (index, line) in lines.iter().enumerate() {
+            draw_text(line, 10.0, 22.0 + index as f32 * 18.0, 18.0, RED);
+        }
+        pop_camera_state();
+    }
+}
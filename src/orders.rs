@@ -0,0 +1,164 @@
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Names a unit in orders. Stays the same for the unit's whole life, unlike
+/// its place in a list, so saved orders still point at the right unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct UnitId(pub u32);
+
+/// What a unit can be told to do.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "order", rename_all = "snake_case")]
+pub enum Order {
+    /// Walk to `to`, ignoring enemies. Done within `spread` of it, so a crowd
+    /// sent to one point doesn't pile onto it.
+    Move {
+        to: Vec2,
+        #[serde(default)]
+        spread: f32,
+    },
+    /// As [`Order::Move`], but stopping to fight anything met on the way.
+    AttackMove {
+        to: Vec2,
+        #[serde(default)]
+        spread: f32,
+    },
+    /// Walk the points in a loop, fighting anything met on the way. Never done.
+    Patrol { points: Vec<Vec2> },
+    /// Stand still, fighting anything in range. Never done.
+    HoldPosition,
+    /// Drop every order. Never queued.
+    Stop,
+    /// Keep near another unit. Done once it's gone.
+    Follow { unit: UnitId },
+}
+
+impl Order {
+    /// The fixed point the order walks to, if it has one; for a patrol, the
+    /// next point on it.
+    pub fn destination(&self) -> Option<Vec2> {
+        match self {
+            Order::Move { to, .. } | Order::AttackMove { to, .. } => Some(*to),
+            Order::Patrol { points } => points.first().copied(),
+            Order::HoldPosition | Order::Stop | Order::Follow { .. } => None,
+        }
+    }
+}
+
+/// A unit's orders in the order they'll be carried out; the first is the one
+/// being carried out now.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct OrderQueue {
+    orders: VecDeque<Order>,
+}
+
+impl OrderQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces every order with `order`, or if `queued` adds it to the end.
+    /// [`Order::Stop`] just clears the queue, and a patrol queued after a
+    /// patrol adds its points to that patrol's route, since the first would
+    /// never finish.
+    pub fn issue(&mut self, order: Order, queued: bool) {
+        if !queued || order == Order::Stop {
+            self.orders.clear();
+        }
+        match (self.orders.back_mut(), order) {
+            (_, Order::Stop) => {}
+            (Some(Order::Patrol { points }), Order::Patrol { points: more }) if queued => {
+                for point in more {
+                    if !points.contains(&point) {
+                        points.push(point);
+                    }
+                }
+            }
+            (_, order) => self.orders.push_back(order),
+        }
+    }
+
+    pub fn current(&self) -> Option<&Order> {
+        self.orders.front()
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut Order> {
+        self.orders.front_mut()
+    }
+
+    /// Finishes the current order, so the next one starts.
+    pub fn complete(&mut self) -> Option<Order> {
+        self.orders.pop_front()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Order> {
+        self.orders.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.orders.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    /// Where a unit now at `from` will be once the queued walking is done,
+    /// for placing the next queued order.
+    pub fn end(&self, from: Vec2) -> Vec2 {
+        self.orders.iter().rev().find_map(Order::destination).unwrap_or(from)
+    }
+}
+
+/// One order given to one unit on a given frame, in serialisable form.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderCommand {
+    pub frame: u64,
+    pub unit: UnitId,
+    pub order: Order,
+    /// Added to the unit's queue rather than replacing it.
+    #[serde(default)]
+    pub queued: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_survives_json() {
+        let mut queue = OrderQueue::new();
+        queue.issue(Order::Move { to: vec2(10.0, 20.0), spread: 4.0 }, false);
+        queue.issue(Order::AttackMove { to: vec2(-5.5, 0.0), spread: 0.0 }, true);
+        queue.issue(Order::Patrol { points: vec![vec2(1.0, 2.0), vec2(3.0, 4.0)] }, true);
+        queue.issue(Order::Follow { unit: UnitId(7) }, true);
+        queue.issue(Order::HoldPosition, true);
+
+        let json = serde_json::to_string(&queue).unwrap();
+        assert_eq!(serde_json::from_str::<OrderQueue>(&json).unwrap(), queue);
+        assert!(json.starts_with(r#"[{"order":"move","to":[10.0,20.0],"spread":4.0}"#), "{}", json);
+    }
+
+    #[test]
+    fn command_survives_json() {
+        let command = OrderCommand {
+            frame: 42,
+            unit: UnitId(3),
+            order: Order::Patrol { points: vec![vec2(0.0, 0.0), vec2(50.0, 0.0)] },
+            queued: true,
+        };
+        let json = serde_json::to_string(&command).unwrap();
+        assert_eq!(serde_json::from_str::<OrderCommand>(&json).unwrap(), command);
+
+        // Omitted spread and queued flag default
+        let command: OrderCommand = serde_json::from_str(r#"{"frame":1,"unit":2,"order":{"order":"move","to":[3,4]}}"#).unwrap();
+        assert_eq!(command.order, Order::Move { to: vec2(3.0, 4.0), spread: 0.0 });
+        assert!(!command.queued);
+    }
+}
@@ -0,0 +1,702 @@
+use macroquad::prelude::*;
+use macroquad_tiled as tiled;
+use rapier2d::parry::query::intersection_test;
+use rapier2d::prelude::{ColliderSet, Cuboid, Isometry, Vector};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+const DIAGONAL_COST: f32 = std::f32::consts::SQRT_2;
+const NO_PARENT: u32 = u32::MAX;
+
+/// Which cells of a uniform grid can be walked through. Cells are addressed by
+/// column and row; anything outside the grid is blocked.
+#[derive(Debug, Clone)]
+pub struct Grid {
+    width: i32,
+    height: i32,
+    /// Size of a cell in world units, with the grid's corner at the origin.
+    cell_size: Vec2,
+    walkable: Vec<bool>,
+    version: u64,
+}
+
+impl Grid {
+    /// A grid with every cell walkable.
+    pub fn new(width: u32, height: u32, cell_size: Vec2) -> Self {
+        Grid {
+            width: width as i32,
+            height: height as i32,
+            cell_size,
+            walkable: vec![true; (width * height) as usize],
+            version: 0,
+        }
+    }
+
+    /// A grid over a Tiled tile layer, walkable where `is_walkable` says of the
+    /// tile, which is `None` for empty cells.
+    pub fn from_tiled_layer(
+        map: &tiled::Map,
+        layer: &str,
+        is_walkable: impl Fn(Option<&tiled::Tile>) -> bool,
+    ) -> Option<Self> {
+        let tiles = map.layers.get(layer)?;
+        let cell_size = vec2(map.raw_tiled_map.tilewidth as f32, map.raw_tiled_map.tileheight as f32);
+        let mut grid = Grid::new(tiles.width, tiles.height, cell_size);
+        for (cell, tile) in grid.walkable.iter_mut().zip(&tiles.data) {
+            *cell = is_walkable(tile.as_ref());
+        }
+        Some(grid)
+    }
+
+    /// A grid covering `size` world units from the origin, blocked wherever a
+    /// solid collider overlaps a cell. Sensors are ignored.
+    pub fn from_colliders(colliders: &ColliderSet, size: Vec2, cell_size: Vec2) -> Self {
+        let cells = (size / cell_size).ceil();
+        let mut grid = Grid::new(cells.x as u32, cells.y as u32, cell_size);
+        // Slightly smaller than a cell, so colliders that only touch an edge
+        // don't block the cell beyond it
+        let cell_shape = Cuboid::new(Vector::new(cell_size.x, cell_size.y) * 0.499);
+        for (_, collider) in colliders.iter().filter(|(_, collider)| !collider.is_sensor()) {
+            let aabb = collider.compute_aabb();
+            let min = grid.clamp_cell(grid.cell_at(vec2(aabb.mins.x, aabb.mins.y)));
+            let max = grid.clamp_cell(grid.cell_at(vec2(aabb.maxs.x, aabb.maxs.y)));
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let center = grid.center(ivec2(x, y));
+                    let position = Isometry::translation(center.x, center.y);
+                    let overlaps = intersection_test(&position, &cell_shape, collider.position(), collider.shape());
+                    if overlaps.unwrap_or(true) {
+                        grid.walkable[(y * grid.width + x) as usize] = false;
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height as u32
+    }
+
+    pub fn cell_size(&self) -> Vec2 {
+        self.cell_size
+    }
+
+    /// Goes up by one on every change to walkability, so paths and caches can
+    /// tell they're out of date.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn contains(&self, cell: IVec2) -> bool {
+        cell.x >= 0 && cell.y >= 0 && cell.x < self.width && cell.y < self.height
+    }
+
+    pub fn index(&self, cell: IVec2) -> Option<usize> {
+        self.contains(cell).then(|| (cell.y * self.width + cell.x) as usize)
+    }
+
+    pub fn cell_of_index(&self, index: usize) -> IVec2 {
+        ivec2(index as i32 % self.width, index as i32 / self.width)
+    }
+
+    pub fn is_walkable(&self, cell: IVec2) -> bool {
+        self.index(cell).is_some_and(|index| self.walkable[index])
+    }
+
+    pub fn set_walkable(&mut self, cell: IVec2, walkable: bool) {
+        if let Some(index) = self.index(cell) {
+            if self.walkable[index] != walkable {
+                self.walkable[index] = walkable;
+                self.version += 1;
+            }
+        }
+    }
+
+    /// The cell containing a world point, which may be outside the grid.
+    pub fn cell_at(&self, point: Vec2) -> IVec2 {
+        (point / self.cell_size).floor().as_ivec2()
+    }
+
+    pub fn clamp_cell(&self, cell: IVec2) -> IVec2 {
+        cell.clamp(IVec2::ZERO, ivec2(self.width - 1, self.height - 1).max(IVec2::ZERO))
+    }
+
+    /// The world position of a cell's centre.
+    pub fn center(&self, cell: IVec2) -> Vec2 {
+        (cell.as_vec2() + 0.5) * self.cell_size
+    }
+
+    /// The walkable cell nearest `cell`, searching outwards ring by ring up to
+    /// `max_distance` cells, e.g. for a move order onto a wall.
+    pub fn nearest_walkable(&self, cell: IVec2, max_distance: i32) -> Option<IVec2> {
+        for distance in 0..=max_distance {
+            let ring = (-distance..=distance).flat_map(|x| {
+                (-distance..=distance)
+                    .filter(move |y| x.abs() == distance || y.abs() == distance)
+                    .map(move |y| cell + ivec2(x, y))
+            });
+            let nearest = ring
+                .filter(|&candidate| self.is_walkable(candidate))
+                .min_by_key(|candidate| (*candidate - cell).length_squared());
+            if nearest.is_some() {
+                return nearest;
+            }
+        }
+        None
+    }
+
+    /// Whether a move between neighbouring cells is allowed: both walkable, and
+    /// diagonal moves not squeezing between two blocked corners.
+    pub fn can_step(&self, from: IVec2, direction: IVec2) -> bool {
+        let to = from + direction;
+        if !self.is_walkable(to) {
+            return false;
+        }
+        direction.x == 0
+            || direction.y == 0
+            || self.is_walkable(from + ivec2(direction.x, 0)) && self.is_walkable(from + ivec2(0, direction.y))
+    }
+
+    /// Whether a body of `radius` can travel straight from `from` to `to`
+    /// through walkable cells only. Checks the centre line and both edges.
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2, radius: f32) -> bool {
+        let side = (to - from).perp().normalize_or_zero() * radius;
+        if radius > 0.0 && side != Vec2::ZERO {
+            self.ray_clear(from + side, to + side) && self.ray_clear(from - side, to - side) && self.ray_clear(from, to)
+        } else {
+            self.ray_clear(from, to)
+        }
+    }
+
+    /// Walks the cells the segment crosses, in order.
+    fn ray_clear(&self, from: Vec2, to: Vec2) -> bool {
+        let start = from / self.cell_size;
+        let end = to / self.cell_size;
+        let mut cell = start.floor().as_ivec2();
+        let last = end.floor().as_ivec2();
+        let delta = end - start;
+        let step = ivec2(delta.x.signum() as i32, delta.y.signum() as i32);
+        // Distance along the segment, as a fraction, to the next cell boundary
+        // on each axis and between boundaries
+        let next_boundary = |start: f32, delta: f32, cell: i32| {
+            if delta > 0.0 {
+                ((cell + 1) as f32 - start) / delta
+            } else if delta < 0.0 {
+                (cell as f32 - start) / delta
+            } else {
+                f32::INFINITY
+            }
+        };
+        let mut next = vec2(next_boundary(start.x, delta.x, cell.x), next_boundary(start.y, delta.y, cell.y));
+        let across = vec2(1.0 / delta.x.abs(), 1.0 / delta.y.abs());
+
+        // Steps left to take on each axis, which bounds the walk however the
+        // boundary distances round
+        let mut remaining = (last - cell).abs();
+        if !self.is_walkable(cell) {
+            return false;
+        }
+        while remaining != IVec2::ZERO {
+            if remaining.x > 0 && remaining.y > 0 && (next.x - next.y).abs() <= 1e-6 {
+                // Through a corner: both cells beside it must be open too
+                if !self.is_walkable(cell + ivec2(step.x, 0)) || !self.is_walkable(cell + ivec2(0, step.y)) {
+                    return false;
+                }
+                cell += step;
+                remaining -= 1;
+                next += across;
+            } else if remaining.y == 0 || remaining.x > 0 && next.x < next.y {
+                cell.x += step.x;
+                remaining.x -= 1;
+                next.x += across.x;
+            } else {
+                cell.y += step.y;
+                remaining.y -= 1;
+                next.y += across.y;
+            }
+            if !self.is_walkable(cell) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Whether a path can still be followed from its first point, e.g. after the
+    /// grid has changed.
+    pub fn path_clear(&self, points: &[Vec2], radius: f32) -> bool {
+        points.windows(2).all(|segment| self.line_of_sight(segment[0], segment[1], radius))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathOptions {
+    /// Search with jump point search, which expands far fewer cells on open
+    /// grids and finds equally short paths.
+    pub jump_points: bool,
+    /// Cut corners the grid path takes where there's a clear straight line.
+    pub smooth: bool,
+    /// Clearance kept from blocked cells when smoothing.
+    pub radius: f32,
+}
+
+impl Default for PathOptions {
+    fn default() -> Self {
+        PathOptions {
+            jump_points: true,
+            smooth: true,
+            radius: 0.0,
+        }
+    }
+}
+
+/// A cell waiting to be expanded, cheapest estimate first.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Open {
+    pub(crate) estimate: f32,
+    pub(crate) cost: f32,
+    pub(crate) index: u32,
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Open {
+    // Reversed for the max-heap; among equal estimates, the one furthest along
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .total_cmp(&self.estimate)
+            .then(self.cost.total_cmp(&other.cost))
+            .then(other.index.cmp(&self.index))
+    }
+}
+
+/// The exact distance between cells when moving in eight directions.
+pub(crate) fn octile(from: IVec2, to: IVec2) -> f32 {
+    let delta = (to - from).abs();
+    let (long, short) = (delta.max_element() as f32, delta.min_element() as f32);
+    long + (DIAGONAL_COST - 1.0) * short
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchState {
+    Searching,
+    /// Every cell along the way, from start to goal.
+    Found(Vec<IVec2>),
+    NotFound,
+}
+
+/// An A* search that can be run a bit at a time, so many searches can share a
+/// frame budget.
+#[derive(Debug, Clone)]
+pub struct Search {
+    start: IVec2,
+    goal: IVec2,
+    jump_points: bool,
+    version: u64,
+    open: BinaryHeap<Open>,
+    cost: Vec<f32>,
+    parent: Vec<u32>,
+    closed: Vec<bool>,
+    expanded: usize,
+    state: SearchState,
+}
+
+pub(crate) const DIRECTIONS: [IVec2; 8] = [
+    IVec2::new(1, 0),
+    IVec2::new(-1, 0),
+    IVec2::new(0, 1),
+    IVec2::new(0, -1),
+    IVec2::new(1, 1),
+    IVec2::new(1, -1),
+    IVec2::new(-1, 1),
+    IVec2::new(-1, -1),
+];
+
+impl Search {
+    /// Searches from `start` to `goal`. The start cell needn't be walkable, so
+    /// units pushed into a wall can still leave it; the goal must be.
+    pub fn new(grid: &Grid, start: IVec2, goal: IVec2, jump_points: bool) -> Self {
+        let cells = grid.walkable.len();
+        let mut search = Search {
+            start,
+            goal,
+            jump_points,
+            version: grid.version,
+            open: BinaryHeap::new(),
+            cost: vec![f32::INFINITY; cells],
+            parent: vec![NO_PARENT; cells],
+            closed: vec![false; cells],
+            expanded: 0,
+            state: SearchState::Searching,
+        };
+        match grid.index(start) {
+            Some(index) if grid.is_walkable(goal) => {
+                search.cost[index] = 0.0;
+                search.open.push(Open {
+                    estimate: octile(start, goal),
+                    cost: 0.0,
+                    index: index as u32,
+                });
+            }
+            _ => search.state = SearchState::NotFound,
+        }
+        search
+    }
+
+    pub fn start(&self) -> IVec2 {
+        self.start
+    }
+
+    pub fn goal(&self) -> IVec2 {
+        self.goal
+    }
+
+    /// The grid version the search started on.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Cells expanded so far.
+    pub fn expanded(&self) -> usize {
+        self.expanded
+    }
+
+    pub fn state(&self) -> &SearchState {
+        &self.state
+    }
+
+    /// Expands at most `budget` cells and returns where the search got to.
+    pub fn step(&mut self, grid: &Grid, budget: usize) -> &SearchState {
+        for _ in 0..budget {
+            if self.state != SearchState::Searching {
+                break;
+            }
+            let Some(current) = self.open.pop() else {
+                self.state = SearchState::NotFound;
+                break;
+            };
+            let index = current.index as usize;
+            if self.closed[index] {
+                continue;
+            }
+            self.closed[index] = true;
+            self.expanded += 1;
+            let cell = grid.cell_of_index(index);
+            if cell == self.goal {
+                self.state = SearchState::Found(self.trace(grid, index));
+                break;
+            }
+
+            let mut successors = Vec::with_capacity(8);
+            if self.jump_points {
+                for direction in self.pruned_directions(grid, index) {
+                    if let Some(jump) = self.jump(grid, cell, direction) {
+                        successors.push(jump);
+                    }
+                }
+            } else {
+                successors.extend(
+                    DIRECTIONS
+                        .iter()
+                        .filter(|&&direction| grid.can_step(cell, direction))
+                        .map(|&direction| cell + direction),
+                );
+            }
+            for next in successors {
+                let Some(next_index) = grid.index(next) else {
+                    continue;
+                };
+                let cost = current.cost + octile(cell, next);
+                if self.closed[next_index] || cost >= self.cost[next_index] {
+                    continue;
+                }
+                self.cost[next_index] = cost;
+                self.parent[next_index] = index as u32;
+                self.open.push(Open {
+                    estimate: cost + octile(next, self.goal),
+                    cost,
+                    index: next_index as u32,
+                });
+            }
+        }
+        &self.state
+    }
+
+    /// Runs to the end.
+    pub fn finish(&mut self, grid: &Grid) -> &SearchState {
+        self.step(grid, usize::MAX)
+    }
+
+    /// Directions worth searching from a cell, given the way it was reached.
+    /// Jump point search only looks ahead and around obstacles just passed.
+    fn pruned_directions(&self, grid: &Grid, index: usize) -> Vec<IVec2> {
+        let cell = grid.cell_of_index(index);
+        let parent = self.parent[index];
+        if parent == NO_PARENT {
+            return DIRECTIONS.iter().copied().filter(|&direction| grid.can_step(cell, direction)).collect();
+        }
+        let from = grid.cell_of_index(parent as usize);
+        let direction = (cell - from).signum();
+        let open = |offset: IVec2| grid.is_walkable(cell + offset);
+        let mut directions = Vec::with_capacity(5);
+        if direction.x != 0 && direction.y != 0 {
+            let horizontal = ivec2(direction.x, 0);
+            let vertical = ivec2(0, direction.y);
+            if open(vertical) {
+                directions.push(vertical);
+            }
+            if open(horizontal) {
+                directions.push(horizontal);
+            }
+            if grid.can_step(cell, direction) {
+                directions.push(direction);
+            }
+        } else {
+            let sides = [direction.perp(), -direction.perp()];
+            if open(direction) {
+                directions.push(direction);
+                for side in sides {
+                    if grid.can_step(cell, direction + side) {
+                        directions.push(direction + side);
+                    }
+                }
+            }
+            // Sideways, in case an obstacle just passed opened up
+            for side in sides {
+                if open(side) {
+                    directions.push(side);
+                }
+            }
+        }
+        directions
+    }
+
+    /// Travels from `cell` in `direction` until reaching the goal or a cell
+    /// worth branching from. Diagonal moves never cut corners.
+    fn jump(&self, grid: &Grid, mut cell: IVec2, direction: IVec2) -> Option<IVec2> {
+        loop {
+            if !grid.can_step(cell, direction) {
+                return None;
+            }
+            cell += direction;
+            if cell == self.goal {
+                return Some(cell);
+            }
+            if direction.x != 0 && direction.y != 0 {
+                // A diagonal stops where either straight line finds something
+                if self.jump(grid, cell, ivec2(direction.x, 0)).is_some()
+                    || self.jump(grid, cell, ivec2(0, direction.y)).is_some()
+                {
+                    return Some(cell);
+                }
+            } else {
+                // A straight line stops beside the end of an obstacle
+                let behind = -direction;
+                for side in [direction.perp(), -direction.perp()] {
+                    if grid.is_walkable(cell + side) && !grid.is_walkable(cell + behind + side) {
+                        return Some(cell);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The cells from the start to `index`, filling in the straight runs between
+    /// jump points.
+    fn trace(&self, grid: &Grid, index: usize) -> Vec<IVec2> {
+        let mut cells = vec![grid.cell_of_index(index)];
+        let mut current = index;
+        while self.parent[current] != NO_PARENT {
+            let parent = self.parent[current] as usize;
+            let from = grid.cell_of_index(parent);
+            let to = grid.cell_of_index(current);
+            let direction = (from - to).signum();
+            let mut cell = to;
+            while cell != from {
+                cell += direction;
+                cells.push(cell);
+            }
+            current = parent;
+        }
+        cells.reverse();
+        cells
+    }
+}
+
+/// Drops the waypoints a body of `radius` can skip by going straight, keeping
+/// the first and last.
+pub fn smooth_path(grid: &Grid, points: &[Vec2], radius: f32) -> Vec<Vec2> {
+    let Some(&first) = points.first() else {
+        return Vec::new();
+    };
+    let mut smoothed = vec![first];
+    let mut anchor = 0;
+    while anchor + 1 < points.len() {
+        // The furthest point still in sight, or at least the next one
+        let next = (anchor + 2..points.len())
+            .rev()
+            .find(|&index| grid.line_of_sight(points[anchor], points[index], radius))
+            .unwrap_or(anchor + 1);
+        smoothed.push(points[next]);
+        anchor = next;
+    }
+    smoothed
+}
+
+/// A path between world points, starting at `from` and ending at `to`, through
+/// the centres of the cells between. `None` when the goal can't be reached.
+pub fn find_path(grid: &Grid, from: Vec2, to: Vec2, options: PathOptions) -> Option<Vec<Vec2>> {
+    let mut search = Search::new(grid, grid.cell_at(from), grid.cell_at(to), options.jump_points);
+    match search.finish(grid) {
+        SearchState::Found(cells) => Some(path_points(grid, cells, from, to, options)),
+        _ => None,
+    }
+}
+
+fn path_points(grid: &Grid, cells: &[IVec2], from: Vec2, to: Vec2, options: PathOptions) -> Vec<Vec2> {
+    let mut points: Vec<Vec2> = cells.iter().map(|&cell| grid.center(cell)).collect();
+    // The exact ends replace the centres of their cells
+    if let Some(first) = points.first_mut() {
+        *first = from;
+    }
+    if let Some(last) = points.last_mut() {
+        *last = to;
+    }
+    if points.len() == 1 {
+        points.push(to);
+    }
+    if options.smooth {
+        smooth_path(grid, &points, options.radius)
+    } else {
+        points
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PathRequestId(u64);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathStatus {
+    Pending,
+    /// From the requested start to the goal, as [`find_path`] returns.
+    Found(Vec<Vec2>),
+    /// No route, or the request was cancelled or already taken.
+    NotFound,
+}
+
+struct PathRequest {
+    id: PathRequestId,
+    from: Vec2,
+    to: Vec2,
+    options: PathOptions,
+    search: Search,
+}
+
+/// Path requests answered over several frames, spending at most `budget` cell
+/// expansions per update, so hundreds of units can ask at once without a
+/// spike. Searches restart if the grid changes under them.
+pub struct PathQueue {
+    /// Cells expanded per update across all searches.
+    pub budget: usize,
+    pending: VecDeque<PathRequest>,
+    finished: HashMap<PathRequestId, Option<Vec<Vec2>>>,
+    next_id: u64,
+}
+
+impl Default for PathQueue {
+    fn default() -> Self {
+        Self::new(2000)
+    }
+}
+
+impl PathQueue {
+    pub fn new(budget: usize) -> Self {
+        PathQueue {
+            budget,
+            pending: VecDeque::new(),
+            finished: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn request(&mut self, grid: &Grid, from: Vec2, to: Vec2, options: PathOptions) -> PathRequestId {
+        let id = PathRequestId(self.next_id);
+        self.next_id += 1;
+        self.pending.push_back(PathRequest {
+            id,
+            from,
+            to,
+            options,
+            search: Search::new(grid, grid.cell_at(from), grid.cell_at(to), options.jump_points),
+        });
+        id
+    }
+
+    pub fn cancel(&mut self, id: PathRequestId) {
+        self.pending.retain(|request| request.id != id);
+        self.finished.remove(&id);
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Works through the requests oldest first until the budget runs out.
+    pub fn update(&mut self, grid: &Grid) {
+        let mut budget = self.budget;
+        while budget > 0 {
+            let Some(request) = self.pending.front_mut() else {
+                break;
+            };
+            if request.search.version() != grid.version() {
+                request.search = Search::new(
+                    grid,
+                    request.search.start(),
+                    request.search.goal(),
+                    request.options.jump_points,
+                );
+            }
+            let expanded = request.search.expanded();
+            request.search.step(grid, budget);
+            // Counting at least one, so searches that end at once still cost
+            budget = budget.saturating_sub((request.search.expanded() - expanded).max(1));
+            let path = match request.search.state() {
+                SearchState::Searching => continue,
+                SearchState::Found(cells) => Some(path_points(grid, cells, request.from, request.to, request.options)),
+                SearchState::NotFound => None,
+            };
+            let id = request.id;
+            self.pending.pop_front();
+            self.finished.insert(id, path);
+        }
+    }
+
+    /// The result, once; later calls for the same request say `NotFound`.
+    pub fn take(&mut self, id: PathRequestId) -> PathStatus {
+        if let Some(path) = self.finished.remove(&id) {
+            return path.map_or(PathStatus::NotFound, PathStatus::Found);
+        }
+        if self.pending.iter().any(|request| request.id == id) {
+            PathStatus::Pending
+        } else {
+            PathStatus::NotFound
+        }
+    }
+}
@@ -1,95 +1,421 @@
+use crate::aseprite::{AnimationPlayer, SpriteDrawParams, SpriteSheet};
 use macroquad::prelude::*;
+use rapier2d::math::{Point, Real, Vector};
+use rapier2d::prelude::{ColliderHandle, ColliderSet, RigidBodyHandle, RigidBodySet, RigidBodyType};
 
-struct Rectangle {
-    rect: Rect,
-    color: Color,
-    is_dragging: bool,
-    drag_offset: Vec2,
+/// Maps a world-space point into the local space of something drawn with `world`,
+/// e.g. a [`TransformStack`](crate::transform::TransformStack) matrix. `None` if
+/// the matrix is degenerate, such as a zero scale.
+pub fn to_local(world: Mat3, point: Vec2) -> Option<Vec2> {
+    if world.determinant().abs() <= f32::EPSILON {
+        return None;
+    }
+    Some(world.inverse().transform_point2(point))
+}
+
+/// A shape in local space, tested against points transformed by the inverse of
+/// the matrix it is drawn with.
+#[derive(Debug, Clone)]
+pub enum HitShape {
+    Rect(Rect),
+    Circle { center: Vec2, radius: f32 },
+    /// Any simple polygon, convex or not.
+    Polygon(Vec<Vec2>),
+}
+
+impl HitShape {
+    pub fn contains_local(&self, point: Vec2) -> bool {
+        match self {
+            HitShape::Rect(rect) => rect.contains(point),
+            HitShape::Circle { center, radius } => point.distance_squared(*center) <= radius * radius,
+            HitShape::Polygon(points) => polygon_contains(points, point),
+        }
+    }
+
+    /// Whether the world-space `point` is inside the shape drawn with `world`.
+    pub fn contains(&self, world: Mat3, point: Vec2) -> bool {
+        to_local(world, point).is_some_and(|local| self.contains_local(local))
+    }
+}
+
+/// Even-odd rule: a point is inside if a ray from it crosses the edges an odd
+/// number of times.
+fn polygon_contains(points: &[Vec2], point: Vec2) -> bool {
+    let mut inside = false;
+    let mut previous = match points.last() {
+        Some(last) => *last,
+        None => return false,
+    };
+    for current in points {
+        if (current.y > point.y) != (previous.y > point.y) {
+            let x = current.x + (point.y - current.y) / (previous.y - current.y) * (previous.x - current.x);
+            if point.x < x {
+                inside = !inside;
+            }
+        }
+        previous = *current;
+    }
+    inside
+}
+
+/// CPU copy of a sprite sheet's texture for per-pixel hit tests, so clicks on
+/// transparent parts of a sprite fall through.
+pub struct SpriteMask {
+    image: Image,
+    /// Pixels with at least this alpha count as hits.
+    pub threshold: f32,
 }
 
-impl Rectangle {
-    fn new(x: f32, y: f32, w: f32, h: f32, color: Color) -> Self {
-        Rectangle {
-            rect: Rect::new(x, y, w, h),
-            color,
-            is_dragging: false,
-            drag_offset: Vec2::ZERO,
+impl SpriteMask {
+    /// Reads the sheet's texture back from the GPU; build once per sheet, after
+    /// any atlas packing.
+    pub fn new(sheet: &SpriteSheet) -> Self {
+        SpriteMask {
+            image: sheet.texture().get_texture_data(),
+            threshold: 0.5,
         }
     }
 
-    fn draw(&self) {
-        draw_rectangle(
-            self.rect.x,
-            self.rect.y,
-            self.rect.w,
-            self.rect.h,
-            self.color,
-        );
+    /// Whether the world-space `point` hits an opaque pixel of a visible layer of
+    /// the player's current frame, drawn with `world` and `params` as in
+    /// [`AnimationPlayer::draw_transformed`].
+    pub fn contains(&self, player: &AnimationPlayer, params: &SpriteDrawParams, world: Mat3, point: Vec2) -> bool {
+        let Some(pixel) = to_local(world * player.local_matrix(params), point) else {
+            return false;
+        };
+        let size = player.size();
+        if pixel.x < 0.0 || pixel.y < 0.0 || pixel.x >= size.x || pixel.y >= size.y {
+            return false;
+        }
+
+        player.sheet().layers().iter().any(|layer| {
+            if !player.layer_state(&layer.name).is_some_and(|state| state.visible) {
+                return false;
+            }
+            let Some(frame) = layer.frames.get(player.current_frame()) else {
+                return false;
+            };
+            // Trimmed frames only cover part of the sprite
+            let x = pixel.x as i32 - frame.sprite_source_size.x;
+            let y = pixel.y as i32 - frame.sprite_source_size.y;
+            if x < 0 || y < 0 || x >= frame.frame.w || y >= frame.frame.h {
+                return false;
+            }
+            let color = self
+                .image
+                .get_pixel((frame.frame.x + x) as u32, (frame.frame.y + y) as u32);
+            color.a >= self.threshold
+        })
     }
+}
+
+/// Anything that can be hit tested with a world-space point, so one drag and drop
+/// path handles shapes, sprites and physics objects alike.
+pub trait Pickable {
+    /// The point relative to the object if it's hit: offset from a rect's corner
+    /// or a circle's centre, or in local space for transformed things.
+    fn pick(&self, point: Vec2) -> Option<Vec2>;
 
     fn contains(&self, point: Vec2) -> bool {
-        self.rect.contains(point)
+        self.pick(point).is_some()
+    }
+}
+
+impl Pickable for Rect {
+    fn pick(&self, point: Vec2) -> Option<Vec2> {
+        self.contains(point).then(|| point - self.point())
+    }
+}
+
+impl Pickable for Circle {
+    fn pick(&self, point: Vec2) -> Option<Vec2> {
+        self.contains(&point).then(|| point - self.point())
+    }
+}
+
+/// Untransformed, so the shape's coordinates are world coordinates.
+impl Pickable for HitShape {
+    fn pick(&self, point: Vec2) -> Option<Vec2> {
+        self.contains_local(point).then_some(point)
     }
+}
+
+/// Something drawn with a world matrix, picked in its local space.
+pub struct Transformed<T>(pub T, pub Mat3);
 
-    fn start_drag(&mut self, mouse_pos: Vec2) {
-        self.is_dragging = true;
-        self.drag_offset = mouse_pos - Vec2::new(self.rect.x, self.rect.y);
+impl<T: Pickable> Pickable for Transformed<T> {
+    fn pick(&self, point: Vec2) -> Option<Vec2> {
+        self.0.pick(to_local(self.1, point)?)
     }
+}
+
+/// An animated sprite, hit only on opaque pixels of its current frame.
+pub struct SpritePick<'a> {
+    pub mask: &'a SpriteMask,
+    pub player: &'a AnimationPlayer,
+    pub params: &'a SpriteDrawParams,
+    /// As passed to [`AnimationPlayer::draw_transformed`].
+    pub world: Mat3,
+}
 
-    fn update_position(&mut self, mouse_pos: Vec2) {
-        if self.is_dragging {
-            self.rect.x = mouse_pos.x - self.drag_offset.x;
-            self.rect.y = mouse_pos.y - self.drag_offset.y;
+impl Pickable for SpritePick<'_> {
+    fn pick(&self, point: Vec2) -> Option<Vec2> {
+        if !self.mask.contains(self.player, self.params, self.world, point) {
+            return None;
         }
+        to_local(self.world, point)
     }
+}
+
+/// A rapier collider, hit through a point query against its shape. The local
+/// point is in the collider's frame.
+pub struct ColliderPick<'a> {
+    pub colliders: &'a ColliderSet,
+    pub handle: ColliderHandle,
+}
 
-    fn stop_drag(&mut self) {
-        self.is_dragging = false;
+impl Pickable for ColliderPick<'_> {
+    fn pick(&self, point: Vec2) -> Option<Vec2> {
+        let collider = self.colliders.get(self.handle)?;
+        let point = Point::new(point.x, point.y);
+        if !collider.shape().contains_point(collider.position(), &point) {
+            return None;
+        }
+        let local = collider.position().inverse_transform_point(&point);
+        Some(vec2(local.x, local.y))
     }
 }
 
-#[macroquad::main("Interactive Rectangles")]
-async fn main() {
-    let mut rectangles = vec![
-        Rectangle::new(100.0, 100.0, 100.0, 100.0, BLUE),
-        Rectangle::new(250.0, 100.0, 100.0, 100.0, RED),
-        Rectangle::new(400.0, 100.0, 100.0, 100.0, GREEN),
-    ];
+/// Drags a rigid body by a point on it. Dynamic bodies are pulled there by a
+/// damped spring, like a mouse joint, so they still collide and keep their
+/// momentum when let go; other bodies are moved directly.
+#[derive(Debug, Clone, Copy)]
+pub struct BodyDrag {
+    pub body: RigidBodyHandle,
+    /// The grabbed point in the body's local space.
+    pub anchor: Vec2,
+    /// How quickly the spring pulls, in oscillations per second.
+    pub frequency: f32,
+    /// 1 for critical damping, lower to overshoot and wobble.
+    pub damping_ratio: f32,
+}
 
-    loop {
-        clear_background(WHITE);
+impl BodyDrag {
+    /// Grabs `body` at the world-space `point`.
+    pub fn new(bodies: &RigidBodySet, body: RigidBodyHandle, point: Vec2) -> Option<Self> {
+        let anchor = bodies
+            .get(body)?
+            .position()
+            .inverse_transform_point(&Point::new(point.x, point.y));
+        Some(BodyDrag {
+            body,
+            anchor: vec2(anchor.x, anchor.y),
+            frequency: 5.0,
+            damping_ratio: 0.7,
+        })
+    }
 
-        let mouse_pos = Vec2::new(mouse_position().0, mouse_position().1);
-        let mouse_pressed = is_mouse_button_pressed(MouseButton::Left);
-        let mouse_down = is_mouse_button_down(MouseButton::Left);
+    /// Pulls the anchor towards `target`; call once per physics step.
+    pub fn update(&self, bodies: &mut RigidBodySet, target: Vec2, dt: f32) {
+        let Some(body) = bodies.get_mut(self.body) else {
+            return;
+        };
+        let anchor = body.position() * Point::new(self.anchor.x, self.anchor.y);
+        let target = Point::new(target.x, target.y);
 
-        for rect in rectangles.iter_mut() {
-            if rect.contains(mouse_pos) {
-                if mouse_pressed {
-                    rect.start_drag(mouse_pos);
-                }
-                rect.color.a = 0.7; // Change transparency on hover
+        if !body.is_dynamic() {
+            let translation = body.translation() + (target - anchor);
+            if body.body_type() == RigidBodyType::KinematicPositionBased {
+                body.set_next_kinematic_translation(translation);
             } else {
-                rect.color.a = 1.0; // Reset transparency when not hovering
+                body.set_translation(translation, true);
             }
+            return;
+        }
+
+        // Spring constants scaled by mass so the feel doesn't depend on it
+        let omega = 2.0 * std::f32::consts::PI * self.frequency;
+        let stiffness = body.mass() * omega * omega;
+        let damping = 2.0 * body.mass() * self.damping_ratio * omega;
+        let force: Vector<Real> = (target - anchor) * stiffness - body.velocity_at_point(&anchor) * damping;
+        body.apply_impulse_at_point(force * dt, anchor, true);
+    }
+}
+
+/// Items drawn back to front, for things not in a
+/// [`SceneGraph`](crate::scene::SceneGraph). Picking walks it front to back.
+#[derive(Debug, Clone)]
+pub struct ZOrder<Id> {
+    items: Vec<Id>,
+}
+
+impl<Id> Default for ZOrder<Id> {
+    fn default() -> Self {
+        ZOrder { items: Vec::new() }
+    }
+}
+
+impl<Id: Copy + PartialEq> ZOrder<Id> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an item on top of the others.
+    pub fn push(&mut self, id: Id) {
+        self.items.push(id);
+    }
+
+    pub fn remove(&mut self, id: Id) {
+        self.items.retain(|item| *item != id);
+    }
+
+    /// Moves an item in front of all the others.
+    pub fn raise(&mut self, id: Id) {
+        if let Some(index) = self.items.iter().position(|item| *item == id) {
+            let item = self.items.remove(index);
+            self.items.push(item);
+        }
+    }
+
+    /// Back to front, the order to draw in.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Id> + '_ {
+        self.items.iter().copied()
+    }
+
+    /// The topmost item `hit` accepts, with whatever point `hit` returns for it,
+    /// typically the point in the item's local space.
+    pub fn pick(&self, mut hit: impl FnMut(Id) -> Option<Vec2>) -> Option<(Id, Vec2)> {
+        self.iter().rev().find_map(|id| hit(id).map(|local| (id, local)))
+    }
+}
+
+/// Pointer interaction reported by [`Picker`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PickEvent<Id> {
+    HoverEnter(Id),
+    HoverLeave(Id),
+    /// `grab` is the pressed point in the item's local space.
+    DragStart { id: Id, point: Vec2, grab: Vec2 },
+    /// Only sent when the pointer actually moves.
+    DragMove { id: Id, point: Vec2, delta: Vec2 },
+    DragEnd { id: Id, point: Vec2 },
+}
+
+/// An item being dragged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Drag<Id> {
+    pub id: Id,
+    /// Where the item was grabbed, in its local space at the time.
+    pub grab: Vec2,
+    pub start: Vec2,
+    pub point: Vec2,
+}
+
+/// Tracks hover and drag state across frames. Only the topmost item under the
+/// pointer is hovered, and at most one item is dragged; while dragging, the
+/// dragged item stays hovered and nothing else is hit tested.
+#[derive(Debug, Clone)]
+pub struct Picker<Id> {
+    hovered: Option<Id>,
+    drag: Option<Drag<Id>>,
+    events: Vec<PickEvent<Id>>,
+}
 
-            if mouse_down {
-                rect.update_position(mouse_pos);
+impl<Id> Default for Picker<Id> {
+    fn default() -> Self {
+        Picker {
+            hovered: None,
+            drag: None,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl<Id: Copy + PartialEq> Picker<Id> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn hovered(&self) -> Option<Id> {
+        self.hovered
+    }
+
+    pub fn drag(&self) -> Option<&Drag<Id>> {
+        self.drag.as_ref()
+    }
+
+    pub fn is_dragging(&self, id: Id) -> bool {
+        self.drag.is_some_and(|drag| drag.id == id)
+    }
+
+    /// Updates from the left mouse button with the pointer at `point`. `pick`
+    /// returns the topmost item under a point and the point in its local space.
+    pub fn update(&mut self, point: Vec2, pick: impl FnOnce(Vec2) -> Option<(Id, Vec2)>) -> &[PickEvent<Id>] {
+        self.handle(
+            point,
+            is_mouse_button_pressed(MouseButton::Left),
+            is_mouse_button_down(MouseButton::Left),
+            pick,
+        )
+    }
+
+    /// As [`Picker::update`], with the button state supplied.
+    pub fn handle(
+        &mut self,
+        point: Vec2,
+        pressed: bool,
+        down: bool,
+        pick: impl FnOnce(Vec2) -> Option<(Id, Vec2)>,
+    ) -> &[PickEvent<Id>] {
+        self.events.clear();
+
+        if let Some(drag) = &mut self.drag {
+            if !down {
+                self.events.push(PickEvent::DragEnd { id: drag.id, point });
+                self.drag = None;
             } else {
-                rect.stop_drag();
+                if point != drag.point {
+                    self.events.push(PickEvent::DragMove {
+                        id: drag.id,
+                        point,
+                        delta: point - drag.point,
+                    });
+                    drag.point = point;
+                }
+                return &self.events;
             }
-
-            rect.draw();
         }
 
-        draw_text(
-            "Hover over rectangles and drag them!",
-            20.0,
-            20.0,
-            30.0,
-            BLACK,
-        );
+        let top = pick(point);
+        let hovered = top.map(|(id, _)| id);
+        if hovered != self.hovered {
+            if let Some(left) = self.hovered {
+                self.events.push(PickEvent::HoverLeave(left));
+            }
+            if let Some(entered) = hovered {
+                self.events.push(PickEvent::HoverEnter(entered));
+            }
+            self.hovered = hovered;
+        }
+        if let (true, Some((id, grab))) = (pressed, top) {
+            self.drag = Some(Drag {
+                id,
+                grab,
+                start: point,
+                point,
+            });
+            self.events.push(PickEvent::DragStart { id, point, grab });
+        }
+        &self.events
+    }
 
-        next_frame().await
+    /// Drops hover and drag state without events, e.g. once the item is deleted.
+    pub fn forget(&mut self, id: Id) {
+        if self.hovered == Some(id) {
+            self.hovered = None;
+        }
+        if self.is_dragging(id) {
+            self.drag = None;
+        }
     }
 }
@@ -0,0 +1,405 @@
+use crate::aseprite::SPRITE_VERTEX_SHADER;
+use crate::material::{RESOLUTION_UNIFORM, TIME_UNIFORM};
+use macroquad::prelude::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+const CRT_SHADER: &str = r#"#version 100
+precision mediump float;
+varying lowp vec4 color;
+varying lowp vec2 uv;
+uniform sampler2D Texture;
+uniform vec2 iResolution;
+uniform float curvature;
+uniform float scanlines;
+
+void main() {
+    // Barrel distortion, pushing the edges outwards
+    vec2 centered = uv * 2.0 - 1.0;
+    centered *= 1.0 + curvature * dot(centered, centered);
+    vec2 coord = centered * 0.5 + 0.5;
+    if (coord.x < 0.0 || coord.x > 1.0 || coord.y < 0.0 || coord.y > 1.0) {
+        gl_FragColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+    vec4 texel = texture2D(Texture, coord);
+    float line = 0.5 + 0.5 * sin(coord.y * iResolution.y * 3.14159);
+    gl_FragColor = vec4(texel.rgb * (1.0 - scanlines * line), texel.a);
+}
+"#;
+
+const BLOOM_SHADER: &str = r#"#version 100
+precision mediump float;
+varying lowp vec4 color;
+varying lowp vec2 uv;
+uniform sampler2D Texture;
+uniform vec2 iResolution;
+uniform float threshold;
+uniform float intensity;
+uniform float spread;
+
+void main() {
+    vec2 texel = spread / iResolution;
+    vec4 base = texture2D(Texture, uv);
+    vec3 glow = vec3(0.0);
+    for (int x = -3; x <= 3; x++) {
+        for (int y = -3; y <= 3; y++) {
+            vec3 tap = texture2D(Texture, uv + vec2(float(x), float(y)) * texel).rgb;
+            glow += max(tap - threshold, 0.0);
+        }
+    }
+    gl_FragColor = vec4(base.rgb + glow / 49.0 * intensity, base.a);
+}
+"#;
+
+const VIGNETTE_SHADER: &str = r#"#version 100
+precision mediump float;
+varying lowp vec4 color;
+varying lowp vec2 uv;
+uniform sampler2D Texture;
+uniform float radius;
+uniform float strength;
+
+void main() {
+    vec4 texel = texture2D(Texture, uv);
+    float shade = smoothstep(radius, radius - 0.4, distance(uv, vec2(0.5)));
+    gl_FragColor = vec4(texel.rgb * mix(1.0, shade, strength), texel.a);
+}
+"#;
+
+const PALETTE_SHADER: &str = r#"#version 100
+precision mediump float;
+varying lowp vec4 color;
+varying lowp vec2 uv;
+uniform sampler2D Texture;
+uniform float levels;
+
+void main() {
+    vec4 texel = texture2D(Texture, uv);
+    float steps = max(levels - 1.0, 1.0);
+    gl_FragColor = vec4(floor(texel.rgb * steps + 0.5) / steps, texel.a);
+}
+"#;
+
+const PIXELATE_SHADER: &str = r#"#version 100
+precision mediump float;
+varying lowp vec4 color;
+varying lowp vec2 uv;
+uniform sampler2D Texture;
+uniform vec2 iResolution;
+uniform float pixel_size;
+
+void main() {
+    vec2 size = max(pixel_size, 1.0) / iResolution;
+    gl_FragColor = texture2D(Texture, (floor(uv / size) + 0.5) * size);
+}
+"#;
+
+/// Built-in fullscreen effects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Effect {
+    /// Curved screen with scanlines.
+    Crt,
+    /// Glow around pixels brighter than a threshold.
+    Bloom,
+    /// Darkened corners.
+    Vignette,
+    /// Quantizes each colour channel to a few levels.
+    Palette,
+    /// Blocks of `pixel_size` pixels.
+    Pixelate,
+}
+
+impl Effect {
+    fn fragment_shader(self) -> &'static str {
+        match self {
+            Effect::Crt => CRT_SHADER,
+            Effect::Bloom => BLOOM_SHADER,
+            Effect::Vignette => VIGNETTE_SHADER,
+            Effect::Palette => PALETTE_SHADER,
+            Effect::Pixelate => PIXELATE_SHADER,
+        }
+    }
+
+    /// Tunable parameters and their defaults.
+    pub fn params(self) -> &'static [(&'static str, f32)] {
+        match self {
+            Effect::Crt => &[("curvature", 0.08), ("scanlines", 0.25)],
+            Effect::Bloom => &[("threshold", 0.6), ("intensity", 1.5), ("spread", 2.0)],
+            Effect::Vignette => &[("radius", 0.75), ("strength", 0.6)],
+            Effect::Palette => &[("levels", 6.0)],
+            Effect::Pixelate => &[("pixel_size", 4.0)],
+        }
+    }
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PassConfig {
+    pub effect: Effect,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    /// Overrides for [`Effect::params`].
+    #[serde(default)]
+    pub params: BTreeMap<String, f32>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PostProcessConfig {
+    /// Applied in order, each reading the previous pass's output.
+    #[serde(default)]
+    pub passes: Vec<PassConfig>,
+    /// Renders the scene at this size and upscales it by the largest whole factor
+    /// that fits the window, for crisp pixel art.
+    #[serde(default)]
+    pub low_resolution: Option<(u32, u32)>,
+}
+
+pub struct Pass {
+    effect: Effect,
+    material: Material,
+    enabled: bool,
+    params: BTreeMap<String, f32>,
+}
+
+impl Pass {
+    pub fn effect(&self) -> Effect {
+        self.effect
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+struct Targets {
+    size: UVec2,
+    scene: RenderTarget,
+    ping_pong: [RenderTarget; 2],
+}
+
+impl Targets {
+    fn new(size: UVec2) -> Self {
+        let target = || {
+            let target = render_target(size.x, size.y);
+            target.texture.set_filter(FilterMode::Nearest);
+            target
+        };
+        Targets {
+            size,
+            scene: target(),
+            ping_pong: [target(), target()],
+        }
+    }
+}
+
+/// Renders a scene into a texture, runs it through a chain of fullscreen
+/// effects and draws the result to the screen.
+///
+/// Draw the scene between [`PostProcess::begin`] and [`PostProcess::end`].
+pub struct PostProcess {
+    passes: Vec<Pass>,
+    low_resolution: Option<UVec2>,
+    targets: Option<Targets>,
+}
+
+impl PostProcess {
+    pub fn new(config: PostProcessConfig) -> Result<Self, macroquad::Error> {
+        let mut passes = Vec::with_capacity(config.passes.len());
+        for pass in config.passes {
+            let effect = pass.effect;
+            let mut uniforms = vec![
+                UniformDesc::new(TIME_UNIFORM, UniformType::Float1),
+                UniformDesc::new(RESOLUTION_UNIFORM, UniformType::Float2),
+            ];
+            uniforms.extend(
+                effect
+                    .params()
+                    .iter()
+                    .map(|(name, _)| UniformDesc::new(name, UniformType::Float1)),
+            );
+            let material = load_material(
+                ShaderSource::Glsl {
+                    vertex: SPRITE_VERTEX_SHADER,
+                    fragment: effect.fragment_shader(),
+                },
+                MaterialParams {
+                    uniforms,
+                    ..Default::default()
+                },
+            )?;
+            let mut params: BTreeMap<String, f32> = effect
+                .params()
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect();
+            for (name, value) in pass.params {
+                if !params.contains_key(&name) {
+                    warn!("{:?} has no parameter {:?}", effect, name);
+                    continue;
+                }
+                params.insert(name, value);
+            }
+            passes.push(Pass {
+                effect,
+                material,
+                enabled: pass.enabled,
+                params,
+            });
+        }
+
+        Ok(PostProcess {
+            passes,
+            low_resolution: config.low_resolution.map(UVec2::from),
+            targets: None,
+        })
+    }
+
+    pub async fn load(json_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let json_data = load_string(json_path).await?;
+        Ok(PostProcess::new(serde_json::from_str(&json_data)?)?)
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    /// Enables or disables every pass of `effect`.
+    pub fn set_enabled(&mut self, effect: Effect, enabled: bool) {
+        for pass in self.passes.iter_mut().filter(|pass| pass.effect == effect) {
+            pass.enabled = enabled;
+        }
+    }
+
+    pub fn toggle(&mut self, effect: Effect) {
+        for pass in self.passes.iter_mut().filter(|pass| pass.effect == effect) {
+            pass.enabled = !pass.enabled;
+        }
+    }
+
+    pub fn set_param(&mut self, effect: Effect, name: &str, value: f32) -> Result<(), String> {
+        let mut found = false;
+        for pass in self.passes.iter_mut().filter(|pass| pass.effect == effect) {
+            if let Some(param) = pass.params.get_mut(name) {
+                *param = value;
+                found = true;
+            }
+        }
+        if found {
+            Ok(())
+        } else {
+            Err(format!("no {:?} pass with a parameter {:?}", effect, name))
+        }
+    }
+
+    pub fn set_low_resolution(&mut self, size: Option<(u32, u32)>) {
+        self.low_resolution = size.map(UVec2::from);
+    }
+
+    /// Size of the texture the scene is drawn into.
+    pub fn resolution(&self) -> UVec2 {
+        self.low_resolution
+            .unwrap_or_else(|| uvec2(screen_width() as u32, screen_height() as u32))
+            .max(UVec2::ONE)
+    }
+
+    /// Where the processed image is drawn on screen. In low-resolution mode it is
+    /// scaled by a whole factor and centred.
+    pub fn output_rect(&self) -> Rect {
+        match self.low_resolution {
+            Some(size) => {
+                let size = size.as_vec2();
+                let scale = (screen_width() / size.x).min(screen_height() / size.y).floor().max(1.0);
+                let output = size * scale;
+                Rect::new(
+                    ((screen_width() - output.x) / 2.0).floor(),
+                    ((screen_height() - output.y) / 2.0).floor(),
+                    output.x,
+                    output.y,
+                )
+            }
+            None => Rect::new(0.0, 0.0, screen_width(), screen_height()),
+        }
+    }
+
+    /// Maps a screen position, e.g. the mouse, to a position in `view`.
+    pub fn screen_to_view(&self, point: Vec2, view: Rect) -> Vec2 {
+        let output = self.output_rect();
+        view.point() + (point - output.point()) / output.size() * view.size()
+    }
+
+    /// Maps a position in `view` to the screen, for overlays drawn after
+    /// [`PostProcess::end`].
+    pub fn view_to_screen(&self, point: Vec2, view: Rect) -> Vec2 {
+        let output = self.output_rect();
+        output.point() + (point - view.point()) / view.size() * output.size()
+    }
+
+    /// Starts drawing the scene with `view`, the world rect to show, y down.
+    pub fn begin(&mut self, view: Rect) {
+        let size = self.resolution();
+        if self.targets.as_ref().is_none_or(|targets| targets.size != size) {
+            self.targets = Some(Targets::new(size));
+        }
+        let Some(targets) = &self.targets else {
+            return;
+        };
+        set_camera(&Camera2D {
+            render_target: Some(targets.scene.clone()),
+            ..Camera2D::from_display_rect(view)
+        });
+    }
+
+    /// Runs the enabled passes and draws the result to the screen, clearing any
+    /// letterbox borders to black and leaving the default camera set.
+    pub fn end(&mut self) {
+        let Some(targets) = &self.targets else {
+            set_default_camera();
+            return;
+        };
+        let size = targets.size.as_vec2();
+        let params = DrawTextureParams {
+            dest_size: Some(size),
+            // Render targets are stored upside down
+            flip_y: true,
+            ..Default::default()
+        };
+
+        let mut source = &targets.scene;
+        for (index, pass) in self.passes.iter().filter(|pass| pass.enabled).enumerate() {
+            let destination = &targets.ping_pong[index % 2];
+            set_camera(&Camera2D {
+                render_target: Some(destination.clone()),
+                ..Camera2D::from_display_rect(Rect::new(0.0, 0.0, size.x, size.y))
+            });
+            clear_background(BLANK);
+            pass.material.set_uniform(TIME_UNIFORM, get_time() as f32);
+            pass.material.set_uniform(RESOLUTION_UNIFORM, size);
+            for (name, value) in &pass.params {
+                pass.material.set_uniform(name, *value);
+            }
+            gl_use_material(&pass.material);
+            draw_texture_ex(&source.texture, 0.0, 0.0, WHITE, params.clone());
+            gl_use_default_material();
+            source = destination;
+        }
+
+        set_default_camera();
+        clear_background(BLACK);
+        let output = self.output_rect();
+        draw_texture_ex(
+            &source.texture,
+            output.x,
+            output.y,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(output.size()),
+                ..params
+            },
+        );
+    }
+}
@@ -0,0 +1,405 @@
+use crate::picking::to_local;
+use crate::transform::{Transform, TransformStack};
+use macroquad::prelude::*;
+use std::cell::Cell;
+
+/// Identifies a node in a [`SceneGraph`]. Ids of removed nodes are never reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: usize,
+    generation: u32,
+}
+
+struct Node<T> {
+    value: T,
+    local: Transform,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    visible: bool,
+    world: Cell<Mat3>,
+    dirty: Cell<bool>,
+}
+
+struct Slot<T> {
+    generation: u32,
+    node: Option<Node<T>>,
+}
+
+/// A subtree taken out by [`SceneGraph::detach`]. Its ids stay reserved so
+/// [`SceneGraph::restore`] brings the nodes back under the same ids; dropping it
+/// leaves those slots unused for good.
+pub struct Detached<T> {
+    id: NodeId,
+    parent: Option<NodeId>,
+    /// Index among the parent's children, or the roots.
+    position: usize,
+    nodes: Vec<(NodeId, Node<T>)>,
+}
+
+impl<T> Detached<T> {
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+}
+
+/// A tree of nodes, each with a local [`Transform`] relative to its parent and a
+/// value to draw. World matrices are cached and recomputed only after a node or
+/// one of its ancestors moves.
+pub struct SceneGraph<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+    roots: Vec<NodeId>,
+}
+
+impl<T> Default for SceneGraph<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> SceneGraph<T> {
+    pub fn new() -> Self {
+        SceneGraph {
+            slots: Vec::new(),
+            free: Vec::new(),
+            roots: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, value: T, local: Transform) -> NodeId {
+        let id = self.insert(value, local, None);
+        self.roots.push(id);
+        id
+    }
+
+    /// Adds a node drawn after, and so on top of, `parent`'s existing children.
+    /// Returns `None` if `parent` doesn't exist.
+    pub fn add_child(&mut self, parent: NodeId, value: T, local: Transform) -> Option<NodeId> {
+        self.node(parent)?;
+        let id = self.insert(value, local, Some(parent));
+        self.node_mut(parent)?.children.push(id);
+        Some(id)
+    }
+
+    /// Removes a node and all its descendants, returning the node's value.
+    pub fn remove(&mut self, id: NodeId) -> Option<T> {
+        let parent = self.node(id)?.parent;
+        self.siblings_mut(parent).retain(|sibling| *sibling != id);
+
+        let mut stack = vec![id];
+        let mut value = None;
+        while let Some(next) = stack.pop() {
+            let slot = &mut self.slots[next.index];
+            let node = slot.node.take()?;
+            slot.generation += 1;
+            self.free.push(next.index);
+            stack.extend(node.children);
+            if next == id {
+                value = Some(node.value);
+            }
+        }
+        value
+    }
+
+    /// Takes a node and its descendants out of the graph, keeping their ids for
+    /// [`SceneGraph::restore`], e.g. so an undone delete doesn't invalidate ids
+    /// held elsewhere.
+    pub fn detach(&mut self, id: NodeId) -> Option<Detached<T>> {
+        let parent = self.node(id)?.parent;
+        let siblings = self.siblings_mut(parent);
+        let position = siblings.iter().position(|sibling| *sibling == id)?;
+        siblings.remove(position);
+
+        let mut nodes = Vec::new();
+        let mut stack = vec![id];
+        while let Some(next) = stack.pop() {
+            if let Some(node) = self.slots[next.index].node.take() {
+                stack.extend(&node.children);
+                nodes.push((next, node));
+            }
+        }
+        Some(Detached {
+            id,
+            parent,
+            position,
+            nodes,
+        })
+    }
+
+    /// Puts a detached subtree back where it was. Fails, handing it back, if its
+    /// parent has since been removed.
+    pub fn restore(&mut self, detached: Detached<T>) -> Result<NodeId, Detached<T>> {
+        if detached.parent.is_some_and(|parent| !self.contains(parent)) {
+            return Err(detached);
+        }
+        let Detached {
+            id,
+            parent,
+            position,
+            nodes,
+        } = detached;
+        for (node_id, node) in nodes {
+            node.dirty.set(true);
+            self.slots[node_id.index].node = Some(node);
+        }
+        let siblings = self.siblings_mut(parent);
+        siblings.insert(position.min(siblings.len()), id);
+        Ok(id)
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.node(id).is_some()
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.node(id).map(|node| &node.value)
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+        self.node_mut(id).map(|node| &mut node.value)
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id)?.parent
+    }
+
+    /// Children in draw order.
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        self.node(id).map_or(&[], |node| &node.children)
+    }
+
+    /// Top-level nodes in draw order.
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    /// All live nodes, in no particular order.
+    pub fn ids(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.slots.iter().enumerate().filter(|(_, slot)| slot.node.is_some()).map(|(index, slot)| NodeId {
+            index,
+            generation: slot.generation,
+        })
+    }
+
+    pub fn transform(&self, id: NodeId) -> Option<Transform> {
+        self.node(id).map(|node| node.local)
+    }
+
+    pub fn set_transform(&mut self, id: NodeId, local: Transform) -> bool {
+        self.update_transform(id, |transform| *transform = local)
+    }
+
+    /// Edits the local transform in place and marks the subtree for recomputation.
+    pub fn update_transform(&mut self, id: NodeId, update: impl FnOnce(&mut Transform)) -> bool {
+        let Some(node) = self.node_mut(id) else {
+            return false;
+        };
+        update(&mut node.local);
+        self.mark_dirty(id);
+        true
+    }
+
+    pub fn is_visible(&self, id: NodeId) -> bool {
+        self.node(id).is_some_and(|node| node.visible)
+    }
+
+    /// Hidden nodes are skipped by [`SceneGraph::draw`] along with their children.
+    pub fn set_visible(&mut self, id: NodeId, visible: bool) {
+        if let Some(node) = self.node_mut(id) {
+            node.visible = visible;
+        }
+    }
+
+    /// The node's local-to-world matrix.
+    pub fn world_matrix(&self, id: NodeId) -> Option<Mat3> {
+        let node = self.node(id)?;
+        if node.dirty.get() {
+            let parent = match node.parent {
+                Some(parent) => self.world_matrix(parent)?,
+                None => Mat3::IDENTITY,
+            };
+            node.world.set(parent * node.local.to_matrix());
+            node.dirty.set(false);
+        }
+        Some(node.world.get())
+    }
+
+    pub fn world_transform(&self, id: NodeId) -> Option<Transform> {
+        self.world_matrix(id).map(Transform::from_matrix)
+    }
+
+    /// Moves a node, with its subtree, under `parent` or to the top level, keeping
+    /// where it is in the world. Fails if either node doesn't exist or `parent` is
+    /// inside the node's own subtree.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+        if let Some(parent) = parent {
+            if !self.contains(parent) || self.is_ancestor(id, parent) {
+                return false;
+            }
+        }
+
+        let world = self.world_matrix(id).unwrap_or(Mat3::IDENTITY);
+        let parent_world = parent.and_then(|parent| self.world_matrix(parent)).unwrap_or(Mat3::IDENTITY);
+
+        let old_parent = self.node(id).and_then(|node| node.parent);
+        self.siblings_mut(old_parent).retain(|sibling| *sibling != id);
+        self.siblings_mut(parent).push(id);
+        if let Some(node) = self.node_mut(id) {
+            node.parent = parent;
+            node.local = Transform::from_matrix(parent_world.inverse() * world);
+        }
+        self.mark_dirty(id);
+        true
+    }
+
+    /// Whether `ancestor` is `id` or one of its ancestors.
+    pub fn is_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut current = Some(id);
+        while let Some(next) = current {
+            if next == ancestor {
+                return true;
+            }
+            current = self.parent(next);
+        }
+        false
+    }
+
+    /// Moves the node to the end of its siblings so it draws on top of them.
+    pub fn raise(&mut self, id: NodeId) {
+        let Some(parent) = self.node(id).map(|node| node.parent) else {
+            return;
+        };
+        let siblings = self.siblings_mut(parent);
+        siblings.retain(|sibling| *sibling != id);
+        siblings.push(id);
+    }
+
+    /// Raises the node and each of its ancestors, so the node draws on top of
+    /// everything outside its own subtree.
+    pub fn raise_to_front(&mut self, id: NodeId) {
+        let mut current = Some(id);
+        while let Some(next) = current {
+            self.raise(next);
+            current = self.parent(next);
+        }
+    }
+
+    /// Visible nodes in the order [`SceneGraph::draw`] visits them; iterate in
+    /// reverse to find the topmost node under a point.
+    pub fn draw_order(&self) -> Vec<NodeId> {
+        let mut order = Vec::new();
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            if let Some(node) = self.node(id).filter(|node| node.visible) {
+                order.push(id);
+                stack.extend(node.children.iter().rev());
+            }
+        }
+        order
+    }
+
+    /// The topmost visible node `hit` accepts, given the point in the node's local
+    /// space, along with that local point.
+    pub fn pick(&self, point: Vec2, mut hit: impl FnMut(NodeId, &T, Vec2) -> bool) -> Option<(NodeId, Vec2)> {
+        self.draw_order().into_iter().rev().find_map(|id| {
+            let local = to_local(self.world_matrix(id)?, point)?;
+            hit(id, self.get(id)?, local).then_some((id, local))
+        })
+    }
+
+    /// Visits visible nodes depth first, parents before children, with each
+    /// node's world matrix.
+    pub fn draw(&self, mut draw_node: impl FnMut(NodeId, &T, Mat3)) {
+        let mut stack = TransformStack::new();
+        for root in &self.roots {
+            self.draw_node(*root, &mut stack, &mut draw_node);
+        }
+    }
+
+    fn draw_node(&self, id: NodeId, stack: &mut TransformStack, draw_node: &mut impl FnMut(NodeId, &T, Mat3)) {
+        let Some(node) = self.node(id) else {
+            return;
+        };
+        if !node.visible {
+            return;
+        }
+        stack.push(node.local);
+        let world = stack.current();
+        node.world.set(world);
+        node.dirty.set(false);
+        draw_node(id, &node.value, world);
+        for child in &node.children {
+            self.draw_node(*child, stack, draw_node);
+        }
+        stack.pop();
+    }
+
+    fn insert(&mut self, value: T, local: Transform, parent: Option<NodeId>) -> NodeId {
+        let node = Node {
+            value,
+            local,
+            parent,
+            children: Vec::new(),
+            visible: true,
+            world: Cell::new(Mat3::IDENTITY),
+            dirty: Cell::new(true),
+        };
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.node = Some(node);
+                NodeId {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    node: Some(node),
+                });
+                NodeId {
+                    index: self.slots.len() - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    fn node(&self, id: NodeId) -> Option<&Node<T>> {
+        self.slots
+            .get(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Option<&mut Node<T>> {
+        self.slots
+            .get_mut(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+    }
+
+    fn siblings_mut(&mut self, parent: Option<NodeId>) -> &mut Vec<NodeId> {
+        match parent.and_then(|parent| self.slots[parent.index].node.as_mut()) {
+            Some(parent) => &mut parent.children,
+            None => &mut self.roots,
+        }
+    }
+
+    fn mark_dirty(&self, id: NodeId) {
+        let mut stack = vec![id];
+        while let Some(next) = stack.pop() {
+            if let Some(node) = self.node(next) {
+                // A dirty node's subtree is already dirty
+                if node.dirty.replace(true) && next != id {
+                    continue;
+                }
+                stack.extend(&node.children);
+            }
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use macroquad::prelude::*;
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+/// Radians between successive fallback directions, so coincident units spread
+/// evenly however many share a spot.
+const GOLDEN_ANGLE: f32 = 2.399_963;
+
+/// A uniform grid of buckets for finding what's near a point without testing
+/// everything. Items are indices into the caller's own list of positions.
+#[derive(Debug, Clone)]
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>, BuildHasherDefault<CellHasher>>,
+}
+
+/// Multiplicative hashing for cell coordinates, much cheaper than the default
+/// hasher, which is built to resist adversarial keys.
+#[derive(Default)]
+struct CellHasher(u64);
+
+impl Hasher for CellHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_u64(*byte as u64);
+        }
+    }
+
+    fn write_i32(&mut self, value: i32) {
+        self.write_u64(value as u32 as u64);
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0 = (self.0.rotate_left(5) ^ value).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+}
+
+impl SpatialHash {
+    /// Queries are fastest with cells about the size of the query radius.
+    pub fn new(cell_size: f32) -> Self {
+        SpatialHash {
+            cell_size: cell_size.max(f32::EPSILON),
+            cells: HashMap::default(),
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    /// Empties the grid, keeping the buckets that were in use so refilling it
+    /// every frame doesn't allocate.
+    pub fn clear(&mut self) {
+        self.cells.retain(|_, items| {
+            let used = !items.is_empty();
+            items.clear();
+            used
+        });
+    }
+
+    pub fn insert(&mut self, index: usize, point: Vec2) {
+        self.cells.entry(self.cell(point)).or_default().push(index);
+    }
+
+    /// Moves an item to the cell of its new position, if that's a different one.
+    pub fn relocate(&mut self, index: usize, from: Vec2, to: Vec2) {
+        let (old, new) = (self.cell(from), self.cell(to));
+        if old == new {
+            return;
+        }
+        if let Some(items) = self.cells.get_mut(&old) {
+            if let Some(position) = items.iter().position(|item| *item == index) {
+                items.swap_remove(position);
+            }
+        }
+        self.insert(index, to);
+    }
+
+    /// Refills the grid with `points`, each stored as its index.
+    pub fn rebuild(&mut self, points: &[Vec2]) {
+        self.clear();
+        for (index, point) in points.iter().enumerate() {
+            self.insert(index, *point);
+        }
+    }
+
+    fn cell(&self, point: Vec2) -> (i32, i32) {
+        let cell = (point / self.cell_size).floor();
+        (cell.x as i32, cell.y as i32)
+    }
+
+    /// The first and last cells [`SpatialHash::query`] visits.
+    fn cells_around(&self, point: Vec2, radius: f32) -> ((i32, i32), (i32, i32)) {
+        (self.cell(point - radius), self.cell(point + radius))
+    }
+
+    /// Calls `visit` with every item in the cells within `radius` of `point` on
+    /// either axis: a superset of the items that near, for the caller to test.
+    pub fn query(&self, point: Vec2, radius: f32, mut visit: impl FnMut(usize)) {
+        let ((min_x, min_y), (max_x, max_y)) = self.cells_around(point, radius);
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                for &index in self.cells.get(&(x, y)).into_iter().flatten() {
+                    visit(index);
+                }
+            }
+        }
+    }
+
+    /// Indices of the `points` within `radius` of `point`, in ascending order.
+    pub fn within(&self, points: &[Vec2], point: Vec2, radius: f32) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.query(point, radius, |index| {
+            if points[index].distance_squared(point) <= radius * radius {
+                found.push(index);
+            }
+        });
+        found.sort_unstable();
+        found
+    }
+}
+
+/// Pushes apart squares of side `size`, placed by their top-left corners, that
+/// overlap. Each pair moves by half its overlap, at most `max_push` per call,
+/// so crowds settle rather than jitter.
+///
+/// Pairs are resolved in index order with the same result as a pairwise loop,
+/// but only neighbours found through `hash` are tested, so thousands of units
+/// are cheap. Pushed units are moved to their new cells straight away, and a
+/// unit's neighbours are found again once a push moves it to other cells.
+pub fn separate(positions: &mut [Vec2], size: f32, max_push: f32, hash: &mut SpatialHash) {
+    hash.rebuild(positions);
+    let mut neighbours = Vec::new();
+    for index in 0..positions.len() {
+        // The last neighbour tested; later ones are still to come
+        let mut last = index;
+        'search: loop {
+            let cells = hash.cells_around(positions[index], size);
+            neighbours.clear();
+            hash.query(positions[index], size, |other| {
+                if other > last {
+                    neighbours.push(other);
+                }
+            });
+            neighbours.sort_unstable();
+            for &other in &neighbours {
+                last = other;
+                // Only this unit and the ones already tested move, so the list
+                // holds until this unit's search covers other cells
+                if push_apart(positions, index, other, size, max_push, hash)
+                    && hash.cells_around(positions[index], size) != cells
+                {
+                    continue 'search;
+                }
+            }
+            break;
+        }
+    }
+}
+
+/// Returns whether the pair overlapped and was pushed.
+fn push_apart(positions: &mut [Vec2], first: usize, second: usize, size: f32, max_push: f32, hash: &mut SpatialHash) -> bool {
+    let (from_first, from_second) = (positions[first], positions[second]);
+    let offset = from_first - from_second;
+    let depth = (size - offset.x.abs()).min(size - offset.y.abs());
+    if depth <= 0.0 {
+        return false;
+    }
+    let direction = offset.try_normalize().unwrap_or_else(|| fallback_direction(first));
+    let push = direction * (depth / 2.0).min(max_push);
+    positions[first] += push;
+    positions[second] -= push;
+    hash.relocate(first, from_first, positions[first]);
+    hash.relocate(second, from_second, positions[second]);
+    true
+}
+
+/// Which way to push units that share a position, where there's no direction
+/// between them: fixed per unit, so runs are repeatable, and different for each
+/// unit so a stack fans out.
+pub fn fallback_direction(index: usize) -> Vec2 {
+    Vec2::from_angle(index as f32 * GOLDEN_ANGLE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coincident_pair_splits_along_fallback() {
+        let start = vec2(100.0, 100.0);
+        let mut positions = vec![start; 2];
+        separate(&mut positions, 16.0, 4.0, &mut SpatialHash::new(16.0));
+
+        let push = positions[0] - start;
+        assert!(push.length() > 0.0);
+        assert!(push.normalize().dot(fallback_direction(0)) > 0.99);
+        assert_eq!(positions[1] - start, -push);
+    }
+
+    #[test]
+    fn matches_pairwise_loop() {
+        // Scattered over about twice their area, so many overlap and some are
+        // pushed across cells
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 40) as f32 / (1u64 << 24) as f32
+        };
+        let side = (400.0f32 * 2.0).sqrt() * 16.0;
+        let start: Vec<Vec2> = (0..400).map(|_| vec2(next() * side, next() * side)).collect();
+
+        let mut hashed = start.clone();
+        let mut hash = SpatialHash::new(16.0);
+        let mut pairwise = start;
+        let mut unused = SpatialHash::new(16.0);
+        for _ in 0..3 {
+            separate(&mut hashed, 16.0, 4.0, &mut hash);
+            for first in 0..pairwise.len() {
+                for second in first + 1..pairwise.len() {
+                    push_apart(&mut pairwise, first, second, 16.0, 4.0, &mut unused);
+                }
+            }
+        }
+        assert_eq!(hashed, pairwise);
+    }
+
+    #[test]
+    fn coincident_stack_spreads_out() {
+        let mut positions = vec![vec2(100.0, 100.0); 5];
+        let mut hash = SpatialHash::new(16.0);
+        for _ in 0..50 {
+            separate(&mut positions, 16.0, 4.0, &mut hash);
+        }
+        for (index, position) in positions.iter().enumerate() {
+            for other in &positions[index + 1..] {
+                let offset = (*position - *other).abs();
+                assert!(offset.x >= 16.0 - 1e-3 || offset.y >= 16.0 - 1e-3, "{} and {} overlap", position, other);
+            }
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use crate::pathfinding::Grid;
+use crate::spatial::fallback_direction;
+use macroquad::prelude::*;
+use serde::Deserialize;
+use std::cmp::Ordering;
+
+/// Something steered: where it is and how it's moving.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Agent {
+    /// Stable across frames, e.g. a unit id; decides which way agents on the
+    /// same spot separate.
+    pub id: u32,
+    pub position: Vec2,
+    pub velocity: Vec2,
+}
+
+impl Agent {
+    pub fn new(id: u32, position: Vec2) -> Self {
+        Agent {
+            id,
+            position,
+            velocity: Vec2::ZERO,
+        }
+    }
+
+    /// Applies a steering force for `dt` seconds, within the profile's limits.
+    pub fn integrate(&mut self, force: Vec2, profile: &SteeringProfile, dt: f32) {
+        let force = force.clamp_length_max(profile.max_force);
+        self.velocity = (self.velocity + force * dt).clamp_length_max(profile.max_speed);
+        self.position += self.velocity * dt;
+    }
+}
+
+/// How much each behaviour counts in [`steer`].
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SteeringWeights {
+    /// Seeking, arriving or following a path, whichever the target asks for.
+    pub target: f32,
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+    pub avoidance: f32,
+}
+
+impl Default for SteeringWeights {
+    fn default() -> Self {
+        SteeringWeights {
+            target: 1.0,
+            separation: 1.5,
+            alignment: 0.0,
+            cohesion: 0.0,
+            avoidance: 2.0,
+        }
+    }
+}
+
+/// Movement limits and behaviour weights, typically one per unit type.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SteeringProfile {
+    pub max_speed: f32,
+    pub max_force: f32,
+    /// Half the body's width, kept clear of walls.
+    pub radius: f32,
+    /// Distance from an arrive target at which to start slowing down.
+    pub slowing_radius: f32,
+    /// Neighbours this close count for alignment and cohesion.
+    pub neighbour_radius: f32,
+    /// Neighbours this close are steered away from.
+    pub separation_radius: f32,
+    /// How far ahead to look for walls, in seconds at the current velocity.
+    pub look_ahead: f32,
+    pub weights: SteeringWeights,
+}
+
+impl Default for SteeringProfile {
+    fn default() -> Self {
+        SteeringProfile {
+            max_speed: 100.0,
+            max_force: 400.0,
+            radius: 10.0,
+            slowing_radius: 60.0,
+            neighbour_radius: 60.0,
+            separation_radius: 24.0,
+            look_ahead: 0.4,
+            weights: SteeringWeights::default(),
+        }
+    }
+}
+
+/// Where [`steer`] heads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target<'a> {
+    /// Brakes to a halt.
+    None,
+    /// Full speed towards a point, overshooting it.
+    Seek(Vec2),
+    /// Towards a point, slowing to stop on it.
+    Arrive(Vec2),
+    /// Along the waypoints, arriving at the last; see [`advance_path`].
+    Path(&'a [Vec2]),
+    /// At a velocity, e.g. from a flow field.
+    Velocity(Vec2),
+}
+
+/// Full speed towards `target`.
+pub fn seek(agent: &Agent, target: Vec2, max_speed: f32) -> Vec2 {
+    (target - agent.position).normalize_or_zero() * max_speed - agent.velocity
+}
+
+/// Towards `target`, slowing linearly within `slowing_radius` of it.
+pub fn arrive(agent: &Agent, target: Vec2, max_speed: f32, slowing_radius: f32) -> Vec2 {
+    let offset = target - agent.position;
+    let distance = offset.length();
+    if distance <= f32::EPSILON {
+        return -agent.velocity;
+    }
+    let speed = max_speed * (distance / slowing_radius.max(f32::EPSILON)).min(1.0);
+    offset / distance * speed - agent.velocity
+}
+
+/// Away from neighbours within `radius`, harder the closer they are.
+/// A pair on the same spot is pushed apart both ways along the lower id's
+/// fallback direction, as in [`separate`](crate::spatial::separate).
+pub fn separation(agent: &Agent, neighbours: &[Agent], radius: f32, max_speed: f32) -> Vec2 {
+    let push: Vec2 = neighbours
+        .iter()
+        .filter_map(|neighbour| {
+            let away = agent.position - neighbour.position;
+            let distance = away.length();
+            (distance < radius).then(|| {
+                let direction = away.try_normalize().unwrap_or_else(|| coincident_direction(agent.id, neighbour.id));
+                direction * (1.0 - distance / radius)
+            })
+        })
+        .sum();
+    push.clamp_length_max(1.0) * max_speed
+}
+
+/// Which way `id` moves away from `other` on the same spot: opposite to the
+/// way `other` moves away from `id`.
+fn coincident_direction(id: u32, other: u32) -> Vec2 {
+    match id.cmp(&other) {
+        Ordering::Less => fallback_direction(id as usize),
+        Ordering::Greater => -fallback_direction(other as usize),
+        Ordering::Equal => Vec2::ZERO,
+    }
+}
+
+/// Towards the average velocity of neighbours within `radius`.
+pub fn alignment(agent: &Agent, neighbours: &[Agent], radius: f32) -> Vec2 {
+    let (sum, count) = within(agent, neighbours, radius).fold((Vec2::ZERO, 0), |(sum, count), neighbour| {
+        (sum + neighbour.velocity, count + 1)
+    });
+    if count == 0 {
+        return Vec2::ZERO;
+    }
+    sum / count as f32 - agent.velocity
+}
+
+/// Towards the centre of neighbours within `radius`.
+pub fn cohesion(agent: &Agent, neighbours: &[Agent], radius: f32, max_speed: f32) -> Vec2 {
+    let (sum, count) = within(agent, neighbours, radius).fold((Vec2::ZERO, 0), |(sum, count), neighbour| {
+        (sum + neighbour.position, count + 1)
+    });
+    if count == 0 {
+        return Vec2::ZERO;
+    }
+    seek(agent, sum / count as f32, max_speed)
+}
+
+fn within<'a>(agent: &'a Agent, neighbours: &'a [Agent], radius: f32) -> impl Iterator<Item = &'a Agent> + 'a {
+    neighbours
+        .iter()
+        .filter(move |neighbour| neighbour.position.distance_squared(agent.position) < radius * radius)
+}
+
+/// Sideways from walls ahead, felt for along three rays: straight ahead and
+/// angled to either side. Braking too when the way straight ahead is blocked.
+pub fn avoid_obstacles(agent: &Agent, grid: &Grid, profile: &SteeringProfile) -> Vec2 {
+    let ahead = agent.velocity * profile.look_ahead;
+    if ahead.length_squared() <= f32::EPSILON {
+        return Vec2::ZERO;
+    }
+    let blocked = |angle: f32, length: f32| {
+        let end = agent.position + Vec2::from_angle(angle).rotate(ahead) * length;
+        !grid.line_of_sight(agent.position, end, profile.radius)
+    };
+    let (front, left, right) = (blocked(0.0, 1.0), blocked(-0.5, 0.7), blocked(0.5, 0.7));
+    // `perp` is the side the positive angle turns towards
+    let side = agent.velocity.perp().normalize_or_zero();
+    let mut force = Vec2::ZERO;
+    if right {
+        force -= side;
+    }
+    if left {
+        force += side;
+    }
+    if front {
+        if force == Vec2::ZERO {
+            force = if right { -side } else { side };
+        }
+        force -= agent.velocity.normalize_or_zero() * 0.5;
+    }
+    force.normalize_or_zero() * profile.max_speed
+}
+
+/// Seeks the first waypoint, or arrives at it if it's the last.
+pub fn follow_path(agent: &Agent, path: &[Vec2], profile: &SteeringProfile) -> Vec2 {
+    match path {
+        [] => -agent.velocity,
+        [last] => arrive(agent, *last, profile.max_speed, profile.slowing_radius),
+        [next, ..] => seek(agent, *next, profile.max_speed),
+    }
+}
+
+/// Drops waypoints within `reach` of `position`, except the last, which
+/// [`follow_path`] arrives at.
+pub fn advance_path(position: Vec2, path: &mut Vec<Vec2>, reach: f32) {
+    let reached = path
+        .iter()
+        .take(path.len().saturating_sub(1))
+        .take_while(|waypoint| waypoint.distance(position) <= reach)
+        .count();
+    path.drain(..reached);
+}
+
+/// The weighted sum of every behaviour, within the profile's force limit.
+/// `neighbours` shouldn't include the agent itself; walls are avoided if a
+/// grid is given.
+///
+/// Behaviours each give a change of velocity, scaled here so that a change of
+/// `max_speed` takes the full `max_force`: agents answer within
+/// `max_speed / max_force` seconds rather than drifting towards their goal.
+pub fn steer(agent: &Agent, profile: &SteeringProfile, target: Target, neighbours: &[Agent], grid: Option<&Grid>) -> Vec2 {
+    let weights = &profile.weights;
+    let towards = match target {
+        Target::None => -agent.velocity,
+        Target::Seek(point) => seek(agent, point, profile.max_speed),
+        Target::Arrive(point) => arrive(agent, point, profile.max_speed, profile.slowing_radius),
+        Target::Path(path) => follow_path(agent, path, profile),
+        Target::Velocity(velocity) => velocity.clamp_length_max(profile.max_speed) - agent.velocity,
+    };
+    let mut force = towards * weights.target;
+    if weights.separation != 0.0 {
+        force += separation(agent, neighbours, profile.separation_radius, profile.max_speed) * weights.separation;
+    }
+    if weights.alignment != 0.0 {
+        force += alignment(agent, neighbours, profile.neighbour_radius) * weights.alignment;
+    }
+    if weights.cohesion != 0.0 {
+        force += cohesion(agent, neighbours, profile.neighbour_radius, profile.max_speed) * weights.cohesion;
+    }
+    if let (Some(grid), true) = (grid, weights.avoidance != 0.0) {
+        force += avoid_obstacles(agent, grid, profile) * weights.avoidance;
+    }
+    let gain = profile.max_force / profile.max_speed.max(f32::EPSILON);
+    (force * gain).clamp_length_max(profile.max_force)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coincident_agents_separate_opposite_ways() {
+        let first = Agent::new(3, vec2(40.0, 40.0));
+        let second = Agent::new(7, vec2(40.0, 40.0));
+        let push = separation(&first, &[second], 24.0, 100.0);
+        assert!(push.length() > 0.0);
+        assert_eq!(separation(&second, &[first], 24.0, 100.0), -push);
+
+        // The same however the neighbours are ordered
+        let third = Agent::new(5, vec2(40.0, 40.0));
+        assert_eq!(separation(&first, &[second, third], 24.0, 100.0), separation(&first, &[third, second], 24.0, 100.0));
+    }
+
+    #[test]
+    fn coincident_agents_steer_opposite_ways() {
+        let profile = SteeringProfile::default();
+        let first = Agent::new(1, vec2(40.0, 40.0));
+        let second = Agent::new(2, vec2(40.0, 40.0));
+        let force = steer(&first, &profile, Target::None, &[second], None);
+        assert!(force.length() > 0.0);
+        assert_eq!(steer(&second, &profile, Target::None, &[first], None), -force);
+    }
+}
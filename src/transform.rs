@@ -1,14 +1,14 @@
 use macroquad::prelude::*;
 
-#[derive(Clone, Copy)]
-struct Transform {
-    position: Vec2,
-    rotation: f32,
-    scale: Vec2,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub position: Vec2,
+    pub rotation: f32,
+    pub scale: Vec2,
 }
 
 impl Transform {
-    fn new(position: Vec2, rotation: f32, scale: Vec2) -> Self {
+    pub fn new(position: Vec2, rotation: f32, scale: Vec2) -> Self {
         Self {
             position,
             rotation,
@@ -16,40 +16,63 @@ impl Transform {
         }
     }
 
-    fn to_matrix(&self) -> Mat3 {
+    pub fn to_matrix(&self) -> Mat3 {
         Mat3::from_scale_angle_translation(self.scale, self.rotation, self.position)
     }
+
+    /// Decomposes an affine matrix. Skew, e.g. from rotating inside a non-uniformly
+    /// scaled parent, can't be represented and is lost.
+    pub fn from_matrix(matrix: Mat3) -> Self {
+        let (scale, rotation, position) = Affine2::from_mat3(matrix).to_scale_angle_translation();
+        Self {
+            position,
+            rotation,
+            scale,
+        }
+    }
 }
 
-struct TransformStack {
+impl Default for Transform {
+    fn default() -> Self {
+        Self::new(Vec2::ZERO, 0.0, Vec2::ONE)
+    }
+}
+
+pub struct TransformStack {
     stack: Vec<Mat3>,
 }
 
+impl Default for TransformStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TransformStack {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             stack: vec![Mat3::IDENTITY],
         }
     }
 
-    fn push(&mut self, transform: Transform) {
+    pub fn push(&mut self, transform: Transform) {
         let current = self.stack.last().unwrap_or(&Mat3::IDENTITY);
         let new = *current * transform.to_matrix();
         self.stack.push(new);
     }
 
-    fn pop(&mut self) {
+    pub fn pop(&mut self) {
         if self.stack.len() > 1 {
             self.stack.pop();
         }
     }
 
-    fn current(&self) -> Mat3 {
+    pub fn current(&self) -> Mat3 {
         *self.stack.last().unwrap_or(&Mat3::IDENTITY)
     }
 }
 
-fn draw_rectangle_transformed(x: f32, y: f32, w: f32, h: f32, color: Color, transform: Mat3) {
+pub fn draw_rectangle_transformed(x: f32, y: f32, w: f32, h: f32, color: Color, transform: Mat3) {
     let points = [
         transform.transform_point2(vec2(x, y)),
         transform.transform_point2(vec2(x + w, y)),
@@ -61,75 +84,173 @@ fn draw_rectangle_transformed(x: f32, y: f32, w: f32, h: f32, color: Color, tran
     draw_triangle(points[0], points[2], points[3], color);
 }
 
-#[macroquad::main("Nested Transform with Shader")]
-async fn main() {
-    let mut transform_stack = TransformStack::new();
-
-    let vert = load_string("assets/shaders/default_vert.glsl")
-        .await
-        .unwrap();
-    let frag = load_string("assets/shaders/default_frag.glsl")
-        .await
-        .unwrap();
-
-    // Create a material with our fragment shader
-    let material = load_material(
-        ShaderSource::Glsl {
-            vertex: vert.as_str(),
-            fragment: frag.as_str(),
-        },
-        MaterialParams {
-            uniforms: vec![
-                UniformDesc::new("iTime", UniformType::Float1),
-                UniformDesc::new("iResolution", UniformType::Float2),
-            ],
-            ..Default::default()
-        },
+/// Extends a 2D affine matrix to the 3D model matrix macroquad draws with.
+pub fn to_model_matrix(matrix: Mat3) -> Mat4 {
+    Mat4::from_cols(
+        vec4(matrix.x_axis.x, matrix.x_axis.y, 0.0, 0.0),
+        vec4(matrix.y_axis.x, matrix.y_axis.y, 0.0, 0.0),
+        Vec4::Z,
+        vec4(matrix.z_axis.x, matrix.z_axis.y, 0.0, 1.0),
     )
-    .unwrap();
+}
 
-    loop {
-        clear_background(WHITE);
+/// Runs `draw` with `matrix` applied to macroquad's model matrix, so ordinary draw
+/// calls such as `draw_text` are transformed too. Each change of model matrix
+/// starts a new draw call, so prefer the `_transformed` functions for many
+/// small shapes.
+pub fn with_transform<R>(matrix: Mat3, draw: impl FnOnce() -> R) -> R {
+    let gl = unsafe { get_internal_gl() }.quad_gl;
+    gl.push_model_matrix(to_model_matrix(matrix));
+    let result = draw();
+    let gl = unsafe { get_internal_gl() }.quad_gl;
+    gl.pop_model_matrix();
+    result
+}
 
-        let time = get_time() as f32;
-        gl_use_material(&material);
-        material.set_uniform("iTime", time);
-        material.set_uniform("iResolution", vec2(screen_width(), screen_height()));
+pub fn draw_triangle_transformed(v1: Vec2, v2: Vec2, v3: Vec2, color: Color, transform: Mat3) {
+    draw_triangle(
+        transform.transform_point2(v1),
+        transform.transform_point2(v2),
+        transform.transform_point2(v3),
+        color,
+    );
+}
 
-        // Root transform
-        let root_transform = Transform::new(
-            vec2(screen_width() * 0.5, screen_height() * 0.5),
-            time * 0.5,
-            Vec2::ONE,
-        );
+/// Fills a convex polygon.
+pub fn draw_polygon_transformed(points: &[Vec2], color: Color, transform: Mat3) {
+    if points.len() < 3 {
+        return;
+    }
+    let vertices = points
+        .iter()
+        .map(|point| {
+            let point = transform.transform_point2(*point);
+            Vertex::new(point.x, point.y, 0.0, 0.0, 0.0, color)
+        })
+        .collect();
+    let indices = (1..points.len() as u16 - 1).flat_map(|i| [0, i, i + 1]).collect();
+    draw_mesh(&Mesh {
+        vertices,
+        indices,
+        texture: None,
+    });
+}
 
-        transform_stack.push(root_transform);
+/// Draws a line whose thickness is in local units, so it scales with the
+/// transform like the rest of the shape.
+pub fn draw_line_transformed(x1: f32, y1: f32, x2: f32, y2: f32, thickness: f32, color: Color, transform: Mat3) {
+    let start = vec2(x1, y1);
+    let end = vec2(x2, y2);
+    let Some(direction) = (end - start).try_normalize() else {
+        return;
+    };
+    let offset = direction.perp() * thickness / 2.0;
+    draw_polygon_transformed(&[start + offset, end + offset, end - offset, start - offset], color, transform);
+}
 
-        // Draw the root rectangle
-        draw_rectangle_transformed(-50.0, -50.0, 100.0, 100.0, RED, transform_stack.current());
+pub fn draw_rectangle_lines_transformed(x: f32, y: f32, w: f32, h: f32, thickness: f32, color: Color, transform: Mat3) {
+    // Edges are inset by half the thickness so corners meet without overlapping
+    let t = thickness / 2.0;
+    draw_line_transformed(x, y + t, x + w, y + t, thickness, color, transform);
+    draw_line_transformed(x, y + h - t, x + w, y + h - t, thickness, color, transform);
+    draw_line_transformed(x + t, y + thickness, x + t, y + h - thickness, thickness, color, transform);
+    draw_line_transformed(x + w - t, y + thickness, x + w - t, y + h - thickness, thickness, color, transform);
+}
 
-        // Child transform
-        let child_transform = Transform::new(vec2(100.0, 0.0), time * 2.0, Vec2::ONE * 0.5);
+/// Enough segments for a circle to look round at its largest on-screen radius.
+fn circle_segments(r: f32, transform: Mat3) -> usize {
+    let screen_radius = r.max(0.0) * transform.x_axis.truncate().length().max(transform.y_axis.truncate().length());
+    (screen_radius.sqrt() * 4.0).clamp(8.0, 128.0) as usize
+}
 
-        transform_stack.push(child_transform);
+fn circle_points(x: f32, y: f32, r: f32, transform: Mat3) -> Vec<Vec2> {
+    circle_points_n(x, y, r, circle_segments(r, transform))
+}
 
-        // Draw the child rectangle with shader
-        let child_matrix = transform_stack.current();
-        draw_rectangle_transformed(-50.0, -50.0, 100.0, 100.0, WHITE, child_matrix);
+/// `segments` points evenly around a circle, the first at angle 0.
+fn circle_points_n(x: f32, y: f32, r: f32, segments: usize) -> Vec<Vec2> {
+    (0..segments)
+        .map(|i| {
+            let angle = i as f32 / segments as f32 * std::f32::consts::TAU;
+            vec2(x, y) + Vec2::from_angle(angle) * r
+        })
+        .collect()
+}
 
-        // Grandchild transform
-        let grandchild_transform = Transform::new(vec2(100.0, 0.0), time * -3.0, Vec2::ONE * 0.5);
+/// Draws a circle, which becomes a rotated ellipse under non-uniform scale.
+pub fn draw_circle_transformed(x: f32, y: f32, r: f32, color: Color, transform: Mat3) {
+    draw_polygon_transformed(&circle_points(x, y, r, transform), color, transform);
+}
 
-        transform_stack.push(grandchild_transform);
+/// Draws a ring of `thickness` centred on the circle. Both edges share one
+/// segment count so each inner point sits at its outer partner's angle; a ring
+/// thicker than the circle's diameter is filled to the centre.
+pub fn draw_circle_lines_transformed(x: f32, y: f32, r: f32, thickness: f32, color: Color, transform: Mat3) {
+    let outer_radius = r + thickness / 2.0;
+    let segments = circle_segments(outer_radius, transform);
+    let outer = circle_points_n(x, y, outer_radius, segments);
+    let inner = circle_points_n(x, y, (r - thickness / 2.0).max(0.0), segments);
+    let vertices = outer
+        .iter()
+        .chain(&inner)
+        .map(|point| {
+            let point = transform.transform_point2(*point);
+            Vertex::new(point.x, point.y, 0.0, 0.0, 0.0, color)
+        })
+        .collect();
+    let segments = outer.len() as u16;
+    let indices = (0..segments)
+        .flat_map(|i| {
+            let next = (i + 1) % segments;
+            [i, next, segments + i, next, segments + next, segments + i]
+        })
+        .collect();
+    draw_mesh(&Mesh {
+        vertices,
+        indices,
+        texture: None,
+    });
+}
 
-        // Draw the grandchild rectangle
-        draw_rectangle_transformed(-50.0, -50.0, 100.0, 100.0, GREEN, transform_stack.current());
+/// Draws a texture with its top-left corner at (`x`, `y`) in local space. Honours
+/// `dest_size`, `source`, `flip_x` and `flip_y`; rotate through the transform
+/// instead of `rotation` and `pivot`.
+pub fn draw_texture_transformed(
+    texture: &Texture2D,
+    x: f32,
+    y: f32,
+    color: Color,
+    params: &DrawTextureParams,
+    transform: Mat3,
+) {
+    let texture_size = vec2(texture.width(), texture.height());
+    let source = params
+        .source
+        .unwrap_or(Rect::new(0.0, 0.0, texture_size.x, texture_size.y));
+    let size = params.dest_size.unwrap_or(source.size());
+    let (mut uv_min, mut uv_max) = (source.point() / texture_size, (source.point() + source.size()) / texture_size);
+    if params.flip_x {
+        std::mem::swap(&mut uv_min.x, &mut uv_max.x);
+    }
+    if params.flip_y {
+        std::mem::swap(&mut uv_min.y, &mut uv_max.y);
+    }
 
-        // Pop all transforms
-        transform_stack.pop();
-        transform_stack.pop();
-        transform_stack.pop();
+    let vertices = [vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0)]
+        .map(|corner| {
+            let position = transform.transform_point2(vec2(x, y) + corner * size);
+            let uv = uv_min + corner * (uv_max - uv_min);
+            Vertex::new(position.x, position.y, 0.0, uv.x, uv.y, color)
+        })
+        .to_vec();
+    draw_mesh(&Mesh {
+        vertices,
+        indices: vec![0, 1, 2, 0, 2, 3],
+        texture: Some(texture.clone()),
+    });
+}
 
-        next_frame().await
-    }
+/// Draws text with its baseline starting at (`x`, `y`) in local space.
+pub fn draw_text_transformed(text: &str, x: f32, y: f32, font_size: f32, color: Color, transform: Mat3) -> TextDimensions {
+    with_transform(transform, || draw_text(text, x, y, font_size, color))
 }
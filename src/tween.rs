@@ -0,0 +1,644 @@
+use crate::transform::Transform;
+use macroquad::prelude::*;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashMap};
+use std::f32::consts::PI;
+
+/// Easing curves, mapping linear progress in 0..=1 to eased progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ease {
+    #[default]
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+    ExpoIn,
+    ExpoOut,
+    ExpoInOut,
+    /// Overshoots backwards before moving.
+    BackIn,
+    /// Overshoots the target and settles back.
+    BackOut,
+    BackInOut,
+    ElasticOut,
+    BounceOut,
+}
+
+impl Ease {
+    pub fn apply(self, t: f32) -> f32 {
+        const BACK: f32 = 1.70158;
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Ease::Linear => t,
+            Ease::QuadIn => t * t,
+            Ease::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Ease::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Ease::CubicIn => t * t * t,
+            Ease::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Ease::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Ease::SineIn => 1.0 - (t * PI / 2.0).cos(),
+            Ease::SineOut => (t * PI / 2.0).sin(),
+            Ease::SineInOut => -((PI * t).cos() - 1.0) / 2.0,
+            Ease::ExpoIn => {
+                if t == 0.0 {
+                    0.0
+                } else {
+                    2f32.powf(10.0 * t - 10.0)
+                }
+            }
+            Ease::ExpoOut => {
+                if t == 1.0 {
+                    1.0
+                } else {
+                    1.0 - 2f32.powf(-10.0 * t)
+                }
+            }
+            Ease::ExpoInOut => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                } else if t < 0.5 {
+                    2f32.powf(20.0 * t - 10.0) / 2.0
+                } else {
+                    (2.0 - 2f32.powf(-20.0 * t + 10.0)) / 2.0
+                }
+            }
+            Ease::BackIn => (BACK + 1.0) * t * t * t - BACK * t * t,
+            Ease::BackOut => 1.0 + (BACK + 1.0) * (t - 1.0).powi(3) + BACK * (t - 1.0).powi(2),
+            Ease::BackInOut => {
+                let back = BACK * 1.525;
+                if t < 0.5 {
+                    (2.0 * t).powi(2) * ((back + 1.0) * 2.0 * t - back) / 2.0
+                } else {
+                    ((2.0 * t - 2.0).powi(2) * ((back + 1.0) * (t * 2.0 - 2.0) + back) + 2.0) / 2.0
+                }
+            }
+            Ease::ElasticOut => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                } else {
+                    2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+                }
+            }
+            Ease::BounceOut => {
+                const N: f32 = 7.5625;
+                const D: f32 = 2.75;
+                if t < 1.0 / D {
+                    N * t * t
+                } else if t < 2.0 / D {
+                    let t = t - 1.5 / D;
+                    N * t * t + 0.75
+                } else if t < 2.5 / D {
+                    let t = t - 2.25 / D;
+                    N * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / D;
+                    N * t * t + 0.984375
+                }
+            }
+        }
+    }
+}
+
+/// A value a tween can animate. In JSON a number, `[x, y]` or `[r, g, b, a]`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(from = "ValueData")]
+pub enum Value {
+    Float(f32),
+    Vec2(Vec2),
+    Color(Color),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ValueData {
+    Float(f32),
+    Vec2([f32; 2]),
+    Color([f32; 4]),
+}
+
+impl From<ValueData> for Value {
+    fn from(data: ValueData) -> Self {
+        match data {
+            ValueData::Float(value) => Value::Float(value),
+            ValueData::Vec2([x, y]) => Value::Vec2(vec2(x, y)),
+            ValueData::Color([r, g, b, a]) => Value::Color(Color::new(r, g, b, a)),
+        }
+    }
+}
+
+impl Value {
+    /// Interpolates towards `to`; mismatched kinds jump to `to` at the end.
+    pub fn lerp(self, to: Value, t: f32) -> Value {
+        match (self, to) {
+            (Value::Float(a), Value::Float(b)) => Value::Float(a + (b - a) * t),
+            (Value::Vec2(a), Value::Vec2(b)) => Value::Vec2(a.lerp(b, t)),
+            (Value::Color(a), Value::Color(b)) => Value::Color(Color::from_vec(a.to_vec().lerp(b.to_vec(), t))),
+            _ if t < 1.0 => self,
+            _ => to,
+        }
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<Vec2> for Value {
+    fn from(value: Vec2) -> Self {
+        Value::Vec2(value)
+    }
+}
+
+impl From<Color> for Value {
+    fn from(value: Color) -> Self {
+        Value::Color(value)
+    }
+}
+
+/// A tree of tweens and timing. Motions are sampled at a time rather than
+/// stepped, so they can be replayed, scrubbed or repeated exactly.
+///
+/// Properties are plain names such as `"player.rotation"`; [`Tweens`] stores the
+/// latest value of each for the game to read.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Motion {
+    Tween {
+        property: String,
+        from: Value,
+        to: Value,
+        duration: f32,
+        #[serde(default)]
+        ease: Ease,
+    },
+    Delay {
+        duration: f32,
+    },
+    /// Children one after another.
+    Sequence {
+        children: Vec<Motion>,
+    },
+    /// Children at the same time; lasts as long as the longest.
+    Parallel {
+        children: Vec<Motion>,
+    },
+    /// Plays `motion` `count` times, or forever without a count; a count of 0 is
+    /// rejected. With `yoyo` every other repetition plays backwards.
+    Repeat {
+        motion: Box<Motion>,
+        #[serde(default, deserialize_with = "repeat_count")]
+        count: Option<u32>,
+        #[serde(default)]
+        yoyo: bool,
+    },
+    /// Reported by [`Tweens::update`] when playback passes it.
+    Event {
+        name: String,
+    },
+}
+
+impl Motion {
+    pub fn tween(property: &str, from: impl Into<Value>, to: impl Into<Value>, duration: f32, ease: Ease) -> Self {
+        Motion::Tween {
+            property: property.to_string(),
+            from: from.into(),
+            to: to.into(),
+            duration,
+            ease,
+        }
+    }
+
+    pub fn delay(duration: f32) -> Self {
+        Motion::Delay { duration }
+    }
+
+    pub fn sequence(children: Vec<Motion>) -> Self {
+        Motion::Sequence { children }
+    }
+
+    pub fn parallel(children: Vec<Motion>) -> Self {
+        Motion::Parallel { children }
+    }
+
+    pub fn event(name: &str) -> Self {
+        Motion::Event { name: name.to_string() }
+    }
+
+    /// Repeats `count` times, or forever with `None`. Panics on a count of 0,
+    /// which JSON rejects too.
+    pub fn repeat(self, count: Option<u32>) -> Self {
+        assert_ne!(count, Some(0), "repeat count must be at least 1");
+        Motion::Repeat {
+            motion: Box::new(self),
+            count,
+            yoyo: false,
+        }
+    }
+
+    /// Plays forwards then backwards, `count` passes in total or forever with
+    /// `None`. Panics on a count of 0, which JSON rejects too.
+    pub fn yoyo(self, count: Option<u32>) -> Self {
+        assert_ne!(count, Some(0), "repeat count must be at least 1");
+        Motion::Repeat {
+            motion: Box::new(self),
+            count,
+            yoyo: true,
+        }
+    }
+
+    /// Infinite for endless repeats.
+    pub fn duration(&self) -> f32 {
+        match self {
+            Motion::Tween { duration, .. } | Motion::Delay { duration } => duration.max(0.0),
+            Motion::Sequence { children } => children.iter().map(Motion::duration).sum(),
+            Motion::Parallel { children } => children.iter().map(Motion::duration).fold(0.0, f32::max),
+            Motion::Repeat { motion, count, .. } => match count {
+                Some(count) => motion.duration() * *count as f32,
+                None if motion.duration() > 0.0 => f32::INFINITY,
+                None => 0.0,
+            },
+            Motion::Event { .. } => 0.0,
+        }
+    }
+
+    /// Writes every property as it is at `time`. Parts not started yet leave
+    /// their properties alone.
+    pub fn sample(&self, time: f32, values: &mut HashMap<String, Value>) {
+        match self {
+            Motion::Tween {
+                property,
+                from,
+                to,
+                duration,
+                ease,
+            } => {
+                if time < 0.0 {
+                    return;
+                }
+                let progress = if *duration > 0.0 { time / duration } else { 1.0 };
+                values.insert(property.clone(), from.lerp(*to, ease.apply(progress)));
+            }
+            Motion::Sequence { children } => {
+                let mut start = 0.0;
+                for child in children {
+                    if time < start {
+                        break;
+                    }
+                    child.sample(time - start, values);
+                    start += child.duration();
+                }
+            }
+            Motion::Parallel { children } => {
+                for child in children {
+                    child.sample(time, values);
+                }
+            }
+            Motion::Repeat { motion, count, yoyo } => {
+                let (cycle, local) = repeat_cycle(motion.duration(), *count, time);
+                let local = if *yoyo && cycle % 2 == 1 {
+                    motion.duration() - local
+                } else {
+                    local
+                };
+                motion.sample(local, values);
+            }
+            Motion::Delay { .. } | Motion::Event { .. } => {}
+        }
+    }
+
+    /// Whether any tween in the motion writes `property`.
+    pub fn animates(&self, property: &str) -> bool {
+        match self {
+            Motion::Tween { property: animated, .. } => animated == property,
+            Motion::Sequence { children } | Motion::Parallel { children } => {
+                children.iter().any(|child| child.animates(property))
+            }
+            Motion::Repeat { motion, .. } => motion.animates(property),
+            Motion::Delay { .. } | Motion::Event { .. } => false,
+        }
+    }
+
+    /// Collects events in the interval (`from`, `to`]. Events inside backwards yoyo
+    /// passes aren't reported.
+    fn events(&self, from: f32, to: f32, events: &mut Vec<String>) {
+        match self {
+            Motion::Event { name } => {
+                if from < 0.0 && to >= 0.0 {
+                    events.push(name.clone());
+                }
+            }
+            Motion::Sequence { children } => {
+                let mut start = 0.0;
+                for child in children {
+                    child.events(from - start, to - start, events);
+                    start += child.duration();
+                }
+            }
+            Motion::Parallel { children } => {
+                for child in children {
+                    child.events(from, to, events);
+                }
+            }
+            Motion::Repeat { motion, count, yoyo } => {
+                // Cap the repetitions scanned in one step in case of a huge jump
+                const MAX_CYCLES: i64 = 64;
+                let duration = motion.duration();
+                if duration <= 0.0 {
+                    motion.events(from, to, events);
+                    return;
+                }
+                let (first, first_local) = repeat_cycle(duration, *count, from);
+                let (last, last_local) = repeat_cycle(duration, *count, to);
+                for cycle in first.max(last - MAX_CYCLES)..=last {
+                    if *yoyo && cycle % 2 == 1 {
+                        continue;
+                    }
+                    let start = if cycle == first { first_local } else { f32::NEG_INFINITY };
+                    let end = if cycle == last { last_local } else { duration };
+                    motion.events(start, end, events);
+                }
+            }
+            Motion::Tween { .. } | Motion::Delay { .. } => {}
+        }
+    }
+}
+
+/// Rejects a repeat count of 0, which leaves no repetition to sample.
+fn repeat_count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    match Option::<u32>::deserialize(deserializer)? {
+        Some(0) => Err(D::Error::custom("repeat count must be at least 1")),
+        count => Ok(count),
+    }
+}
+
+/// Splits `time` into a repetition index and the time within it. Times before
+/// the start stay in the first repetition; times after the last end in it.
+fn repeat_cycle(duration: f32, count: Option<u32>, time: f32) -> (i64, f32) {
+    if duration <= 0.0 || time < 0.0 {
+        return (0, time);
+    }
+    if let Some(count) = count {
+        if time >= duration * count as f32 {
+            return (count as i64 - 1, duration);
+        }
+    }
+    let cycle = (time / duration).floor();
+    (cycle as i64, time - cycle * duration)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Keyframe {
+    pub time: f32,
+    pub value: Value,
+    /// Easing from the previous keyframe to this one.
+    #[serde(default)]
+    pub ease: Ease,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TimelineEvent {
+    pub time: f32,
+    pub name: String,
+}
+
+/// Keyframed tracks, one per property, as loaded from JSON.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Timeline {
+    pub tracks: BTreeMap<String, Vec<Keyframe>>,
+    #[serde(default)]
+    pub events: Vec<TimelineEvent>,
+    /// Omit to play once.
+    #[serde(default)]
+    pub repeat: Option<TimelineRepeat>,
+}
+
+/// How a [`Timeline`] repeats, with the same fields as [`Motion::Repeat`]: `{}`
+/// loops forever.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+pub struct TimelineRepeat {
+    /// Omit to loop forever; 0 is rejected.
+    #[serde(default, deserialize_with = "repeat_count")]
+    pub count: Option<u32>,
+    #[serde(default)]
+    pub yoyo: bool,
+}
+
+impl Timeline {
+    pub async fn load(json_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let json_data = load_string(json_path).await?;
+        Ok(serde_json::from_str(&json_data)?)
+    }
+
+    /// Converts the keyframes to a parallel group of tween sequences.
+    pub fn to_motion(&self) -> Motion {
+        let mut children = Vec::new();
+        for (property, keyframes) in &self.tracks {
+            let mut keyframes = keyframes.clone();
+            keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+            let Some(first) = keyframes.first() else {
+                continue;
+            };
+            // Hold the first value until its keyframe, then tween between neighbours
+            let mut sequence = vec![Motion::tween(property, first.value, first.value, first.time, Ease::Linear)];
+            for pair in keyframes.windows(2) {
+                sequence.push(Motion::tween(
+                    property,
+                    pair[0].value,
+                    pair[1].value,
+                    pair[1].time - pair[0].time,
+                    pair[1].ease,
+                ));
+            }
+            children.push(Motion::sequence(sequence));
+        }
+        for event in &self.events {
+            children.push(Motion::sequence(vec![Motion::delay(event.time), Motion::event(&event.name)]));
+        }
+
+        let motion = Motion::parallel(children);
+        match self.repeat {
+            None => motion,
+            Some(repeat) => Motion::Repeat {
+                motion: Box::new(motion),
+                count: repeat.count,
+                yoyo: repeat.yoyo,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TweenId(u64);
+
+impl TweenId {
+    /// A plain number for scripts and save data.
+    pub fn to_bits(self) -> u64 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        TweenId(bits)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TweenEvent {
+    /// A [`Motion::Event`] was passed.
+    Event { tween: TweenId, name: String },
+    /// The motion played to its end and was removed.
+    Completed { tween: TweenId },
+}
+
+struct Playing {
+    id: TweenId,
+    motion: Motion,
+    time: f32,
+    started: bool,
+    on_complete: Option<Box<dyn FnOnce()>>,
+}
+
+/// Plays motions and holds the current value of every animated property.
+/// Values stay at their last state after a motion ends.
+#[derive(Default)]
+pub struct Tweens {
+    playing: Vec<Playing>,
+    values: HashMap<String, Value>,
+    next_id: u64,
+}
+
+impl Tweens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn play(&mut self, motion: Motion) -> TweenId {
+        let id = TweenId(self.next_id);
+        self.next_id += 1;
+        self.playing.push(Playing {
+            id,
+            motion,
+            time: 0.0,
+            started: false,
+            on_complete: None,
+        });
+        id
+    }
+
+    /// Calls `callback` once the motion finishes, but not if it is stopped.
+    pub fn on_complete(&mut self, id: TweenId, callback: impl FnOnce() + 'static) {
+        if let Some(playing) = self.playing.iter_mut().find(|playing| playing.id == id) {
+            playing.on_complete = Some(Box::new(callback));
+        }
+    }
+
+    /// Stops a motion, leaving its properties where they are.
+    pub fn stop(&mut self, id: TweenId) {
+        self.playing.retain(|playing| playing.id != id);
+    }
+
+    /// Stops every motion animating `property`.
+    pub fn stop_property(&mut self, property: &str) {
+        self.playing.retain(|playing| !playing.motion.animates(property));
+    }
+
+    pub fn is_playing(&self, id: TweenId) -> bool {
+        self.playing.iter().any(|playing| playing.id == id)
+    }
+
+    /// Advances every motion and returns the events passed and motions completed,
+    /// in order.
+    pub fn update(&mut self, dt: f32) -> Vec<TweenEvent> {
+        let mut events = Vec::new();
+        let mut index = 0;
+        while index < self.playing.len() {
+            let playing = &mut self.playing[index];
+            let from = if playing.started { playing.time } else { f32::NEG_INFINITY };
+            playing.started = true;
+            playing.time += dt;
+
+            playing.motion.sample(playing.time, &mut self.values);
+            let mut names = Vec::new();
+            playing.motion.events(from, playing.time, &mut names);
+            let tween = playing.id;
+            events.extend(names.into_iter().map(|name| TweenEvent::Event { tween, name }));
+
+            if playing.time >= playing.motion.duration() {
+                let playing = self.playing.remove(index);
+                if let Some(on_complete) = playing.on_complete {
+                    on_complete();
+                }
+                events.push(TweenEvent::Completed { tween });
+            } else {
+                index += 1;
+            }
+        }
+        events
+    }
+
+    pub fn value(&self, property: &str) -> Option<Value> {
+        self.values.get(property).copied()
+    }
+
+    pub fn set_value(&mut self, property: &str, value: impl Into<Value>) {
+        self.values.insert(property.to_string(), value.into());
+    }
+
+    pub fn float(&self, property: &str) -> Option<f32> {
+        match self.value(property)? {
+            Value::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn vec2(&self, property: &str) -> Option<Vec2> {
+        match self.value(property)? {
+            Value::Vec2(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn color(&self, property: &str) -> Option<Color> {
+        match self.value(property)? {
+            Value::Color(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Copies `<name>.position`, `<name>.rotation` and `<name>.scale` onto
+    /// `transform`, for those that have values. A float scale scales uniformly.
+    pub fn apply_transform(&self, name: &str, transform: &mut Transform) {
+        if let Some(position) = self.vec2(&format!("{}.position", name)) {
+            transform.position = position;
+        }
+        if let Some(rotation) = self.float(&format!("{}.rotation", name)) {
+            transform.rotation = rotation;
+        }
+        match self.value(&format!("{}.scale", name)) {
+            Some(Value::Vec2(scale)) => transform.scale = scale,
+            Some(Value::Float(scale)) => transform.scale = Vec2::splat(scale),
+            _ => {}
+        }
+    }
+}